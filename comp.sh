@@ -1,8 +1,7 @@
 move: compile
-	mv ./receiver/target/release/receiver ./transport-starter-code-main/4700recv
-	mv ./sender/target/release/sender ./transport-starter-code-main/4700send
+	mv ./target/release/receiver ./transport-starter-code-main/4700recv
+	mv ./target/release/sender ./transport-starter-code-main/4700send
+	mv ./target/release/impair ./transport-starter-code-main/impair
 
 compile: 
-	cd receiver && ~/.cargo/bin/cargo build --release
-    cd ..
-	cd sender && ~/.cargo/bin/cargo build --release
\ No newline at end of file
+	~/.cargo/bin/cargo build --release
\ No newline at end of file
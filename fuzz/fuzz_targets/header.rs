@@ -0,0 +1,34 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+// Both crates carry their own copy of the header code
+macro_rules! check {
+    ($krate:ident, $data:expr) => {{
+        use $krate::util::tcp_header::{TcpHeader, HEADER_SIZE};
+        use $krate::util::util::{decode_sack, encode_sack, segment_len};
+
+        let data: &[u8] = $data;
+        match TcpHeader::new(data) {
+            Ok(header) => {
+                // Every byte of the header is a field, so it serializes back as it came
+                assert_eq!(header.as_bytes(), &data[..HEADER_SIZE]);
+
+                let payload = &data[HEADER_SIZE..];
+                header.calculate_header_data_hash(payload);
+                segment_len(header.frame_flags, payload);
+
+                // Trailing bytes that don't make a whole block are ignored
+                let blocks = decode_sack(payload);
+                assert_eq!(encode_sack(&blocks), &payload[..blocks.len() * 8]);
+                assert_eq!(blocks.len(), payload.len() / 8);
+            }
+            Err(_) => assert!(data.len() < HEADER_SIZE),
+        }
+    }};
+}
+
+fuzz_target!(|data: &[u8]| {
+    check!(sender, data);
+    check!(receiver, data);
+});
@@ -0,0 +1,164 @@
+#![no_main]
+
+use libfuzzer_sys::arbitrary::{self, Arbitrary};
+use libfuzzer_sys::fuzz_target;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use fuzz::{Wire, PEER};
+use receiver::util::crypto::{session_key, Protection, COOKIE_SIZE};
+use receiver::util::seq::SeqNum;
+use receiver::util::tcp_header::{TcpHeader, HEADER_SIZE};
+use receiver::Receiver;
+use sim::SimClock;
+
+const CONNECTION_ID: u64 = 0x5eed;
+const DATASIZE: usize = 1500 - HEADER_SIZE;
+// What the receiver may hold, its limit plus one segment
+const MAX_BUFFERED: usize = (4 << 20) + 1500;
+
+#[derive(Arbitrary, Debug)]
+enum Action {
+    // Data of stream 0: the piece of the sent stream at the given offset, maybe from another port
+    Segment {
+        offset: u16,
+        len: u16,
+        port: Option<u16>,
+    },
+    // Anything on another stream, under the connection's key
+    Forged {
+        seq: u32, // Relative to the first data sequence number
+        flags: u8,
+        frame_flags: u8,
+        stream_id: u16,
+        stream_seq: u32,
+        payload: Vec<u8>,
+    },
+    // Bytes as they come, they hardly ever pass the hash check
+    Raw(Vec<u8>),
+    // Let time pass, in milliseconds
+    Wait(u16),
+}
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    init_seq: u32,
+    stream: Vec<u8>,
+    actions: Vec<Action>,
+}
+
+fn packet(
+    protection: &Protection,
+    seq: SeqNum,
+    flags: u8,
+    frame_flags: u8,
+    stream_id: u16,
+    stream_seq: SeqNum,
+    payload: &[u8],
+) -> Vec<u8> {
+    let mut header = TcpHeader {
+        source_port: 1000,
+        destination_port: 2000,
+        sequence_number: seq,
+        ack_number: SeqNum(0),
+        header_length: 4,
+        frame_flags,
+        flags,
+        window_size: 65340,
+        connection_id: CONNECTION_ID,
+        stream_id,
+        stream_seq,
+        hash_value: [0; 32],
+    };
+    protection.seal(&mut header, payload)
+}
+
+fuzz_target!(|input: Input| {
+    let wire = Wire::default();
+    let clock = Arc::new(SimClock::new());
+    let mut receiver = Receiver::with_transport(Box::new(wire.clone()), clock.clone(), 0).unwrap();
+    let peer: SocketAddr = PEER.parse().unwrap();
+
+    // Handshake, the ACK echoes the cookie at the start of the SYN-ACK's payload and data starts right after it
+    let init_seq = SeqNum(input.init_seq);
+    let first = init_seq + 2;
+    let unkeyed = Protection::default();
+    wire.push(packet(&unkeyed, init_seq, 0b0000_0010, 0, 0, SeqNum(0), &[]), peer);
+    receiver.poll().unwrap();
+    let syn_ack = wire.take_sent().pop().unwrap();
+    let ack = packet(&unkeyed, init_seq + 1, 0b0001_0000, 0, 0, SeqNum(0), &[]);
+    let mut ack = TcpHeader::new(&ack).unwrap();
+    ack.ack_number = TcpHeader::new(&syn_ack).unwrap().sequence_number + 1;
+    let cookie = &syn_ack[HEADER_SIZE..HEADER_SIZE + COOKIE_SIZE];
+    wire.push(unkeyed.seal(&mut ack, cookie), peer);
+    // Everything after the handshake is under a key from the cookie
+    let protection = Protection {
+        session: Some(session_key(cookie, CONNECTION_ID)),
+        ..Protection::default()
+    };
+
+    let stream = &input.stream[..input.stream.len().min(u16::MAX as usize)];
+    let mut delivered = Vec::new();
+
+    for action in input.actions.iter().map(Some).chain([None]) {
+        match action {
+            Some(Action::Segment { offset, len, port }) => {
+                let start = *offset as usize;
+                let end = (start + (*len as usize).min(DATASIZE)).min(stream.len());
+                // An empty segment would take a sequence number without carrying a byte of the stream
+                if start < end {
+                    let from = SocketAddr::new(peer.ip(), port.unwrap_or(peer.port()));
+                    let seq = first + *offset as u32;
+                    let data = &stream[start..end];
+                    let stream_seq = SeqNum(*offset as u32);
+                    let segment = packet(&protection, seq, 0b0001_1000, 0, 0, stream_seq, data);
+                    wire.push(segment, from);
+                }
+            }
+            Some(Action::Forged {
+                seq,
+                flags,
+                frame_flags,
+                stream_id,
+                stream_seq,
+                payload,
+            }) => {
+                let payload = &payload[..payload.len().min(DATASIZE)];
+                wire.push(
+                    packet(
+                        &protection,
+                        first + *seq,
+                        *flags,
+                        frame_flags & 0b1111,
+                        (*stream_id).max(1),
+                        SeqNum(*stream_seq),
+                        payload,
+                    ),
+                    peer,
+                );
+            }
+            Some(Action::Raw(bytes)) => wire.push(bytes.clone(), peer),
+            Some(Action::Wait(ms)) => {
+                clock.advance_to(clock.elapsed() + Duration::from_millis(*ms as u64))
+            }
+            None => {}
+        }
+
+        while wire.pending() > 0 {
+            receiver.poll().unwrap();
+        }
+
+        delivered.extend(receiver.read(0));
+        for stream_id in receiver.readable() {
+            receiver.read(stream_id);
+            while receiver.recv_message(stream_id).is_some() {}
+        }
+
+        assert!(stream.starts_with(&delivered), "delivered bytes that were not sent");
+        assert!(receiver.buffered() <= MAX_BUFFERED, "{} bytes buffered", receiver.buffered());
+        for datagram in wire.take_sent() {
+            assert!(TcpHeader::new(&datagram).is_ok() && datagram.len() <= 1500);
+        }
+    }
+});
@@ -0,0 +1,174 @@
+#![no_main]
+
+use libfuzzer_sys::arbitrary::{self, Arbitrary};
+use libfuzzer_sys::fuzz_target;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use fuzz::{Wire, PEER};
+use sender::util::crypto::{session_key, Protection};
+use sender::util::seq::SeqNum;
+use sender::util::tcp_header::{TcpHeader, FORWARD, HEADER_SIZE, PATH_CHALLENGE, UNORDERED};
+use sender::util::util::encode_sack;
+use sender::{Reliability, Sender};
+use sim::SimClock;
+
+#[derive(Arbitrary, Debug)]
+enum Kind {
+    Reliable,
+    Lifetime(u16),
+    MaxRetransmits(u8),
+    Unordered,
+}
+
+#[derive(Arbitrary, Debug)]
+enum Action {
+    // Answer to the SYN with any cookie, valid unless the ack number is off
+    SynAck { seq: u32, window: u16, ack_off: u8, cookie: [u8; 16] },
+    // Cumulative ACK and SACK blocks, relative to the SYN's sequence number
+    Ack {
+        ack: u16,
+        window: u16,
+        messages: bool, // Credit for the message stream instead of stream 0
+        sack: Vec<(u16, u16)>,
+    },
+    // Path validation with any token
+    Challenge(Vec<u8>),
+    // Bytes as they come, they hardly ever pass the hash check
+    Raw(Vec<u8>),
+    // Let time pass, in milliseconds
+    Wait(u16),
+}
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    seed: u64,
+    init_seq: u32,
+    stream: Vec<u8>,
+    messages: Vec<(Kind, Vec<u8>)>,
+    actions: Vec<Action>,
+}
+
+
+fn header(
+    connection_id: u64,
+    seq: SeqNum,
+    ack: SeqNum,
+    flags: u8,
+    window: u16,
+    stream_id: u16,
+) -> TcpHeader {
+    TcpHeader {
+        source_port: 1000,
+        destination_port: 2000,
+        sequence_number: seq,
+        ack_number: ack,
+        header_length: 4,
+        frame_flags: 0,
+        flags,
+        window_size: window,
+        connection_id,
+        stream_id,
+        stream_seq: SeqNum(0),
+        hash_value: [0; 32],
+    }
+}
+
+fuzz_target!(|input: Input| {
+    let wire = Wire::default();
+    let clock = Arc::new(SimClock::new());
+    let mut sender = Sender::with_transport(
+        "127.0.0.1".to_string(),
+        2000,
+        Box::new(wire.clone()),
+        clock.clone(),
+        input.seed,
+        65340,
+        4,
+    )
+    .unwrap();
+    sender.set_initial_seq(input.init_seq).unwrap();
+    let peer: SocketAddr = PEER.parse().unwrap();
+
+    // Stream 0 carries the byte stream, stream 1 the messages
+    sender.send(0, &input.stream).unwrap();
+    let messages = sender.open_stream();
+    for (kind, msg) in input.messages.iter().take(64) {
+        let _ = match kind {
+            Kind::Reliable => sender.send_message_with(messages, msg, Reliability::Reliable),
+            Kind::Lifetime(ms) => sender.send_message_with(
+                messages,
+                msg,
+                Reliability::Lifetime(Duration::from_millis(*ms as u64)),
+            ),
+            Kind::MaxRetransmits(max) => {
+                sender.send_message_with(messages, msg, Reliability::MaxRetransmits(*max as u32))
+            }
+            Kind::Unordered => sender.send_unordered(messages, msg),
+        };
+    }
+
+    // The SYN tells us the connection ID
+    sender.poll().unwrap();
+    let syn = TcpHeader::new(&wire.take_sent()[0]).unwrap();
+    let (connection_id, isn) = (syn.connection_id, syn.sequence_number);
+    // After the handshake, packets are under a key from the cookie of the SYN-ACK that completed it
+    let mut protection = Protection::default();
+
+    for action in input.actions.iter().map(Some).chain([None]) {
+        match action {
+            Some(Action::SynAck { seq, window, ack_off, cookie }) => {
+                let ack = isn + 1 + *ack_off as u32;
+                let mut header = header(connection_id, SeqNum(*seq), ack, 0b0001_0010, *window, 0);
+                wire.push(protection.seal(&mut header, cookie), peer);
+                if *ack_off == 0 && protection.session.is_none() {
+                    protection.session = Some(session_key(cookie, connection_id));
+                }
+            }
+            Some(Action::Ack {
+                ack,
+                window,
+                messages: to_messages,
+                sack,
+            }) => {
+                let blocks: Vec<_> = sack
+                    .iter()
+                    .take(4)
+                    .map(|(start, end)| (isn + *start as u32, isn + *end as u32))
+                    .collect();
+                let stream_id = if *to_messages { messages } else { 0 };
+                let ack = isn + *ack as u32;
+                let mut header = header(connection_id, SeqNum(0), ack, 0b0001_0000, *window, stream_id);
+                wire.push(protection.seal(&mut header, &encode_sack(&blocks)), peer);
+            }
+            Some(Action::Challenge(token)) => {
+                let mut header = header(connection_id, SeqNum(0), SeqNum(0), PATH_CHALLENGE, 0, 0);
+                wire.push(protection.seal(&mut header, &token[..token.len().min(64)]), peer);
+            }
+            Some(Action::Raw(bytes)) => wire.push(bytes.clone(), peer),
+            Some(Action::Wait(ms)) => {
+                clock.advance_to(clock.elapsed() + Duration::from_millis(*ms as u64))
+            }
+            None => {}
+        }
+
+        // Once finished the sender stops reading, so poll a bounded number of times
+        for _ in 0..=wire.pending() {
+            sender.poll().unwrap();
+        }
+        sender.next_timeout();
+
+        // Whatever the receiver says, the sender only ever sends what it was given
+        for datagram in wire.take_sent() {
+            assert!(datagram.len() <= 1500, "{} byte datagram", datagram.len());
+            let header = TcpHeader::new(&datagram).unwrap();
+            let payload = &datagram[HEADER_SIZE..];
+            if header.flags == 0b0001_1000 && header.stream_id == 0 {
+                assert_eq!(header.frame_flags & (FORWARD | UNORDERED), 0);
+                let start = header.stream_seq.0 as usize;
+                assert_eq!(payload, &input.stream[start..start + payload.len()]);
+            }
+        }
+    }
+});
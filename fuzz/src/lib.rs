@@ -0,0 +1,82 @@
+use std::collections::VecDeque;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+// Address of the end under test
+pub const LOCAL: &str = "127.0.0.1:2000";
+// Address the fuzzer talks from
+pub const PEER: &str = "127.0.0.1:1000";
+
+// Datagrams in memory: the fuzzer pushes what the end under test receives and takes what it sent
+#[derive(Debug, Clone, Default)]
+pub struct Wire {
+    inner: Arc<Mutex<WireInner>>,
+}
+
+#[derive(Debug, Default)]
+struct WireInner {
+    inbox: VecDeque<(Vec<u8>, SocketAddr)>,
+    sent: Vec<Vec<u8>>,
+}
+
+impl Wire {
+    // Queue a datagram for the end under test
+    pub fn push(&self, datagram: Vec<u8>, from: SocketAddr) {
+        self.inner.lock().unwrap().inbox.push_back((datagram, from));
+    }
+
+    // Datagrams the end under test has yet to read
+    pub fn pending(&self) -> usize {
+        self.inner.lock().unwrap().inbox.len()
+    }
+
+    // Take everything the end under test sent
+    pub fn take_sent(&self) -> Vec<Vec<u8>> {
+        std::mem::take(&mut self.inner.lock().unwrap().sent)
+    }
+
+    fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.lock().unwrap().sent.push(buf.to_vec());
+        Ok(buf.len())
+    }
+
+    fn recv(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        match self.inner.lock().unwrap().inbox.pop_front() {
+            Some((data, from)) => {
+                let len = data.len().min(buf.len());
+                buf[..len].copy_from_slice(&data[..len]);
+                Ok((len, from))
+            }
+            None => Err(io::ErrorKind::WouldBlock.into()),
+        }
+    }
+}
+
+impl sender::Transport for Wire {
+    fn send_to(&self, buf: &[u8], _addr: &str) -> io::Result<usize> {
+        self.send(buf)
+    }
+
+    fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        self.recv(buf)
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(LOCAL.parse().unwrap())
+    }
+}
+
+impl receiver::Transport for Wire {
+    fn send_to(&self, buf: &[u8], _addr: &str) -> io::Result<usize> {
+        self.send(buf)
+    }
+
+    fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        self.recv(buf)
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(LOCAL.parse().unwrap())
+    }
+}
@@ -0,0 +1,38 @@
+use serde::Deserialize;
+use std::fs;
+
+// Test configuration, same JSON files as the Python simulator
+#[derive(Clone, Debug, Deserialize)]
+pub struct Config {
+    pub seed: Option<u64>,     // Seed of the impairments, random if missing
+    pub lifetime: Option<f64>, // Seconds the sender has to finish
+    pub data: Option<usize>,   // Bytes the sender has to transfer
+    pub network: Network,
+}
+
+// Network conditions applied to both directions
+#[derive(Clone, Debug, Deserialize)]
+pub struct Network {
+    pub delay: f64,             // One-way delay in seconds
+    pub bandwidth: f64,         // Bytes per second
+    pub buffer: usize,          // Bytes the router queue holds before dropping
+    pub drop: Option<f64>,      // Probability of dropping a packet
+    pub duplicate: Option<f64>, // Probability of delivering a packet twice
+    pub mangle: Option<f64>,    // Probability of overwriting 5 bytes of a packet
+    pub jitter: Option<f64>,    // Maximum delay added or removed, in seconds
+}
+
+impl Config {
+    // Load a config file
+    pub fn from_file(path: &str) -> Result<Self, String> {
+        let data = fs::read_to_string(path)
+            .map_err(|e| format!("{e} -> Unable to read data from config file '{path}'"))?;
+        Self::parse(&data)
+            .map_err(|e| format!("{e} -> Unable to parse JSON in config file '{path}'"))
+    }
+
+    // Parse the JSON of a config
+    pub fn parse(data: &str) -> Result<Self, String> {
+        serde_json::from_str(data).map_err(|e| e.to_string())
+    }
+}
@@ -0,0 +1,142 @@
+use rand::prelude::*;
+use rand::rngs::StdRng;
+
+use crate::config::Network;
+use crate::path::Path;
+
+// Largest packet the link carries
+const MAX_PACKET: usize = 1500;
+
+// Direction a packet travels in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    SenderToReceiver,
+    ReceiverToSender,
+}
+
+// Counters of one direction
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub packets: u64,    // Packets sent into the link
+    pub bytes: u64,      // Bytes sent into the link
+    pub dropped: u64,    // Packets dropped at random or for being too big
+    pub duplicated: u64, // Packets delivered twice
+    pub mangled: u64,    // Packets with bytes overwritten
+    pub overflowed: u64, // Packets dropped because the router buffer was full
+}
+
+// Network emulator without any IO: packets go in with the current time and come out once they're due.
+// All randomness comes from one seeded generator, so a run can be reproduced.
+#[derive(Debug)]
+pub struct Emulator {
+    network: Network,
+    rng: StdRng,
+    s_to_r: Path,
+    r_to_s: Path,
+    s_stats: Stats,
+    r_stats: Stats,
+}
+
+impl Emulator {
+    pub fn new(network: Network, seed: Option<u64>, now: f64) -> Self {
+        let rng = match seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        Emulator {
+            s_to_r: Path::new(&network, now),
+            r_to_s: Path::new(&network, now),
+            network,
+            rng,
+            s_stats: Stats::default(),
+            r_stats: Stats::default(),
+        }
+    }
+
+    // Apply the impairments to a packet sent in the given direction
+    pub fn packet_received(&mut self, direction: Direction, data: &[u8], now: f64) {
+        let stats = match direction {
+            Direction::SenderToReceiver => &mut self.s_stats,
+            Direction::ReceiverToSender => &mut self.r_stats,
+        };
+        stats.packets += 1;
+        stats.bytes += data.len() as u64;
+
+        if data.len() > MAX_PACKET {
+            stats.dropped += 1;
+            return;
+        }
+
+        // Draw in the same order as the Python simulator: drop, mangle, duplicate, jitter
+        if fraction(&mut self.rng, self.network.drop) {
+            stats.dropped += 1;
+            return;
+        }
+
+        let mut data = data.to_vec();
+        if fraction(&mut self.rng, self.network.mangle) && !data.is_empty() {
+            stats.mangled += 1;
+            for _ in 0..5 {
+                let ind = self.rng.gen_range(0..data.len());
+                data[ind] = 0x58;
+            }
+        }
+
+        let duplicate = fraction(&mut self.rng, self.network.duplicate);
+        if duplicate {
+            stats.duplicated += 1;
+        }
+
+        let path = match direction {
+            Direction::SenderToReceiver => &mut self.s_to_r,
+            Direction::ReceiverToSender => &mut self.r_to_s,
+        };
+        if duplicate {
+            path.enqueue(data.clone(), jitter(&mut self.rng, self.network.jitter), now);
+        }
+        path.enqueue(data, jitter(&mut self.rng, self.network.jitter), now);
+    }
+
+    // Packets that arrive at the end of the given direction by now
+    pub fn ready_to_deliver(&mut self, direction: Direction, now: f64) -> Vec<Vec<u8>> {
+        self.path_mut(direction).ready_to_deliver(now)
+    }
+
+    // Seconds until a packet may be due in either direction
+    pub fn sleep_time(&self, now: f64) -> f64 {
+        self.s_to_r.sleep_time(now).min(self.r_to_s.sleep_time(now))
+    }
+
+    // Counters of the given direction
+    pub fn stats(&self, direction: Direction) -> Stats {
+        let (mut stats, path) = match direction {
+            Direction::SenderToReceiver => (self.s_stats, &self.s_to_r),
+            Direction::ReceiverToSender => (self.r_stats, &self.r_to_s),
+        };
+        stats.overflowed = path.overflowed();
+        stats
+    }
+
+    fn path_mut(&mut self, direction: Direction) -> &mut Path {
+        match direction {
+            Direction::SenderToReceiver => &mut self.s_to_r,
+            Direction::ReceiverToSender => &mut self.r_to_s,
+        }
+    }
+}
+
+// Whether an event with the given probability happens, nothing is drawn if it's not configured
+fn fraction(rng: &mut StdRng, probability: Option<f64>) -> bool {
+    match probability {
+        Some(probability) => rng.gen::<f64>() < probability,
+        None => false,
+    }
+}
+
+// Delay added to a packet, uniform in [-jitter, jitter]
+fn jitter(rng: &mut StdRng, jitter: Option<f64>) -> f64 {
+    match jitter {
+        Some(jitter) if jitter > 0.0 => rng.gen_range(-jitter..=jitter),
+        _ => 0.0,
+    }
+}
@@ -0,0 +1,9 @@
+pub mod config;
+pub mod emulator;
+pub mod path;
+pub mod proxy;
+
+pub use config::{Config, Network};
+pub use emulator::{Direction, Emulator, Stats};
+pub use path::Path;
+pub use proxy::Impair;
@@ -0,0 +1,185 @@
+use clap::Parser;
+use rand::prelude::*;
+use rand::rngs::StdRng;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::SocketAddr;
+use std::process::{Child, ChildStdout, Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+use impair::{Config, Direction, Impair};
+
+// Runs the sender and the receiver through the emulated network, like the Python `run` script
+#[derive(Parser, Debug)]
+struct Cli {
+    config: String,
+    // Seed of the impairments and the data, overrides the config
+    #[arg(long)]
+    seed: Option<u64>,
+    #[arg(long, default_value = "./4700send")]
+    sender: String,
+    #[arg(long, default_value = "./4700recv")]
+    receiver: String,
+}
+
+fn main() {
+    let cli = Cli::parse();
+    let start = Instant::now();
+
+    if let Err(e) = run(&cli, start) {
+        println!("\nError: {}", e);
+    }
+}
+
+fn run(cli: &Cli, start: Instant) -> Result<(), String> {
+    let config = Config::from_file(&cli.config)?;
+    let seed = cli.seed.or(config.seed);
+    let data = generate_data(config.data.unwrap_or(0), seed);
+
+    log(start, "Simulator", "Beginning simulation");
+    let mut receiver = spawn(&cli.receiver, &[])?;
+    let (port_tx, port_rx) = mpsc::channel();
+    forward_stderr(&mut receiver, "4700recv", start, Some(port_tx));
+    let output = collect_stdout(receiver.stdout.take().unwrap());
+
+    let port = wait_for_port(&port_rx, &mut receiver)?;
+    let addr: SocketAddr = format!("127.0.0.1:{port}").parse().unwrap();
+    let mut impair = Impair::new("127.0.0.1", addr, config.network.clone(), seed)?;
+
+    let proxy_port = impair.local_port()?.to_string();
+    let mut sender = spawn(&cli.sender, &["127.0.0.1", &proxy_port])?;
+    forward_stderr(&mut sender, "4700send", start, None);
+    let mut stdin = sender.stdin.take().unwrap();
+    let sent = data.clone();
+    thread::spawn(move || {
+        if stdin.write_all(&sent).is_err() {
+            eprintln!("Pipe to 4700send broken");
+        }
+    });
+
+    let result = loop {
+        if config
+            .lifetime
+            .is_some_and(|lifetime| start.elapsed().as_secs_f64() > lifetime)
+        {
+            break Err("Simulation time exceeded, and 4700send did not exit".to_string());
+        }
+        if let Ok(Some(_)) = receiver.try_wait() {
+            break Err("4700recv crashed; exiting".to_string());
+        }
+        if let Ok(Some(_)) = sender.try_wait() {
+            break Ok(());
+        }
+        impair.poll()?;
+    };
+
+    let _ = sender.kill();
+    let _ = receiver.kill();
+    result?;
+
+    // The receiver is gone, so its stdout is complete
+    let received = output.join().unwrap();
+    if received == data {
+        println!("\nSuccess!  Data was transmitted correctly.");
+    } else {
+        println!("\nError -- data was not transmitted correctly.");
+        println!("Sent {} bytes, received {} bytes", data.len(), received.len());
+    }
+
+    let s = impair.stats(Direction::SenderToReceiver);
+    let r = impair.stats(Direction::ReceiverToSender);
+    println!(
+        "\nStats: {:.4} total time, {} bytes/{} packets sent ({}/{} sender -> receiver, {}/{} receiver -> sender)",
+        start.elapsed().as_secs_f64(),
+        s.bytes + r.bytes,
+        s.packets + r.packets,
+        s.bytes,
+        s.packets,
+        r.bytes,
+        r.packets
+    );
+    for (name, stats) in [("S->R", s), ("R->S", r)] {
+        println!(
+            "{}: {} dropped, {} duplicated, {} mangled, {} dropped by the full buffer",
+            name, stats.dropped, stats.duplicated, stats.mangled, stats.overflowed
+        );
+    }
+    Ok(())
+}
+
+// Print a line the way the Python simulator does
+fn log(start: Instant, caller: &str, msg: &str) {
+    println!("[{:07.4}  {:>12}]: {}", start.elapsed().as_secs_f64(), caller, msg);
+}
+
+// Blocks of text to send, reproducible for a given seed
+fn generate_data(length: usize, seed: Option<u64>) -> Vec<u8> {
+    let mut rng = match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    let mut data = Vec::new();
+    let mut i = 0;
+    while data.len() < length {
+        data.extend_from_slice(format!("----- Block {:07} -----", i).as_bytes());
+        for _ in 0..675 {
+            data.extend_from_slice(format!("{:02x}", rng.gen::<u8>()).as_bytes());
+        }
+        i += 1;
+    }
+    data
+}
+
+fn spawn(executable: &str, args: &[&str]) -> Result<Child, String> {
+    Command::new(executable)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("{e} -> Could not execute program '{executable}'"))
+}
+
+// Log the stderr of a program, and report the port it binds to
+fn forward_stderr(
+    child: &mut Child,
+    name: &'static str,
+    start: Instant,
+    port: Option<mpsc::Sender<u16>>,
+) {
+    let stderr = child.stderr.take().unwrap();
+    let indent = if name == "4700recv" { " ".repeat(50) } else { String::new() };
+    thread::spawn(move || {
+        for line in BufReader::new(stderr).lines().map_while(Result::ok) {
+            log(start, name, &format!("{}{}", indent, line));
+            if let (Some(tx), Some(rest)) = (&port, line.strip_prefix("Bound to port ")) {
+                let digits: String = rest.chars().take_while(|c| c.is_ascii_digit()).collect();
+                if let Ok(port) = digits.parse() {
+                    let _ = tx.send(port);
+                }
+            }
+        }
+    });
+}
+
+// Read everything the receiver delivers
+fn collect_stdout(mut stdout: ChildStdout) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut received = Vec::new();
+        let _ = stdout.read_to_end(&mut received);
+        received
+    })
+}
+
+// Wait until the receiver printed its port
+fn wait_for_port(port: &Receiver<u16>, receiver: &mut Child) -> Result<u16, String> {
+    loop {
+        if let Ok(port) = port.recv_timeout(std::time::Duration::from_millis(100)) {
+            return Ok(port);
+        }
+        if let Ok(Some(_)) = receiver.try_wait() {
+            return Err("4700recv crashed; exiting".to_string());
+        }
+    }
+}
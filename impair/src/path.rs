@@ -0,0 +1,106 @@
+use std::collections::VecDeque;
+
+use crate::config::Network;
+
+// Sleep when nothing is scheduled, in seconds
+pub const DEFAULT_SLEEP: f64 = 1.0;
+
+// Packet waiting in a queue or a buffer
+#[derive(Clone, Debug)]
+struct EnqueuedPacket {
+    data: Vec<u8>,
+    ts: f64, // Time when the packet leaves the queue
+}
+
+// One direction of the link: packets wait out their delay in the queue,
+// then go through the router buffer at the link bandwidth.
+// Times are seconds since the emulator started, so the caller owns the clock.
+#[derive(Debug)]
+pub struct Path {
+    delay: f64,
+    bandwidth: f64,
+    buffer_size: usize,
+    queue: Vec<EnqueuedPacket>,     // Packets still being delayed, in arrival order
+    buffer: VecDeque<Vec<u8>>,      // Packets waiting for the link
+    busy_until: f64,                // Time when the packet on the link is done
+    packet_sending: Option<Vec<u8>>, // Packet on the link
+    overflowed: u64,                // Packets dropped because the buffer was full
+}
+
+impl Path {
+    pub fn new(network: &Network, now: f64) -> Self {
+        Path {
+            delay: network.delay,
+            bandwidth: network.bandwidth,
+            buffer_size: network.buffer,
+            queue: Vec::new(),
+            buffer: VecDeque::new(),
+            busy_until: now,
+            packet_sending: None,
+            overflowed: 0,
+        }
+    }
+
+    // Start delaying a packet, jitter is added to the configured delay
+    pub fn enqueue(&mut self, data: Vec<u8>, jitter: f64, now: f64) {
+        self.queue.push(EnqueuedPacket {
+            data,
+            ts: now + self.delay + jitter,
+        });
+    }
+
+    // Move the packets that waited out their delay to the buffer, and take the packet done on the link
+    pub fn ready_to_deliver(&mut self, now: f64) -> Vec<Vec<u8>> {
+        let (dequeued, waiting) = self.queue.drain(..).partition(|ep| ep.ts <= now);
+        self.queue = waiting;
+        for ep in dequeued {
+            self.buffer_enqueue(ep.data);
+        }
+
+        let mut result = Vec::new();
+        if self.busy_until > now {
+            return result;
+        }
+
+        if let Some(data) = self.packet_sending.take() {
+            result.push(data);
+        }
+
+        if let Some(data) = self.buffer.pop_front() {
+            self.busy_until = now + data.len() as f64 / self.bandwidth;
+            self.packet_sending = Some(data);
+        }
+
+        result
+    }
+
+    // Seconds until something can happen on this path
+    pub fn sleep_time(&self, now: f64) -> f64 {
+        let queue = self
+            .queue
+            .iter()
+            .map(|ep| ep.ts)
+            .min_by(f64::total_cmp)
+            .map_or(DEFAULT_SLEEP, |ts| (ts - now).max(0.0));
+        let buffer = match self.packet_sending {
+            Some(_) => (self.busy_until - now).max(0.0),
+            None => DEFAULT_SLEEP,
+        };
+        queue.min(buffer)
+    }
+
+    // Packets dropped because the router buffer was full
+    pub fn overflowed(&self) -> u64 {
+        self.overflowed
+    }
+
+    // Drop packets beyond what the router buffer holds
+    fn buffer_enqueue(&mut self, data: Vec<u8>) {
+        let size: usize = self.buffer.iter().map(|data| data.len()).sum();
+        if size + data.len() > self.buffer_size {
+            self.overflowed += 1;
+            return;
+        }
+        self.buffer.push_back(data);
+    }
+}
@@ -0,0 +1,95 @@
+use std::net::{SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+use crate::config::Network;
+use crate::emulator::{Direction, Emulator, Stats};
+
+// UDP proxy between a sender and a receiver, running the emulator on the wall clock.
+// The sender talks to the proxy's port, everything not coming from the receiver is the sender.
+#[derive(Debug)]
+pub struct Impair {
+    socket: UdpSocket,
+    receiver: SocketAddr,
+    sender: Option<SocketAddr>, // Learned from the first packet of the sender
+    emulator: Emulator,
+    start: Instant,
+}
+
+impl Impair {
+    // Bind the proxy on the given host, forwarding to the receiver
+    pub fn new(
+        local_host: &str,
+        receiver: SocketAddr,
+        network: Network,
+        seed: Option<u64>,
+    ) -> Result<Self, String> {
+        let socket = UdpSocket::bind(format!("{}:{}", local_host, 0))
+            .map_err(|e| format!("{} -> Failed to bind to {}:{}", e, local_host, 0))?;
+
+        Ok(Impair {
+            socket,
+            receiver,
+            sender: None,
+            emulator: Emulator::new(network, seed, 0.0),
+            start: Instant::now(),
+        })
+    }
+
+    // Port the sender has to send to
+    pub fn local_port(&self) -> Result<u16, String> {
+        self.socket
+            .local_addr()
+            .map(|addr| addr.port())
+            .map_err(|e| format!("{e} -> Failed to get local port"))
+    }
+
+    // Seconds since the proxy started
+    pub fn now(&self) -> f64 {
+        self.start.elapsed().as_secs_f64()
+    }
+
+    // Counters of the given direction
+    pub fn stats(&self, direction: Direction) -> Stats {
+        self.emulator.stats(direction)
+    }
+
+    // Wait for a packet until the next one is due, then forward everything that is due
+    pub fn poll(&mut self) -> Result<(), String> {
+        let sleep = self.emulator.sleep_time(self.now());
+        // A zero timeout means blocking forever
+        let timeout = Duration::from_secs_f64(sleep).max(Duration::from_micros(100));
+        self.socket
+            .set_read_timeout(Some(timeout))
+            .map_err(|e| format!("{e} -> Failed to set read timeout"))?;
+
+        let mut buf = vec![0; 65535];
+        if let Ok((len, addr)) = self.socket.recv_from(&mut buf) {
+            let direction = if addr == self.receiver {
+                Direction::ReceiverToSender
+            } else {
+                self.sender = Some(addr);
+                Direction::SenderToReceiver
+            };
+            let now = self.now();
+            self.emulator.packet_received(direction, &buf[..len], now);
+        }
+
+        let now = self.now();
+        for data in self.emulator.ready_to_deliver(Direction::ReceiverToSender, now) {
+            if let Some(sender) = self.sender {
+                self.send_data(&data, sender);
+            }
+        }
+        for data in self.emulator.ready_to_deliver(Direction::SenderToReceiver, now) {
+            self.send_data(&data, self.receiver);
+        }
+        Ok(())
+    }
+
+    // Helper function to forward a packet
+    fn send_data(&self, data: &[u8], addr: SocketAddr) {
+        if let Err(e) = self.socket.send_to(data, addr) {
+            eprintln!("{} -> Failed to forward packet to {}", e, addr);
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use rand::prelude::*;
+use rand::rngs::StdRng;
+
+use impair::{Direction, Emulator, Network, Stats};
+
+const SEED: u64 = 7;
+
+fn network() -> Network {
+    Network {
+        delay: 0.1,
+        bandwidth: 1e9,
+        buffer: 1 << 20,
+        drop: Some(0.2),
+        duplicate: Some(0.2),
+        mangle: Some(0.2),
+        jitter: Some(0.05),
+    }
+}
+
+// One packet a second, each one different
+fn packets(count: usize, len: usize) -> Vec<(f64, Vec<u8>)> {
+    (0..count).map(|i| (i as f64, vec![i as u8; len])).collect()
+}
+
+// Feed the packets in on a millisecond clock, and note when each one comes out
+fn run(mut emulator: Emulator, packets: &[(f64, Vec<u8>)]) -> (Vec<(f64, Vec<u8>)>, Stats) {
+    let end = packets.last().map_or(0, |(ts, _)| *ts as u64 * 1000 + 1000);
+    let mut packets = packets.iter().peekable();
+    let mut delivered = Vec::new();
+    for ms in 0..=end {
+        let now = ms as f64 / 1000.0;
+        while let Some((_, data)) = packets.next_if(|(ts, _)| *ts <= now) {
+            emulator.packet_received(Direction::SenderToReceiver, data, now);
+        }
+        for data in emulator.ready_to_deliver(Direction::SenderToReceiver, now) {
+            delivered.push((now, data));
+        }
+    }
+    (delivered, emulator.stats(Direction::SenderToReceiver))
+}
+
+#[test]
+fn draws_are_taken_in_the_order_of_the_python_simulator() {
+    let packets = packets(200, 100);
+    let (delivered, stats) = run(Emulator::new(network(), Some(SEED), 0.0), &packets);
+
+    // Drop, then mangle with the indexes it overwrites, then duplicate, then the jitter of each copy
+    let mut rng = StdRng::seed_from_u64(SEED);
+    let mut expected = Vec::new();
+    let mut expected_stats = Stats::default();
+    for (now, data) in &packets {
+        expected_stats.packets += 1;
+        expected_stats.bytes += data.len() as u64;
+        if rng.gen::<f64>() < 0.2 {
+            expected_stats.dropped += 1;
+            continue;
+        }
+        let mut data = data.clone();
+        if rng.gen::<f64>() < 0.2 {
+            expected_stats.mangled += 1;
+            for _ in 0..5 {
+                let ind = rng.gen_range(0..data.len());
+                data[ind] = 0x58;
+            }
+        }
+        if rng.gen::<f64>() < 0.2 {
+            expected_stats.duplicated += 1;
+            expected.push((now + 0.1 + rng.gen_range(-0.05..=0.05), data.clone()));
+        }
+        expected.push((now + 0.1 + rng.gen_range(-0.05..=0.05), data));
+    }
+    expected.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    assert_eq!(stats, expected_stats);
+    assert!(stats.dropped > 0 && stats.mangled > 0 && stats.duplicated > 0);
+    assert_eq!(delivered.len(), expected.len());
+    for ((at, data), (ts, expected)) in delivered.iter().zip(&expected) {
+        assert_eq!(data, expected);
+        // Out on the first tick after it's due, a copy due on the same tick waits for the link
+        assert!(*at >= *ts && *at <= ts + 0.003, "{at} {ts}");
+    }
+}
+
+#[test]
+fn packets_over_1500_bytes_are_dropped_without_a_draw() {
+    let network = Network {
+        drop: Some(0.5),
+        duplicate: None,
+        mangle: None,
+        jitter: None,
+        ..network()
+    };
+    let packets = packets(50, 1500);
+    let mut with_big = vec![(0.0, vec![1; 1501])];
+    with_big.extend(packets.iter().map(|(ts, data)| (ts + 1.0, data.clone())));
+
+    let (delivered, stats) = run(Emulator::new(network.clone(), Some(SEED), 0.0), &packets);
+    let (delivered_with_big, stats_with_big) = run(Emulator::new(network, Some(SEED), 0.0), &with_big);
+
+    // The big packet doesn't change the fate of the ones after it
+    let data = |delivered: Vec<(f64, Vec<u8>)>| delivered.into_iter().map(|(_, data)| data).collect::<Vec<_>>();
+    assert!(!delivered.is_empty());
+    assert_eq!(data(delivered_with_big), data(delivered));
+    assert_eq!(stats_with_big.packets, stats.packets + 1);
+    assert_eq!(stats_with_big.bytes, stats.bytes + 1501);
+    assert_eq!(stats_with_big.dropped, stats.dropped + 1);
+}
+
+#[test]
+fn the_same_seed_gives_every_packet_the_same_fate() {
+    let packets = packets(200, 100);
+    let first = run(Emulator::new(network(), Some(SEED), 0.0), &packets);
+    let again = run(Emulator::new(network(), Some(SEED), 0.0), &packets);
+    let other = run(Emulator::new(network(), Some(SEED + 1), 0.0), &packets);
+
+    assert_eq!(first, again);
+    assert_ne!(first, other);
+}
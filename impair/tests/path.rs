@@ -0,0 +1,72 @@
+use impair::path::DEFAULT_SLEEP;
+use impair::{Network, Path};
+
+fn network(bandwidth: f64, buffer: usize) -> Network {
+    Network {
+        delay: 0.0,
+        bandwidth,
+        buffer,
+        drop: None,
+        duplicate: None,
+        mangle: None,
+        jitter: None,
+    }
+}
+
+#[test]
+fn packets_beyond_the_buffer_are_dropped() {
+    let mut path = Path::new(&network(1000.0, 3000), 0.0);
+    for i in 0..4 {
+        path.enqueue(vec![i; 1000], 0.0, 0.0);
+    }
+
+    // The fourth packet doesn't fit next to the three waiting for the link
+    assert!(path.ready_to_deliver(0.0).is_empty());
+    assert_eq!(path.overflowed(), 1);
+    let delivered: Vec<_> = (1..=4).flat_map(|s| path.ready_to_deliver(s as f64)).collect();
+    assert_eq!(delivered, vec![vec![0; 1000], vec![1; 1000], vec![2; 1000]]);
+
+    // Once the link drained the buffer there is room again
+    path.enqueue(vec![3; 3000], 0.0, 4.0);
+    path.ready_to_deliver(4.0);
+    assert_eq!(path.overflowed(), 1);
+}
+
+#[test]
+fn packets_go_out_at_the_link_bandwidth() {
+    let mut path = Path::new(&network(1000.0, 1 << 20), 0.0);
+    assert_eq!(path.sleep_time(0.0), DEFAULT_SLEEP);
+    for i in 0..3 {
+        path.enqueue(vec![i; 500], 0.0, 0.0);
+    }
+
+    // Each 500 byte packet holds the link for half a second
+    assert!(path.ready_to_deliver(0.0).is_empty());
+    assert_eq!(path.sleep_time(0.0), 0.5);
+    assert!(path.ready_to_deliver(0.49).is_empty());
+    assert_eq!(path.ready_to_deliver(0.5), vec![vec![0; 500]]);
+    assert!(path.ready_to_deliver(0.99).is_empty());
+    assert_eq!(path.ready_to_deliver(1.0), vec![vec![1; 500]]);
+    assert_eq!(path.ready_to_deliver(1.5), vec![vec![2; 500]]);
+    assert!(path.ready_to_deliver(2.0).is_empty());
+    assert_eq!(path.sleep_time(2.0), DEFAULT_SLEEP);
+}
+
+#[test]
+fn packets_wait_out_their_delay_and_jitter() {
+    let network = Network {
+        delay: 0.1,
+        ..network(1e9, 1 << 20)
+    };
+    let mut path = Path::new(&network, 0.0);
+    path.enqueue(vec![1], 0.05, 0.0);
+    path.enqueue(vec![2], -0.05, 0.0);
+
+    assert!((path.sleep_time(0.0) - 0.05).abs() < 1e-9);
+    assert!(path.ready_to_deliver(0.04).is_empty());
+    path.ready_to_deliver(0.05);
+    assert_eq!(path.ready_to_deliver(0.06), vec![vec![2]]);
+    assert!(path.ready_to_deliver(0.14).is_empty());
+    path.ready_to_deliver(0.16);
+    assert_eq!(path.ready_to_deliver(0.17), vec![vec![1]]);
+}
@@ -0,0 +1,88 @@
+use std::net::UdpSocket;
+
+use impair::{Direction, Emulator, Impair, Network};
+
+const SEED: u64 = 3;
+
+fn network(drop: Option<f64>) -> Network {
+    Network {
+        delay: 0.0,
+        bandwidth: 1e9,
+        buffer: 1 << 20,
+        drop,
+        duplicate: None,
+        mangle: None,
+        jitter: None,
+    }
+}
+
+fn bind() -> UdpSocket {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket.set_nonblocking(true).unwrap();
+    socket
+}
+
+// Poll the proxy until it read a packet in the given direction, then until the other end has it unless it was dropped
+fn forward(impair: &mut Impair, direction: Direction, to: &UdpSocket) -> Option<Vec<u8>> {
+    let stats = impair.stats(direction);
+    while impair.stats(direction).packets == stats.packets {
+        impair.poll().unwrap();
+    }
+    if impair.stats(direction).dropped > stats.dropped {
+        return None;
+    }
+    let mut buf = [0; 1500];
+    loop {
+        if let Ok(len) = to.recv(&mut buf) {
+            return Some(buf[..len].to_vec());
+        }
+        impair.poll().unwrap();
+    }
+}
+
+#[test]
+fn packets_are_forwarded_both_ways_and_counted() {
+    let receiver = bind();
+    let mut impair = Impair::new("127.0.0.1", receiver.local_addr().unwrap(), network(None), Some(SEED)).unwrap();
+    let proxy = format!("127.0.0.1:{}", impair.local_port().unwrap());
+    let sender = bind();
+
+    sender.send_to(&[1; 100], &proxy).unwrap();
+    assert_eq!(forward(&mut impair, Direction::SenderToReceiver, &receiver), Some(vec![1; 100]));
+
+    // The receiver answers the proxy, which knows where the sender is
+    receiver.send_to(&[2; 40], &proxy).unwrap();
+    assert_eq!(forward(&mut impair, Direction::ReceiverToSender, &sender), Some(vec![2; 40]));
+
+    let s_stats = impair.stats(Direction::SenderToReceiver);
+    let r_stats = impair.stats(Direction::ReceiverToSender);
+    assert_eq!((s_stats.packets, s_stats.bytes), (1, 100));
+    assert_eq!((r_stats.packets, r_stats.bytes), (1, 40));
+}
+
+#[test]
+fn the_proxy_gives_packets_the_fate_its_seed_draws() {
+    let receiver = bind();
+    let mut impair = Impair::new("127.0.0.1", receiver.local_addr().unwrap(), network(Some(0.5)), Some(SEED)).unwrap();
+    let proxy = format!("127.0.0.1:{}", impair.local_port().unwrap());
+    let sender = bind();
+
+    let mut arrived = Vec::new();
+    for i in 0..40 {
+        sender.send_to(&[i; 10], &proxy).unwrap();
+        arrived.push(forward(&mut impair, Direction::SenderToReceiver, &receiver).is_some());
+    }
+
+    // The same seed without any sockets drops the same packets
+    let mut emulator = Emulator::new(network(Some(0.5)), Some(SEED), 0.0);
+    let expected: Vec<_> = (0..40)
+        .map(|i| {
+            let now = i as f64;
+            emulator.packet_received(Direction::SenderToReceiver, &[i; 10], now);
+            emulator.ready_to_deliver(Direction::SenderToReceiver, now);
+            !emulator.ready_to_deliver(Direction::SenderToReceiver, now + 0.5).is_empty()
+        })
+        .collect();
+    assert!(arrived.contains(&true) && arrived.contains(&false));
+    assert_eq!(arrived, expected);
+}
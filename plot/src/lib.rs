@@ -0,0 +1,246 @@
+use serde_json::Value;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+// Size of the drawing, every chart gets the full width and a share of the height
+const WIDTH: f64 = 960.0;
+const CHART_HEIGHT: f64 = 320.0;
+const MARGIN: (f64, f64, f64, f64) = (40.0, 30.0, 50.0, 80.0); // Top, right, bottom, left
+const LEGEND_HEIGHT: f64 = 30.0;
+
+// One color per trace, cycled if there are more traces
+const COLORS: [&str; 8] = [
+    "#1f77b4", "#d62728", "#2ca02c", "#ff7f0e", "#9467bd", "#8c564b", "#e377c2", "#17becf",
+];
+
+// Congestion window and RTT over time, read from the event log of one sender
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Trace {
+    pub name: String,
+    pub cwnd: Vec<(f64, f64)>,         // (ms, bytes)
+    pub smoothed_rtt: Vec<(f64, f64)>, // (ms, ms)
+    pub latest_rtt: Vec<(f64, f64)>,   // (ms, ms)
+}
+
+impl Trace {
+    // Load an event log, the trace is named after the file
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let data = fs::read_to_string(path)
+            .map_err(|e| format!("{e} -> Unable to read event log '{}'", path.display()))?;
+        let name = path.file_stem().map_or(String::new(), |s| s.to_string_lossy().to_string());
+        Self::parse(&name, &data)
+            .map_err(|e| format!("{e} -> Unable to parse event log '{}'", path.display()))
+    }
+
+    // Pick the metrics updates out of the JSON lines of an event log
+    pub fn parse(name: &str, data: &str) -> Result<Self, String> {
+        let mut trace = Trace {
+            name: name.to_string(),
+            ..Default::default()
+        };
+
+        for (ind, line) in data.lines().enumerate().filter(|(_, l)| !l.trim().is_empty()) {
+            let event: Value =
+                serde_json::from_str(line).map_err(|e| format!("{e} at line {}", ind + 1))?;
+            if event["name"] != "recovery:metrics_updated" {
+                continue;
+            }
+            let time = event["time"]
+                .as_f64()
+                .ok_or_else(|| format!("Event without a time at line {}", ind + 1))?;
+            let data = &event["data"];
+            if let Some(cwnd) = data["congestion_window"].as_f64() {
+                trace.cwnd.push((time, cwnd));
+            }
+            if let Some(rtt) = data["smoothed_rtt"].as_f64() {
+                trace.smoothed_rtt.push((time, rtt));
+            }
+            if let Some(rtt) = data["latest_rtt"].as_f64() {
+                trace.latest_rtt.push((time, rtt));
+            }
+        }
+        Ok(trace)
+    }
+}
+
+// Chart of the traces over a shared time axis
+struct Chart {
+    top: f64,
+    title: &'static str,
+    x_max: f64,
+    y_max: f64,
+}
+
+impl Chart {
+    fn x(&self, ms: f64) -> f64 {
+        MARGIN.3 + ms / self.x_max * (WIDTH - MARGIN.3 - MARGIN.1)
+    }
+
+    fn y(&self, value: f64) -> f64 {
+        self.top + CHART_HEIGHT - MARGIN.2 - value / self.y_max * (CHART_HEIGHT - MARGIN.0 - MARGIN.2)
+    }
+
+    // Frame, title, grid lines and tick labels
+    fn axes(&self, svg: &mut String) {
+        let (left, right) = (MARGIN.3, WIDTH - MARGIN.1);
+        let (top, bottom) = (self.top + MARGIN.0, self.top + CHART_HEIGHT - MARGIN.2);
+        let _ = writeln!(
+            svg,
+            r##"<text x="{}" y="{}" font-weight="bold">{}</text>"##,
+            left,
+            top - 12.0,
+            self.title
+        );
+        for tick in ticks(self.y_max) {
+            let y = self.y(tick);
+            let _ = write!(svg, r##"<line x1="{left}" y1="{y:.1}" x2="{right}" y2="{y:.1}" stroke="#ddd"/>"##);
+            let _ = writeln!(
+                svg,
+                r#"<text x="{}" y="{:.1}" text-anchor="end">{}</text>"#,
+                left - 6.0,
+                y + 4.0,
+                label(tick)
+            );
+        }
+        for tick in ticks(self.x_max) {
+            let x = self.x(tick);
+            let _ = write!(svg, r##"<line x1="{x:.1}" y1="{top}" x2="{x:.1}" y2="{bottom}" stroke="#eee"/>"##);
+            let _ = writeln!(
+                svg,
+                r#"<text x="{x:.1}" y="{}" text-anchor="middle">{}</text>"#,
+                bottom + 18.0,
+                label(tick)
+            );
+        }
+        let _ = write!(
+            svg,
+            r##"<rect x="{left}" y="{top}" width="{}" height="{}" fill="none" stroke="#333"/>"##,
+            right - left,
+            bottom - top
+        );
+        let _ = writeln!(
+            svg,
+            r#"<text x="{}" y="{}" text-anchor="middle">time (ms)</text>"#,
+            (left + right) / 2.0,
+            bottom + 38.0
+        );
+    }
+
+    // Line through the points, holding each value until the next one if step is set
+    fn line(&self, svg: &mut String, points: &[(f64, f64)], color: &str, step: bool, dashed: bool) {
+        let mut coords = String::new();
+        for (ind, &(time, value)) in points.iter().enumerate() {
+            if step && ind > 0 {
+                let _ = write!(coords, "{:.1},{:.1} ", self.x(time), self.y(points[ind - 1].1));
+            }
+            let _ = write!(coords, "{:.1},{:.1} ", self.x(time), self.y(value));
+        }
+        let dash = if dashed { r#" stroke-dasharray="4 3""# } else { "" };
+        let _ = writeln!(
+            svg,
+            r#"<polyline points="{}" fill="none" stroke="{color}" stroke-width="1.5"{dash}/>"#,
+            coords.trim_end()
+        );
+    }
+
+    // A dot for every point
+    fn dots(&self, svg: &mut String, points: &[(f64, f64)], color: &str) {
+        for &(time, value) in points {
+            let _ = writeln!(
+                svg,
+                r#"<circle cx="{:.1}" cy="{:.1}" r="1.5" fill="{color}" fill-opacity="0.5"/>"#,
+                self.x(time),
+                self.y(value)
+            );
+        }
+    }
+}
+
+// Render the congestion window and the RTT of every trace as an SVG document
+pub fn render(traces: &[Trace]) -> String {
+    let cwnd: Vec<_> = traces.iter().flat_map(|t| t.cwnd.iter().copied()).collect();
+    let rtt: Vec<_> = traces
+        .iter()
+        .flat_map(|t| t.smoothed_rtt.iter().chain(&t.latest_rtt).copied())
+        .collect();
+    // Leave some room above the largest value
+    let max = |points: &[(f64, f64)], pick: fn(&(f64, f64)) -> f64| {
+        points.iter().map(pick).fold(0.0, f64::max).max(1.0) * 1.05
+    };
+    let x_max = max(&[cwnd.as_slice(), rtt.as_slice()].concat(), |p| p.0);
+
+    let charts = [
+        Chart {
+            top: LEGEND_HEIGHT,
+            title: "Congestion window (bytes)",
+            x_max,
+            y_max: max(&cwnd, |p| p.1),
+        },
+        Chart {
+            top: LEGEND_HEIGHT + CHART_HEIGHT,
+            title: "RTT (ms), smoothed as a line and samples as dots",
+            x_max,
+            y_max: max(&rtt, |p| p.1),
+        },
+    ];
+
+    let height = LEGEND_HEIGHT + CHART_HEIGHT * charts.len() as f64;
+    let mut svg = String::new();
+    let _ = write!(svg, r#"<svg xmlns="http://www.w3.org/2000/svg" width="{WIDTH}" height="{height}""#);
+    let _ = writeln!(svg, r#" viewBox="0 0 {WIDTH} {height}" font-family="sans-serif" font-size="12">"#);
+    let _ = writeln!(svg, r#"<rect width="100%" height="100%" fill="white"/>"#);
+
+    for (ind, trace) in traces.iter().enumerate() {
+        let x = MARGIN.3 + ind as f64 * 160.0;
+        let _ = writeln!(
+            svg,
+            r#"<rect x="{x}" y="10" width="12" height="12" fill="{}"/><text x="{}" y="21">{}</text>"#,
+            COLORS[ind % COLORS.len()],
+            x + 18.0,
+            escape(&trace.name)
+        );
+    }
+
+    for chart in &charts {
+        chart.axes(&mut svg);
+    }
+    for (ind, trace) in traces.iter().enumerate() {
+        let color = COLORS[ind % COLORS.len()];
+        charts[0].line(&mut svg, &trace.cwnd, color, true, false);
+        charts[1].dots(&mut svg, &trace.latest_rtt, color);
+        charts[1].line(&mut svg, &trace.smoothed_rtt, color, false, true);
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+// Round tick values from 0 to max, about five of them
+fn ticks(max: f64) -> Vec<f64> {
+    let rough = max / 5.0;
+    let magnitude = 10f64.powf(rough.log10().floor());
+    let step = [1.0, 2.0, 5.0, 10.0]
+        .iter()
+        .map(|m| m * magnitude)
+        .find(|&step| step >= rough)
+        .unwrap_or(rough);
+    (0..)
+        .map(|ind| ind as f64 * step)
+        .take_while(|&tick| tick <= max)
+        .collect()
+}
+
+// Tick label without trailing zeros, thousands shortened
+fn label(value: f64) -> String {
+    if value >= 10_000.0 {
+        format!("{}k", value / 1000.0)
+    } else {
+        format!("{}", (value * 1000.0).round() / 1000.0)
+    }
+}
+
+// Escape text for XML
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
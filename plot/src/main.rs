@@ -0,0 +1,34 @@
+use clap::Parser;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use plot::{render, Trace};
+
+// Plots the congestion window and the RTT over time from sender event logs (--qlog), one color per log
+#[derive(Parser, Debug)]
+struct Cli {
+    #[arg(required = true)]
+    logs: Vec<PathBuf>,
+    // SVG file to write, stdout if missing
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+fn main() -> Result<(), String> {
+    let cli = Cli::parse();
+    let traces = cli
+        .logs
+        .iter()
+        .map(|path| Trace::from_file(path))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let svg = render(&traces);
+    match cli.output {
+        Some(path) => fs::write(&path, svg)
+            .map_err(|e| format!("{e} -> Unable to write '{}'", path.display())),
+        None => io::stdout()
+            .write_all(svg.as_bytes())
+            .map_err(|e| format!("{e} -> Unable to write stdout")),
+    }
+}
@@ -0,0 +1,11 @@
+pub mod tcp_receiver;
+pub mod util;
+
+pub use tcp_receiver::Receiver;
+pub use util::capture::Capture;
+pub use util::crypto::Psk;
+pub use util::noise::{parse_key, to_hex, Identity};
+pub use util::metrics::Exporter;
+pub use util::stats::Stats;
+pub use util::token::ResumptionKey;
+pub use util::transport::{Clock, SystemClock, Transport};
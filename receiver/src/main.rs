@@ -1,20 +1,160 @@
-mod util;
-use std::fmt::format;
-mod tcp_receiver;
-use std::net::UdpSocket;
+use clap::{Parser, ValueEnum};
+use receiver::{Exporter, Identity, Psk, Receiver, ResumptionKey, Stats};
+use std::io;
+use signal_hook::consts::{SIGINT, SIGTERM};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::info;
+use tracing_subscriber::EnvFilter;
 
-use crate::util::tcp_header::TcpHeader;
-use crate::util::util::*;
-use tcp_receiver::Receiver;
+// Command line arguments
+#[derive(Parser, Debug)]
+#[command(author, about, long_about = None)]
+struct Cli {
+    // Write every datagram sent and received to this pcapng file
+    #[arg(long)]
+    pcap: Option<PathBuf>,
+    // Write a JSON-lines trace of the connection's events to this file
+    #[arg(long)]
+    qlog: Option<PathBuf>,
+    // Encrypt and authenticate the connection with the pre-shared key in this file, the other end needs the same
+    #[arg(long)]
+    psk_file: Option<PathBuf>,
+    // Encrypt the connection with keys from a Noise handshake, under a fresh static key
+    #[arg(long, conflicts_with = "psk_file")]
+    noise: bool,
+    // Same, under the static key in this file, 64 hex digits
+    #[arg(long, conflicts_with = "psk_file")]
+    noise_key: Option<PathBuf>,
+    // Only accept senders whose static public key is in this file, one in hex per line
+    #[arg(long, conflicts_with = "psk_file")]
+    allow: Option<PathBuf>,
+    // Diagnostics to print, a level (error, warn, info, debug, trace) or RUST_LOG directives
+    #[arg(long)]
+    log_level: Option<String>,
+    // Format of the statistics printed at the end of the transfer
+    #[arg(long, value_enum, default_value_t = StatsFormat::Text)]
+    stats: StatsFormat,
+    // Serve Prometheus metrics at http://127.0.0.1:PORT/metrics
+    #[arg(long, conflicts_with = "metrics_file")]
+    metrics_port: Option<u16>,
+    // Rewrite this file with Prometheus metrics, for node_exporter's textfile collector
+    #[arg(long)]
+    metrics_file: Option<PathBuf>,
+    // Seconds between rewrites of the metrics file
+    #[arg(long, default_value_t = 5.0)]
+    metrics_interval: f64,
+    // Give up once the sender went quiet for this many seconds
+    #[arg(long)]
+    idle_timeout: Option<f64>,
+    // Hand out resumption tokens under the key in this file, and take data right behind a SYN that brings
+    // one back. That data can be replayed, see Receiver::set_resumption_key
+    #[arg(long, conflicts_with_all = ["noise", "noise_key", "allow"])]
+    resumption_key: Option<PathBuf>,
+    // Largest datagram to take, the sender learns it from the SYN-ACK
+    #[arg(long, default_value_t = 1500)]
+    max_datagram_size: usize,
+}
+
+// Exit codes when the sender reset the connection or went quiet
+const EXIT_RESET: i32 = 3;
+const EXIT_TIMED_OUT: i32 = 4;
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum StatsFormat {
+    Text,
+    Json,
+}
+
+// Diagnostics go to stderr, filtered by --log-level, or by RUST_LOG without it, at info by default
+fn init_logging(level: Option<&str>) -> Result<(), String> {
+    let filter = match level {
+        Some(level) => EnvFilter::try_new(level),
+        None => EnvFilter::try_from_default_env().or_else(|_| EnvFilter::try_new("info")),
+    }
+    .map_err(|e| format!("{e} -> Invalid log level"))?;
 
-use std::hash::{ Hash, Hasher};
-use std::collections::hash_map::DefaultHasher;
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(io::stderr)
+        .with_ansi(false)
+        .with_target(false)
+        .without_time()
+        .init();
+    Ok(())
+}
 
+// Statistics go to stderr, the receiver's stdout is the data
+fn print_stats(stats: &Stats, format: StatsFormat) {
+    match format {
+        StatsFormat::Text => eprintln!("{stats}"),
+        StatsFormat::Json => eprintln!("{}", stats.to_json()),
+    }
+}
+
+// Public keys of the senders to accept, blank lines and lines starting with # are skipped
+fn read_allowed(path: &Path) -> Result<Vec<[u8; 32]>, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("{e} -> Failed to read allowed keys {}", path.display()))?;
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(receiver::parse_key)
+        .collect()
+}
 
 fn main() {
+    // Parse command line arguments
+    let cli = Cli::parse();
+    init_logging(cli.log_level.as_deref()).unwrap();
     // Get the receiver ready
-    let mut receiver = Receiver::new("127.0.0.1".to_string()).unwrap();
-    // Start the receiver
-    receiver.start().expect("Failed to start the receiver");
-
+    let mut receiver = Receiver::new("127.0.0.1".to_string(), cli.pcap.as_deref()).unwrap();
+    if let Some(path) = cli.psk_file {
+        receiver.set_psk(Psk::from_file(&path).unwrap()).unwrap();
+    }
+    if cli.noise || cli.noise_key.is_some() || cli.allow.is_some() {
+        let identity = match &cli.noise_key {
+            Some(path) => Identity::from_file(path).unwrap(),
+            None => Identity::generate(),
+        };
+        let allowed = cli.allow.as_deref().map(read_allowed).transpose().unwrap();
+        info!(key = %receiver::to_hex(&identity.public()), "Noise static key");
+        receiver.set_identity(identity, allowed).unwrap();
+    }
+    if let Some(path) = cli.resumption_key {
+        receiver
+            .set_resumption_key(ResumptionKey::from_file(&path).unwrap())
+            .unwrap();
+    }
+    if let Some(path) = cli.qlog {
+        receiver.log_events(&path).unwrap();
+    }
+    receiver.set_idle_timeout(cli.idle_timeout.map(Duration::from_secs_f64));
+    receiver.set_max_datagram_size(cli.max_datagram_size).unwrap();
+    if let Some(port) = cli.metrics_port {
+        receiver.export_metrics(Exporter::http(port).unwrap());
+    } else if let Some(path) = cli.metrics_file {
+        let interval = Duration::from_secs_f64(cli.metrics_interval);
+        receiver.export_metrics(Exporter::textfile(path, interval));
+    }
+    // Run until interrupted, then report
+    let stop = Arc::new(AtomicBool::new(false));
+    for signal in [SIGINT, SIGTERM] {
+        signal_hook::flag::register(signal, stop.clone()).unwrap();
+    }
+    if let Err(e) = receiver.start(&stop) {
+        let code = match receiver.error() {
+            Some(io::ErrorKind::ConnectionReset) => EXIT_RESET,
+            Some(io::ErrorKind::TimedOut) => EXIT_TIMED_OUT,
+            _ => panic!("Failed to start the receiver: {e:?}"),
+        };
+        eprintln!("Error: {e}");
+        print_stats(&receiver.stats(), cli.stats);
+        process::exit(code);
+    }
+    print_stats(&receiver.stats(), cli.stats);
 }
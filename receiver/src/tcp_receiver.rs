@@ -1,35 +1,166 @@
 use rand::prelude::*;
-use std::collections::{HashMap, HashSet, VecDeque};
-use std::io::{self, BufRead, Write};
-use std::net::UdpSocket;
-use std::time::Duration;
+use rand::rngs::StdRng;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::io::{self, Write};
+use std::mem;
+use std::net::{SocketAddr, UdpSocket};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::time::Instant;
-use std::thread::sleep;
+use std::time::{Duration, SystemTime};
+use tracing::{debug, field, info, info_span, warn, Span};
 
-use sha2::{Sha256, Digest};
-use generic_array::GenericArray;
-use typenum::U32;
+use crate::util::capture::Capture;
+use crate::util::cookie::Cookies;
+use crate::util::crypto::{session_key, Keys, Protection, Psk, COOKIE_SIZE, RANDOM_SIZE, TOKEN_SIZE};
+use crate::util::noise::{to_hex, Handshake, Identity, KEY_SIZE};
+use crate::util::metrics::{metric, Exporter};
+use crate::util::qlog::{packet_header, EventLog};
+use crate::util::seq::SeqNum;
+use crate::util::stats::Stats;
+use crate::util::tcp_header::{
+    TcpHeader, FORWARD, HEADER_SIZE, MSG_END, MSG_START, MSS, PATH_CHALLENGE, PATH_RESPONSE, RESUMED,
+    RETRY, RST, TOKEN, UNORDERED,
+};
+use crate::util::token::ResumptionKey;
+use crate::util::transport::{Clock, SystemClock, Transport};
+use crate::util::util::{encode_sack, segment_len};
 
-use crate::util::tcp_header::TcpHeader;
-use crate::{read_to_string, safe_increment};
+const MAX_SACK_BLOCKS: usize = 4; // SACK blocks carried by an ACK
+const MAX_BUFFERED: usize = 4 << 20; // Bytes a connection holds for reassembly and for the application
+const MAX_DATAGRAM: usize = 1500; // Largest datagram taken by default, anything longer is cut short
+const MIN_DATAGRAM: usize = 256; // Room for the largest handshake datagram, the ACK of a Noise handshake
+const NONCE_LIMIT: u32 = 1 << 31; // ACKs a sealed connection may send, every one takes a sequence number for its nonce
 
 // Receiver state
 #[derive(Debug)]
 enum Status {
-    StandBy, // Waiting for the first packet from sender (handshake)
-    Handshake,
-    Sending
+    StandBy, // Waiting for an ACK that echoes the cookie of our SYN-ACK (handshake)
+    Sending,
+    Reset, // The sender reset the connection
+    TimedOut, // The sender stopped sending
 }
 
-// Packet struct
-#[derive(Clone, Debug)]
-struct Packet {
-    timestamp: Instant,
-    data: Vec<u8>,
-    seq_num: u32,
-    ack_num: u32,
-    confirm_ack: u32,
-    data_len: u16,
+// Outstanding validation of a new sender address
+#[derive(Debug)]
+struct PathChallenge {
+    addr: SocketAddr,
+    token: [u8; 8],
+    timestamp: Instant, // time when the challenge is sent
+}
+
+// Noise handshake of a SYN whose cookie came back, kept until its ACK
+#[derive(Debug)]
+struct PendingHandshake {
+    addr: SocketAddr,
+    connection_id: u64,
+    cookie: Vec<u8>,
+    handshake: Handshake,
+    reply: Vec<u8>, // Our message, the same SYN gets it again
+}
+
+// Incoming stream, reassembled independently of the other streams
+#[derive(Debug, Default)]
+struct RecvStream {
+    expect_seq: SeqNum,                         // Stream sequence number delivered next
+    cache: HashMap<SeqNum, (u8, u32, Vec<u8>)>, // Out-of-order segments, with their frame flags and length
+    ready: Vec<u8>,                          // Delivered in order, not read by the application yet
+    partial: Option<Vec<u8>>,                // Message being reassembled, waiting for its last fragment
+    messages: VecDeque<Vec<u8>>,             // Complete messages and unordered records, not read by the application yet
+    skipping: bool,                          // Dropping the rest of a message the sender abandoned
+}
+
+impl RecvStream {
+    // Deliver a segment if it's next in the stream, otherwise keep it until the gap is filled
+    fn insert(&mut self, stream_seq: SeqNum, frame_flags: u8, data: &[u8]) {
+        let len = segment_len(frame_flags, data);
+
+        // Already delivered
+        if stream_seq < self.expect_seq {
+            return;
+        }
+
+        // An unordered record goes out right away, only its slot waits for the segments before it
+        let data = if frame_flags & (UNORDERED | FORWARD) == UNORDERED {
+            self.messages.push_back(data.to_vec());
+            &[]
+        } else {
+            data
+        };
+
+        if stream_seq != self.expect_seq {
+            self.cache.insert(stream_seq, (frame_flags, len, data.to_vec()));
+            return;
+        }
+
+        self.deliver(frame_flags, len, data);
+        while let Some((frame_flags, len, data)) = self.cache.remove(&self.expect_seq) {
+            self.deliver(frame_flags, len, &data);
+        }
+        // Segments that overlap what was delivered will never be next
+        let expect_seq = self.expect_seq;
+        self.cache.retain(|&seq, _| seq > expect_seq);
+    }
+
+    // Hand an in-order segment to the byte stream or to the message it belongs to
+    fn deliver(&mut self, frame_flags: u8, len: u32, data: &[u8]) {
+        self.expect_seq += len;
+
+        // The sender gave up on this message, throw away what we have of it
+        if frame_flags & FORWARD != 0 {
+            self.partial = None;
+            self.skipping = frame_flags & MSG_END == 0;
+            return;
+        }
+
+        // Already delivered when it arrived
+        if frame_flags & UNORDERED != 0 {
+            return;
+        }
+
+        // Fragments of an abandoned message can still arrive after its skip marker
+        if self.skipping {
+            if frame_flags & MSG_START == 0 {
+                self.skipping = frame_flags & MSG_END == 0;
+                return;
+            }
+            self.skipping = false;
+        }
+
+        if frame_flags & MSG_START != 0 {
+            self.partial = Some(Vec::new());
+        }
+
+        // Fragments in the middle of a message carry no frame flags, they follow its first fragment
+        match self.partial.as_mut() {
+            Some(partial) => partial.extend_from_slice(data),
+            None => {
+                self.ready.extend_from_slice(data);
+                return;
+            }
+        }
+        if frame_flags & MSG_END != 0 {
+            self.messages.push_back(self.partial.take().unwrap());
+        }
+    }
+
+    // Memory held by this stream, every segment or message costs a header on top of its bytes
+    fn buffered(&self) -> usize {
+        self.ready.len()
+            + self.partial.as_ref().map_or(0, |msg| msg.len())
+            + self.messages.iter().map(|msg| msg.len() + HEADER_SIZE).sum::<usize>()
+            + self.cache.values().map(|(_, _, data)| data.len() + HEADER_SIZE).sum::<usize>()
+    }
+
+    // Flow-control credit left for this stream
+    fn credit(&self, wnd_size: u16) -> u16 {
+        let buffered = self.ready.len()
+            + self.partial.as_ref().map_or(0, |msg| msg.len())
+            + self.messages.iter().map(|msg| msg.len()).sum::<usize>();
+        wnd_size.saturating_sub(buffered.min(u16::MAX as usize) as u16)
+    }
 }
 
 // Receiver struct
@@ -37,269 +168,987 @@ struct Packet {
 pub struct Receiver {
     remote_host: String,
     remote_port: u16,
-    local_host: String,
     local_port: u16,
+    ports: (u16, u16), // Source and destination ports of the handshake ACK, every segment must carry them
+    idle_timeout: Option<Duration>, // Give up once nothing was heard from the sender for this long
+    last_heard: Option<Instant>,    // When the latest valid packet from the sender arrived
     status: Status,
-    seq_num: u32,
-    ack_num: u32,
-    expect_seq: VecDeque<u32>,
-    expect_ack: VecDeque<u32>,
-    init_seq: u32,
-    socket: UdpSocket,
+    seq_num: SeqNum,
+    ack_num: SeqNum,
+    init_seq: SeqNum,
+    socket: Box<dyn Transport>,
+    clock: Arc<dyn Clock>,
+    rng: StdRng,
     rto: u64,
-    in_flight: VecDeque<Packet>,
     wnd_size: u16,
-    cur_wnd: u16,
-    file: String,
-    cur_buf: u16,
-    cache: HashMap<u32, String>, // check broken order 
-    seen: HashSet<u32> // Include correct and broken order
+    streams: BTreeMap<u16, RecvStream>, // Streams multiplexed on this connection
+    cache: HashMap<SeqNum, u32>, // check broken order, length of the segments received ahead of ack_num
+    connection_id: u64, // Picked by the sender in the SYN
+    path_challenge: Option<PathChallenge>, // Pending validation if the sender's address changed
+    qlog: Option<EventLog>, // Structured trace of the connection, if asked for
+    span: Span,             // Diagnostics of this connection, its ID is known once the SYN arrives
+    state_span: Span,       // Diagnostics of the current status, inside the connection's span
+    stats: Stats,
+    started: Option<Instant>,   // When the SYN arrived
+    last_data: Option<Instant>, // When the latest new data segment arrived
+    acks_sent: u64,
+    exporter: Option<Exporter>, // Live metrics, if asked for
+    protection: Protection,
+    identity: Option<Identity>,           // Our static key, the connection's keys come from a Noise handshake with it
+    allowed: Option<Vec<[u8; KEY_SIZE]>>, // Static keys of the senders we accept, any if None
+    cookies: Cookies,                     // Validates the address of a SYN before anything is kept for it
+    pending: Option<PendingHandshake>,    // Noise handshake of the latest SYN that echoed a cookie
+    resumption: Option<ResumptionKey>,      // Hands out resumption tokens, and takes them back in a SYN
+    resumed: bool,                          // Set up on a SYN that brought a token back, without a handshake ACK
+    max_datagram: usize,                    // Largest datagram taken, the receive buffer's size
+    buf: Vec<u8>,                           // Receive buffer, max_datagram bytes
 }
 
 impl Receiver {
-    // Constructor
-    pub fn new(local_host: String) -> Result<Self, String> {
-        let mut rng = rand::thread_rng();
-        let seq_num: u32 = rng.gen();
-
+    // Constructor, every datagram goes to the capture file if one is given
+    pub fn new(local_host: String, capture: Option<&Path>) -> Result<Self, String> {
         let socket = UdpSocket::bind(format!("{}:{}", local_host, 0))
             .map_err(|e| format!("{} -> Failed to bind to {}:{}", e, local_host, 0))?;
-        let local = socket
-            .local_addr()
-            .map_err(|e| format!("{e} -> Failed to get local port"))?;
         socket
             .set_nonblocking(true)
             .map_err(|e| format!("{e} -> Failed to switch to non-blocking mode"))?;
 
-        eprint!("Bound to port {}", socket.local_addr().unwrap().port());
-        io::stderr().flush();
+        let clock: Arc<dyn Clock> = Arc::new(SystemClock);
+        let socket: Box<dyn Transport> = match capture {
+            Some(path) => Box::new(Capture::new(Box::new(socket), path, clock.clone())?),
+            None => Box::new(socket),
+        };
+
+        let receiver = Self::with_transport(socket, clock, rand::thread_rng().gen())?;
+
+        // The test harness reads the port from this line, so it is printed whatever the log level
+        eprintln!("Bound to port {}", receiver.local_port);
+
+        Ok(receiver)
+    }
+
+    // Constructor over any transport and clock, the seed makes the connection reproducible
+    pub fn with_transport(
+        socket: Box<dyn Transport>,
+        clock: Arc<dyn Clock>,
+        seed: u64,
+    ) -> Result<Self, String> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let seq_num = SeqNum(rng.gen());
+
+        let local = socket
+            .local_addr()
+            .map_err(|e| format!("{e} -> Failed to get local port"))?;
+
+        let cookies = Cookies::new(rng.gen(), clock.now());
+
+        let span = info_span!("connection", id = field::Empty);
+        let state_span = info_span!(parent: &span, "StandBy");
 
         Ok(Receiver {
             remote_host: "".to_string(),
             remote_port: 0,
-            local_host,
             local_port: local.port(),
+            ports: (0, 0),
+            idle_timeout: None,
+            last_heard: None,
             status: Status::StandBy,
             init_seq: seq_num,
             seq_num,
-            ack_num: 0,
-            expect_seq: VecDeque::new(),
-            expect_ack: VecDeque::new(),
+            ack_num: SeqNum(0),
             socket,
+            clock,
+            rng,
             rto: 1000,
-            in_flight: VecDeque::new(),
             wnd_size: 65340,
-            cur_wnd: 65340,
-            file: String::new(),
-            cur_buf: 0,
+            streams: BTreeMap::new(),
             cache: HashMap::new(),
-            seen: HashSet::new()
+            connection_id: 0,
+            path_challenge: None,
+            qlog: None,
+            span,
+            state_span,
+            stats: Stats::default(),
+            started: None,
+            last_data: None,
+            acks_sent: 0,
+            exporter: None,
+            protection: Protection::default(),
+            identity: None,
+            allowed: None,
+            cookies,
+            pending: None,
+            resumption: None,
+            resumed: false,
+            max_datagram: MAX_DATAGRAM,
+            buf: vec![0; MAX_DATAGRAM],
         })
     }
-    // Start the receiver
-    pub fn start(&mut self) -> Result<(), String> {
-        loop {
-            match self.status {
-                // Get the SYN packet from the sender
-                Status::StandBy => {
-                    eprintln!("Standby");
-                    let mut buf: [u8; 1500] = [0; 1500];
-                    loop {
-                        match self.socket.recv_from(&mut buf) {
-                            Ok((_, addr)) => {
-                                let header = TcpHeader::new(&buf[..48]);
-
-                                // Check if the hash value of the header matches the hash value in the header
-                                if !Self::check_hash(&header) {
-                                    continue;
-                                }
-                                if header.flags != 2 {
-                                    continue;
-                                }
-
-                                let a: Vec<String> = addr.to_string().split(":").map(|x| x.to_string()).collect();
-
-                                self.remote_host = a[0].to_string();
-                                self.remote_port = a[1].to_string().parse::<u16>().unwrap();
-                                self.ack_num = header.sequence_number;
-
-                                eprintln!("coming seq# {}, curtent ack# {}", header.sequence_number, self.ack_num);
-
-                                self.send_ack("1", 0b0001_0010);
-
-                                buf.fill(0);
-                                break;
-                            }
-                            Err(_) => {}
-                        }
-                    }
-
-                    self.status = Status::Handshake;
-                }
-                // Get the ACK packet from the sender
-                Status::Handshake => {
-                    eprintln!("Handshake");
-                    let mut buf: [u8; 1500] = [0; 1500];
-                    loop {
-
-                        match self.socket.recv(&mut buf) {
-                            Ok(_) => {
-                                let header = TcpHeader::new(&buf[..48]);
-
-                                // Check if the hash value of the header matches the hash value in the header
-                                if !Self::check_hash(&header) {
-                                    continue;
-                                }
-
-                                if header.sequence_number != self.ack_num {
-                                    continue;
-                                }
-
-                                if header.flags != 16 {
-                                    continue;
-                                }
-                                
-                                self.send_ack("1", 0b0001_0000);
-                                self.status = Status::Sending;
-
-                                buf.fill(0);
-                                break;
-                            }
-                            Err(_) => {}
-                        }
-                    }
-
-                    self.status = Status::Sending;
+    // Start the receiver, print everything delivered on the streams to stdout until stop is set
+    pub fn start(&mut self, stop: &AtomicBool) -> Result<(), String> {
+        while !stop.load(Ordering::Relaxed) {
+            self.poll()?;
+
+            for stream_id in self.readable() {
+                let mut data = self.read(stream_id);
+                while let Some(msg) = self.recv_message(stream_id) {
+                    data.extend(msg);
                 }
+                io::stdout()
+                    .write_all(&data)
+                    .and_then(|_| io::stdout().flush())
+                    .map_err(|e| format!("{e} -> Failed to write stdout"))?;
+            }
+        }
+        Ok(())
+    }
+
+    // Make progress without blocking, delivered data waits in its stream until read
+    pub fn poll(&mut self) -> Result<(), String> {
+        let span = self.state_span.clone();
+        let _entered = span.enter();
+
+        if let Some(mut exporter) = self.exporter.take() {
+            exporter.poll(self.clock.now(), || self.metrics());
+            self.exporter = Some(exporter);
+        }
+
+        let idle = match (self.idle_timeout, self.last_heard) {
+            (Some(timeout), Some(last_heard)) => self.clock.now() >= last_heard + timeout,
+            _ => false,
+        };
+        if idle && matches!(self.status, Status::Sending) {
+            warn!(timeout_ms = self.idle_timeout.unwrap().as_millis() as u64, "nothing heard from the sender");
+            self.set_status(Status::TimedOut);
+        }
+
+        match self.status {
+            Status::Reset => return Err("The connection was reset by the sender".to_string()),
+            Status::TimedOut => return Err("The connection timed out".to_string()),
+            _ => {}
+        }
+        if self.protection.keys.is_some() && self.seq_num - self.init_seq >= NONCE_LIMIT {
+            return Err("The sealed connection ran out of nonces".to_string());
+        }
+
+        // Out of the struct while the datagram is handled, so the handlers can still take &mut self
+        let mut buf = mem::take(&mut self.buf);
+        if let Ok((len, addr)) = self.socket.recv_from(&mut buf) {
+            self.stats.received(len);
+            match self.status {
+                // Answer SYNs until an ACK echoes our cookie
+                Status::StandBy => self.handle_handshake(&buf[..len], addr),
                 // Get the data packet from the sender and send ACK back
-                Status::Sending => {
-                    eprintln!("Sending");
-                    loop {
-
-                        let mut buf: [u8; 1500] = [0; 1500];
-                        match self.socket.recv(&mut buf) {
-                            Ok(_) => {
-                                let header = TcpHeader::new(&buf[..48]);
-                                
-                                // ACK + PSH, ACK, FIN
-                                if header.flags != 24 && header.flags != 16 {
-                                    continue;
-                                }
-                                
-                                // If it's ACK from the handshake
-                                if header.flags == 16 {
-                                    // Check if the hash value of the header matches the hash value in the header
-                                    if !Self::check_hash(&header) {
-                                        continue;
-                                    }
-                                }
-
-                                // If it's ACK + PSH from the sending phase
-                                if header.flags == 24 {
-                                    // Check if the hash value of the header and data matches the hash value in the header
-                                    if !Self::check_header_data_hash(&header, &buf[48..]) {
-                                        continue;
-                                    }
-                                }
-
-                                // For out-of-order packets, it checks if the sequence number has been seen before.
-                                if header.sequence_number != self.ack_num {
-                                    if !self.seen.contains(&header.sequence_number) {
-                                        self.seen.insert(header.sequence_number);
-                                        self.cache.insert(header.sequence_number, read_to_string(&buf[48..]));
-                                    }
-                                    self.send_ack("", 0b0001_0000);
-                                } else {
-                                    // if header.flags == 1 {
-                                    //     // print!("{}", &self.file);
-                                    //     self.send_ack("1", 0b0001_0001);
-                                    //     break;
-                                    // }
-
-                                    // Marks the packet's sequence number as seen.
-                                    self.seen.insert(header.sequence_number);
-                                    let mut data = read_to_string(&buf[48..]);
-                                    let cached_data = self.check_cache(safe_increment(self.ack_num, data.len() as u32));
-
-                                    data.push_str(&cached_data);
-                                    self.file.push_str(&data);
-                                    print!("{}", data);
-                                    io::stdout().flush();
-                                    self.send_ack(&data, 0b0001_0000);
-                                }
-
-                                buf.fill(0);
-                            }
-                            Err(_) => {}
-                        }
-
-                    }
-                }
+                Status::Sending => self.handle_data(&buf[..len], addr),
+                Status::Reset | Status::TimedOut => {}
             }
         }
+        self.buf = buf;
+        Ok(())
+    }
+
+    // Fail with TimedOut once the sender set up a connection and then went quiet for this long.
+    // A sender with nothing to send keeps the connection open with keepalive probes.
+    pub fn set_idle_timeout(&mut self, timeout: Option<Duration>) {
+        self.idle_timeout = timeout;
+    }
+
+    // Use the given initial sequence number instead of the random one, only before the handshake
+    pub fn set_initial_seq(&mut self, seq: u32) -> Result<(), String> {
+        if !matches!(self.status, Status::StandBy) {
+            return Err("The connection is already open".to_string());
+        }
+        self.init_seq = SeqNum(seq);
+        self.seq_num = SeqNum(seq);
+        Ok(())
+    }
+
+    // Only accept a sender that shares this key, and encrypt the connection with it. Only before the handshake.
+    pub fn set_psk(&mut self, psk: Psk) -> Result<(), String> {
+        if !matches!(self.status, Status::StandBy) {
+            return Err("The connection is already open".to_string());
+        }
+        if self.identity.is_some() {
+            return Err("The connection already uses a Noise handshake".to_string());
+        }
+        self.protection.psk = Some(psk);
+        Ok(())
+    }
+
+    // Encrypt the connection with keys from a Noise handshake under our static key, and only
+    // accept senders whose static key is allowed if a list is given. Only before the handshake.
+    pub fn set_identity(
+        &mut self,
+        identity: Identity,
+        allowed: Option<Vec<[u8; KEY_SIZE]>>,
+    ) -> Result<(), String> {
+        if !matches!(self.status, Status::StandBy) {
+            return Err("The connection is already open".to_string());
+        }
+        if self.protection.psk.is_some() {
+            return Err("The connection already uses a pre-shared key".to_string());
+        }
+        if self.resumption.is_some() {
+            return Err("The connection already takes resumption tokens".to_string());
+        }
+        self.identity = Some(identity);
+        self.allowed = allowed;
+        Ok(())
+    }
+
+    // Hand out resumption tokens under this key, and set the connection up on a SYN that brings one back,
+    // taking the data sent right behind it without waiting for a handshake ACK. Receivers with the same key
+    // take each other's tokens. Only before the handshake, and not with a Noise handshake.
+    // That data can be replayed: whoever recorded the SYN and what followed it can send them again while
+    // the token is fresh, and a receiver with this key delivers them again. Keep it to idempotent requests.
+    pub fn set_resumption_key(&mut self, key: ResumptionKey) -> Result<(), String> {
+        if !matches!(self.status, Status::StandBy) {
+            return Err("The connection is already open".to_string());
+        }
+        if self.identity.is_some() {
+            return Err("Resumption tokens don't work with a Noise handshake".to_string());
+        }
+        self.resumption = Some(key);
+        Ok(())
+    }
+
+    // Take datagrams of up to this many bytes, announced to the sender in the SYN-ACK. Only before the handshake.
+    pub fn set_max_datagram_size(&mut self, size: usize) -> Result<(), String> {
+        if !matches!(self.status, Status::StandBy) {
+            return Err("The connection is already open".to_string());
+        }
+        if !(MIN_DATAGRAM..=u16::MAX as usize).contains(&size) {
+            return Err(format!(
+                "The largest datagram must be {MIN_DATAGRAM} to {} bytes, got {size}",
+                u16::MAX
+            ));
+        }
+        self.max_datagram = size;
+        self.buf.resize(size, 0);
+        Ok(())
+    }
+
+    // Write a structured trace of the connection to the given file, one JSON event per line
+    pub fn log_events(&mut self, path: &Path) -> Result<(), String> {
+        self.qlog = Some(EventLog::new(path, "server", self.clock.now())?);
         Ok(())
     }
 
-    // Helper function to check if the hash value of the header matches the hash value in the header
-    fn check_hash(header: &TcpHeader) -> bool {
-        let hash = header.calculate_header_hash();
-        hash == header.hash_value
+    // Publish live metrics while polling, over HTTP or to a file
+    pub fn export_metrics(&mut self, exporter: Exporter) {
+        self.exporter = Some(exporter);
     }
 
-    // Helper function to check if the hash value of the header and data matches the hash value in the header
-    fn check_header_data_hash(header: &TcpHeader, data: &[u8]) -> bool {
-        // Delete the empty space at the end of the array
-        let d2 = read_to_string(data);
-        let hash = header.calculate_header_data_hash(d2.as_bytes());
-        hash == header.hash_value
+    // Metrics in the Prometheus text format
+    pub fn metrics(&self) -> String {
+        let mut out = String::new();
+        let active = matches!(self.status, Status::Sending);
+        metric(
+            &mut out,
+            "transport_receiver_active_connections",
+            "gauge",
+            "Connections past the handshake",
+            active as u64,
+        );
+        metric(
+            &mut out,
+            "transport_receiver_delivered_bytes_total",
+            "counter",
+            "Stream bytes handed to the application",
+            self.stats.payload_bytes,
+        );
+        metric(
+            &mut out,
+            "transport_receiver_buffered_bytes",
+            "gauge",
+            "Bytes held for reassembly and for the application",
+            self.buffered() as u64,
+        );
+        metric(
+            &mut out,
+            "transport_receiver_hash_mismatch_drops_total",
+            "counter",
+            "Packets dropped because their hash didn't match",
+            self.stats.corrupted,
+        );
+        metric(
+            &mut out,
+            "transport_receiver_acks_sent_total",
+            "counter",
+            "ACKs sent, SYN-ACKs and ACKs sent again included",
+            self.acks_sent,
+        );
+        out
     }
 
-    // Retrieve and concatenate data from a cache based on sequential packet sequence numbers.
-    fn check_cache(&mut self, mut seq_num: u32) -> String {
-        let mut data = String::new();
+    // Counters of the connection so far, the transfer time runs until the latest new data
+    pub fn stats(&self) -> Stats {
+        let mut stats = self.stats.clone();
+        if let (Some(started), Some(last_data)) = (self.started, self.last_data) {
+            stats.elapsed = last_data.saturating_duration_since(started);
+        }
+        stats
+    }
 
-        while self.cache.contains_key(&seq_num) {
-            let tmp = self.cache.remove(&seq_num).unwrap();
-            seq_num += tmp.len() as u32;
-            data.push_str(&tmp);
+    // Why the connection ended, if the sender gave up on it
+    pub fn error(&self) -> Option<io::ErrorKind> {
+        match self.status {
+            Status::Reset => Some(io::ErrorKind::ConnectionReset),
+            Status::TimedOut => Some(io::ErrorKind::TimedOut),
+            _ => None,
         }
+    }
 
+    // Port the receiver is bound to, senders connect to it
+    pub fn local_port(&self) -> u16 {
+        self.local_port
+    }
+
+    // Take the data delivered in order on a stream
+    pub fn read(&mut self, stream_id: u16) -> Vec<u8> {
+        let data = match self.streams.get_mut(&stream_id) {
+            Some(stream) => std::mem::take(&mut stream.ready),
+            None => Vec::new(),
+        };
+        self.stats.payload_bytes += data.len() as u64;
         data
     }
-    // Send ACK back to the sender
-    fn send_ack(&mut self, data: &str, flags: u8) {
-        if flags != 1 {
-            self.ack_num = safe_increment(self.ack_num, data.len() as u32);
+
+    // Take the next complete message on a stream, if any
+    pub fn recv_message(&mut self, stream_id: u16) -> Option<Vec<u8>> {
+        let msg = self
+            .streams
+            .get_mut(&stream_id)
+            .and_then(|stream| stream.messages.pop_front())?;
+        self.stats.payload_bytes += msg.len() as u64;
+        Some(msg)
+    }
+
+    // Bytes held for reassembly and for the application, bounded whatever the sender does
+    pub fn buffered(&self) -> usize {
+        self.streams.values().map(|stream| stream.buffered()).sum()
+    }
+
+    // Streams with delivered data or messages waiting to be read
+    pub fn readable(&self) -> Vec<u16> {
+        self.streams
+            .iter()
+            .filter(|(_, stream)| !stream.ready.is_empty() || !stream.messages.is_empty())
+            .map(|(id, _)| *id)
+            .collect()
+    }
+
+    // Handle a packet received before the connection is set up: a SYN gets a cookie in its SYN-ACK,
+    // and nothing is kept until an ACK echoes the cookie from the same address
+    fn handle_handshake(&mut self, buf: &[u8], addr: SocketAddr) {
+        let header = match TcpHeader::new(buf) {
+            Ok(header) => header,
+            Err(_) => return,
+        };
+
+        self.log_packet("transport:packet_received", &header, buf.len());
+
+        // Data before a cookie came back is dropped unanswered: its source address isn't validated, so an
+        // answer could be aimed at someone else. Data that overtook the handshake ACK is sent again.
+        if header.flags == 24 {
+            self.log_dropped("no_connection");
+            return;
+        }
+
+        // Check the hash, or the MAC with a pre-shared key
+        let payload = match self.protection.open(&header, &buf[HEADER_SIZE..]) {
+            Some(payload) => payload,
+            None => {
+                self.stats.corrupted += 1;
+                self.log_dropped("hash_mismatch");
+                return;
+            }
+        };
+
+        match header.flags {
+            2 => self.answer_syn(&header, &payload, addr),
+            16 => self.complete_handshake(&header, &payload, addr),
+            _ => {}
+        }
+    }
+
+    // Answer a SYN with a cookie for its address, a resumption token if we hand them out, and our half
+    // of the key material, if any. A SYN that brings a token of ours back sets the connection up.
+    // With an identity, the first SYN only gets the cookie: the Noise handshake starts when it comes back.
+    fn answer_syn(&mut self, syn: &TcpHeader, payload: &[u8], addr: SocketAddr) {
+        let syn_size = payload.len();
+        // The largest datagram the sender takes ends the SYN, our ACKs are small enough for any
+        let payload = match syn.frame_flags & MSS {
+            0 => payload,
+            _ if payload.len() >= 2 => &payload[..payload.len() - 2],
+            _ => return,
+        };
+        // The token of an earlier connection follows the key material
+        let (payload, token) = match syn.frame_flags & TOKEN {
+            0 => (payload, None),
+            _ if payload.len() >= TOKEN_SIZE => {
+                let (payload, token) = payload.split_at(payload.len() - TOKEN_SIZE);
+                (payload, Some(token))
+            }
+            _ => return,
+        };
+        // The cookie of our retry follows the Noise message, then padding
+        let (payload, echoed) = match syn.frame_flags & RETRY {
+            0 => (payload, None),
+            _ if self.identity.is_some() && payload.len() >= KEY_SIZE + COOKIE_SIZE => {
+                let (payload, rest) = payload.split_at(KEY_SIZE);
+                (payload, Some(&rest[..COOKIE_SIZE]))
+            }
+            _ => return,
+        };
+        let next_seq = syn.sequence_number + 1;
+        let now = self.clock.now();
+        let cookie = match echoed {
+            None => self.cookies.issue(now, addr, syn.connection_id, next_seq, payload).to_vec(),
+            Some(cookie) if self.cookies.check(now, cookie, addr, syn.connection_id, next_seq, payload) => {
+                cookie.to_vec()
+            }
+            Some(_) => {
+                self.log_dropped("invalid_cookie");
+                return;
+            }
+        };
+        let retry = self.identity.is_some() && echoed.is_none();
+        let material = match (&self.identity, echoed) {
+            (None, _) => self.key_material(&cookie, payload),
+            (Some(_), None) => Some(Vec::new()),
+            (Some(_), Some(_)) => self.start_handshake(syn, payload, &cookie, addr),
+        };
+        let Some(material) = material else {
+            self.log_dropped("handshake_failed");
+            return;
+        };
+        debug!(seq = %syn.sequence_number, from = %addr, retry, "SYN");
+
+        let mut header = self.new_header(0b0001_0010, 0);
+        header.sequence_number = self.init_seq;
+        header.destination_port = syn.source_port;
+        header.ack_number = next_seq;
+        header.connection_id = syn.connection_id;
+        let mut reply = cookie.to_vec();
+        let mut resumed = false;
+        if let Some(key) = &self.resumption {
+            let now = SystemTime::now();
+            resumed = token.is_some_and(|token| key.check(token, addr.ip(), now));
+            header.frame_flags = TOKEN | if resumed { RESUMED } else { 0 };
+            reply.extend(key.issue(addr.ip(), now));
+        }
+        reply.extend(material);
+        if retry {
+            header.frame_flags |= RETRY;
+        }
+        // Then the largest datagram we take, the sender cuts its segments to fit
+        header.frame_flags |= MSS;
+        reply.extend((self.max_datagram as u16).to_be_bytes());
+        // A SYN that echoes our cookie is padded to the size of its answer, we never send more than it brought
+        if echoed.is_some() && reply.len() > syn_size {
+            self.log_dropped("syn_too_small");
+            return;
+        }
+        let bytes = self.protection.seal_unkeyed(&mut header, &reply);
+        Self::send_data(
+            &addr.ip().to_string(),
+            &addr.port(),
+            &bytes,
+            self.socket.as_ref(),
+            &mut self.stats,
+        );
+        self.log_packet("transport:packet_sent", &header, bytes.len());
+        self.acks_sent += 1;
+
+        if resumed && matches!(self.status, Status::StandBy) {
+            self.resume(syn, payload, token.unwrap(), addr);
+        }
+    }
+
+    // Set up the connection on a SYN that brought a token back, under keys from its token: with a pre-shared
+    // key and both randoms, without from the token itself. The sender skips the handshake ACK's sequence number.
+    fn resume(&mut self, syn: &TcpHeader, material: &[u8], token: &[u8], addr: SocketAddr) {
+        match &self.protection.psk {
+            Some(psk) => self.protection.keys = Some(Keys::derive(psk, material, token, false)),
+            None => self.protection.session = Some(session_key(token, syn.connection_id)),
+        }
+        self.set_up(syn, addr);
+        self.ack_num = syn.sequence_number + 2;
+        self.seq_num = self.init_seq + 1;
+        self.resumed = true;
+
+        debug!(from = %addr, "resumed");
+        self.set_status(Status::Sending);
+    }
+
+    // Set up the connection once an ACK from the address of a SYN echoes its cookie, along with
+    // the SYN's key material and, with an identity, the sender's last Noise message
+    fn complete_handshake(&mut self, header: &TcpHeader, payload: &[u8], addr: SocketAddr) {
+        let syn_len = match (&self.protection.psk, &self.identity) {
+            (Some(_), _) => RANDOM_SIZE,
+            (None, Some(_)) => KEY_SIZE,
+            (None, None) => 0,
+        };
+        if payload.len() < COOKIE_SIZE + syn_len || header.ack_number != self.seq_num + 1 {
+            return;
+        }
+        let (cookie, rest) = payload.split_at(COOKIE_SIZE);
+        let (syn, reply) = rest.split_at(syn_len);
+
+        let now = self.clock.now();
+        if !self
+            .cookies
+            .check(now, cookie, addr, header.connection_id, header.sequence_number, syn)
+        {
+            self.log_dropped("invalid_cookie");
+            return;
+        }
+
+        // Everything after the handshake is sealed with keys from both randoms, the SYN-ACK's made again
+        // from the cookie
+        if let Some(psk) = &self.protection.psk {
+            let Some(material) = self.key_material(cookie, syn) else {
+                return;
+            };
+            self.protection.keys = Some(Keys::derive(psk, syn, &material, false));
+        }
+        // Or with keys from the Noise handshake the SYN started, whose last message decides whether we talk
+        // to the sender
+        if self.identity.is_some() {
+            let pending = self.pending.as_mut().filter(|pending| {
+                pending.addr == addr && pending.connection_id == header.connection_id && pending.cookie == cookie
+            });
+            if pending.is_none_or(|pending| pending.handshake.read(reply).is_err()) {
+                self.log_dropped("handshake_failed");
+                return;
+            }
+            let handshake = self.pending.take().unwrap().handshake;
+            let remote = handshake.remote_static().unwrap();
+            // The cookie showed the sender is at its address, tell it so it doesn't keep trying
+            if self.allowed.as_ref().is_some_and(|allowed| !allowed.contains(&remote)) {
+                warn!(key = %to_hex(&remote), "sender not allowed");
+                self.log_dropped("sender_not_allowed");
+                self.send_reset(header, addr);
+                return;
+            }
+            info!(key = %to_hex(&remote), "sender authenticated");
+            self.protection.keys = Some(handshake.into_keys());
+        }
+        // Without keys, the rest of the connection is authenticated under a key from the cookie
+        if self.protection.keys.is_none() {
+            self.protection.session = Some(session_key(cookie, header.connection_id));
+        }
+
+        self.set_up(header, addr);
+        self.ack_num = header.sequence_number;
+        self.seq_num = header.ack_number;
+
+        debug!(from = %addr, "handshake completed");
+        self.send_ack(1, 0b0001_0000, 0);
+        self.set_status(Status::Sending);
+    }
+
+    // Take the sender's address, ports and connection ID from the packet that set the connection up
+    fn set_up(&mut self, header: &TcpHeader, addr: SocketAddr) {
+        self.remote_host = addr.ip().to_string();
+        self.remote_port = addr.port();
+        self.ports = (header.source_port, header.destination_port);
+        self.last_heard = Some(self.clock.now());
+        // From now on, only packets carrying this ID belong to us
+        self.connection_id = header.connection_id;
+        self.span
+            .record("id", field::display(format!("{:016x}", self.connection_id)));
+        self.started = Some(self.clock.now());
+    }
+
+    // Our key material for the SYN-ACK without an identity, derived from the cookie so it can be made again
+    // when the cookie comes back: a random with a pre-shared key, nothing without
+    fn key_material(&self, cookie: &[u8], syn: &[u8]) -> Option<Vec<u8>> {
+        if self.protection.psk.is_some() {
+            if syn.len() != RANDOM_SIZE {
+                return None;
+            }
+            return Some(self.cookies.derive(cookie, b"server random").to_vec());
+        }
+        Some(Vec::new())
+    }
+
+    // Our Noise message answering the SYN's, under a fresh ephemeral key. The cookie it echoed showed the
+    // sender is at its address, so the handshake is kept for its ACK, only the latest one. The same SYN
+    // sent again gets the same message.
+    fn start_handshake(&mut self, syn: &TcpHeader, msg: &[u8], cookie: &[u8], addr: SocketAddr) -> Option<Vec<u8>> {
+        if let Some(pending) = self.pending.as_ref().filter(|pending| {
+            pending.addr == addr && pending.connection_id == syn.connection_id && pending.cookie == cookie
+        }) {
+            return Some(pending.reply.clone());
+        }
+        let mut handshake = Handshake::responder(self.identity.as_ref()?);
+        handshake.read(msg).ok()?;
+        let reply = handshake.write().ok()?;
+        self.pending = Some(PendingHandshake {
+            addr,
+            connection_id: syn.connection_id,
+            cookie: cookie.to_vec(),
+            handshake,
+            reply: reply.clone(),
+        });
+        Some(reply)
+    }
+
+    // Handle a packet received while data is flowing
+    fn handle_data(&mut self, buf: &[u8], addr: SocketAddr) {
+        let header = match TcpHeader::new(buf) {
+            Ok(header) => header,
+            Err(_) => return,
+        };
+        self.log_packet("transport:packet_received", &header, buf.len());
+
+        // Answer to our path challenge, the sender now lives at this address
+        if header.flags == PATH_RESPONSE {
+            self.check_path_response(&header, &buf[HEADER_SIZE..], addr);
+            return;
+        }
+
+        if header.flags == RST {
+            self.handle_reset(&header, &buf[HEADER_SIZE..]);
+            return;
+        }
+
+        // Data of another connection, maybe one we had before this one
+        if header.flags == 24 && header.connection_id != self.connection_id {
+            self.log_dropped("connection_id_mismatch");
+            self.send_reset(&header, addr);
+            return;
+        }
+
+        // A copy of the SYN that was delayed, its sender already has our answer. Unless the SYN set the
+        // connection up: its sender may still wait for our SYN-ACK, and can't go on without it.
+        if header.flags == 2 && header.connection_id == self.connection_id {
+            match self.protection.open_unkeyed(&header, &buf[HEADER_SIZE..]) {
+                Some(payload) if self.resumed => self.answer_syn(&header, &payload, addr),
+                _ => self.log_dropped("duplicate_syn"),
+            }
+            return;
+        }
+
+        // ACK + PSH, ACK, FIN
+        if header.flags != 24 && header.flags != 16 {
+            return;
+        }
+
+        // Check the hash of the header and data, or open them with the connection's keys
+        let data = match self.protection.open(&header, &buf[HEADER_SIZE..]) {
+            Some(data) => data,
+            // The ACK that completed the handshake went out before the keys were known,
+            // a copy of it sent again is still acknowledged
+            None if header.flags == 16
+                && self.protection.open_unkeyed(&header, &buf[HEADER_SIZE..]).is_some() =>
+            {
+                Vec::new()
+            }
+            None => {
+                self.stats.corrupted += 1;
+                self.log_dropped("hash_mismatch");
+                return;
+            }
+        };
+
+        if header.connection_id != self.connection_id {
+            self.log_dropped("connection_id_mismatch");
+            return;
+        }
+
+        // The ports stay those of the handshake, whatever the addresses of the datagrams
+        if (header.source_port, header.destination_port) != self.ports {
+            self.log_dropped("port_mismatch");
+            return;
+        }
+
+        // Anti-replay: a segment more than a window behind the cumulative ACK is not acknowledged,
+        // so a recorded one can't be played back to us once sequence numbers came around
+        let replay_start = SeqNum(self.ack_num.0.wrapping_sub(self.wnd_size as u32));
+        if !header
+            .sequence_number
+            .in_range(replay_start, self.ack_num + self.wnd_size as u32)
+        {
+            self.log_dropped("replayed");
+            return;
         }
-        
-        let mut header = TcpHeader {
+
+        self.check_path(addr);
+        self.last_heard = Some(self.clock.now());
+
+        // A retransmitted handshake ACK or a keepalive probe only needs to be acknowledged again
+        if header.flags == 16 {
+            self.send_ack(0, 0b0001_0000, header.stream_id);
+            return;
+        }
+
+        // Segments behind the cumulative ACK or cached ahead of it were handed over already,
+        // and nothing beyond the window is kept
+        let window_end = self.ack_num + self.wnd_size as u32;
+        let new = header.sequence_number.in_range(self.ack_num, window_end)
+            && !self.cache.contains_key(&header.sequence_number);
+
+        // Out of memory, the segment is dropped and resent once the application caught up
+        if new && self.buffered() + data.len() + HEADER_SIZE > MAX_BUFFERED {
+            self.log_dropped("buffer_full");
+            return;
+        }
+
+        // Segments outside the window are counted too, a well-behaved sender only sends those again
+        if new {
+            self.last_data = Some(self.clock.now());
+        } else {
+            self.stats.duplicates += 1;
+        }
+
+        // Every new segment goes to its stream right away, so a gap in one stream doesn't hold back the others
+        if new {
+            self.streams
+                .entry(header.stream_id)
+                .or_default()
+                .insert(header.stream_seq, header.frame_flags, &data);
+        }
+
+        // An empty message still takes one sequence number, a skip marker the slot it replaces
+        let len = segment_len(header.frame_flags, &data);
+
+        // For out-of-order packets, remember the length so the cumulative ACK can jump over them later.
+        // Duplicates of segments already acknowledged are not kept, they would show up in the SACK blocks.
+        if header.sequence_number != self.ack_num {
+            if new {
+                self.cache.insert(header.sequence_number, len);
+            }
+            self.send_ack(0, 0b0001_0000, header.stream_id);
+        } else {
+            let cached_len = self.check_cache(self.ack_num + len);
+            self.send_ack(len + cached_len, 0b0001_0000, header.stream_id);
+        }
+    }
+
+    // Give up on the connection once the sender reset it. The RST must be for our connection and its ports,
+    // pass the check it can make without the connection's keys, and carry a sequence number in our window.
+    fn handle_reset(&mut self, header: &TcpHeader, data: &[u8]) {
+        let valid = header.connection_id == self.connection_id
+            && (header.source_port, header.destination_port) == self.ports
+            && header.sequence_number.in_range(self.ack_num, self.ack_num + self.wnd_size as u32)
+            && self.protection.open_unkeyed(header, data).is_some();
+        if !valid {
+            self.log_dropped("invalid_reset");
+            return;
+        }
+        warn!("connection reset by the sender");
+        self.set_status(Status::Reset);
+    }
+
+    // Answer a segment that belongs to no connection of ours with a RST
+    fn send_reset(&mut self, segment: &TcpHeader, addr: SocketAddr) {
+        let mut header = segment.reset();
+        let bytes = self.protection.seal_unkeyed(&mut header, &[]);
+        Self::send_data(
+            &addr.ip().to_string(),
+            &addr.port(),
+            &bytes,
+            self.socket.as_ref(),
+            &mut self.stats,
+        );
+        self.log_packet("transport:packet_sent", &header, bytes.len());
+    }
+
+    // Start validating a new address if a packet of our connection came from somewhere else
+    fn check_path(&mut self, addr: SocketAddr) {
+        if addr.ip().to_string() == self.remote_host && addr.port() == self.remote_port {
+            return;
+        }
+
+        // Don't flood the new address, wait for the answer of the previous challenge first
+        if let Some(challenge) = &self.path_challenge {
+            if challenge.addr == addr
+                && self.clock.now().duration_since(challenge.timestamp)
+                    < Duration::from_millis(self.rto)
+            {
+                return;
+            }
+        }
+
+        let token: [u8; 8] = self.rng.gen();
+        let mut header = self.new_header(PATH_CHALLENGE, 0);
+        let bytes = self.protection.seal(&mut header, &token);
+        info!(%addr, "validating new path");
+        Self::send_data(
+            &addr.ip().to_string(),
+            &addr.port(),
+            &bytes,
+            self.socket.as_ref(),
+            &mut self.stats,
+        );
+        self.log_packet("transport:packet_sent", &header, bytes.len());
+
+        self.path_challenge = Some(PathChallenge {
+            addr,
+            token,
+            timestamp: self.clock.now(),
+        });
+    }
+
+    // Switch to the new address once the sender echoed our challenge from there
+    fn check_path_response(&mut self, header: &TcpHeader, data: &[u8], addr: SocketAddr) {
+        if header.connection_id != self.connection_id {
+            return;
+        }
+        let token = match self.protection.open(header, data) {
+            Some(token) => token,
+            None => return,
+        };
+
+        if let Some(challenge) = &self.path_challenge {
+            if challenge.addr == addr && challenge.token[..] == token[..] {
+                info!(%addr, "migrated");
+                self.log("connectivity:path_updated", json!({ "new": addr.to_string() }));
+                self.remote_host = addr.ip().to_string();
+                self.remote_port = addr.port();
+                self.path_challenge = None;
+            }
+        }
+    }
+
+    // Retrieve the lengths of cached segments that follow in sequence.
+    // Returns the number of bytes the cumulative ACK can advance over.
+    fn check_cache(&mut self, mut seq_num: SeqNum) -> u32 {
+        let mut len = 0;
+
+        while let Some(tmp) = self.cache.remove(&seq_num) {
+            seq_num += tmp;
+            len += tmp;
+        }
+        // Pieces of a segment the sender cut smaller after the whole of it arrived
+        let ack_num = self.ack_num;
+        self.cache.retain(|seq, _| !seq.in_range(ack_num, seq_num));
+
+        len
+    }
+
+    // Ranges of segments received beyond the cumulative ACK, merged where they touch
+    fn sack_blocks(&self) -> Vec<(SeqNum, SeqNum)> {
+        let mut seqs: Vec<SeqNum> = self.cache.keys().copied().collect();
+        seqs.sort_by_key(|&seq| seq - self.ack_num);
+
+        let mut blocks: Vec<(SeqNum, SeqNum)> = Vec::new();
+        for seq in seqs {
+            let end = seq + self.cache[&seq];
+            if let Some(block) = blocks.last_mut().filter(|block| block.1 == seq) {
+                block.1 = end;
+            } else if blocks.len() < MAX_SACK_BLOCKS {
+                blocks.push((seq, end));
+            } else {
+                break;
+            }
+        }
+        blocks
+    }
+
+    // Build a header for this connection, advertising the credit left on the given stream
+    fn new_header(&self, flags: u8, stream_id: u16) -> TcpHeader {
+        let (window_size, stream_seq) = match self.streams.get(&stream_id) {
+            Some(stream) => (stream.credit(self.wnd_size), stream.expect_seq),
+            None => (self.wnd_size, SeqNum(0)),
+        };
+
+        TcpHeader {
             source_port: self.local_port,
-            destination_port: self.remote_port,
+            destination_port: self.ports.0,
             sequence_number: self.seq_num,
             ack_number: self.ack_num,
             header_length: 4,
+            frame_flags: 0,
             flags,
-            window_size: self.wnd_size,
-            hash_value: [0; 32].into(), // testing
+            window_size,
+            connection_id: self.connection_id,
+            stream_id,
+            stream_seq,
+            hash_value: [0; 32], // testing
+        }
+    }
+
+    // Send ACK back to the sender
+    fn send_ack(&mut self, len: u32, flags: u8, stream_id: u16) {
+        if flags != 1 {
+            self.ack_num += len;
+        }
+
+        let mut header = self.new_header(flags, stream_id);
+        let payload = encode_sack(&self.sack_blocks());
+        // Hash the header and payload, or seal them with the connection's keys
+        let bytes = self.protection.seal(&mut header, &payload);
+
+        Self::send_data(
+            &self.remote_host,
+            &self.remote_port,
+            &bytes,
+            self.socket.as_ref(),
+            &mut self.stats,
+        );
+        self.log_packet("transport:packet_sent", &header, bytes.len());
+        self.acks_sent += 1;
+        self.seq_num += 1;
+    }
+
+    // Change status, the event log keeps track of the transitions
+    fn set_status(&mut self, status: Status) {
+        let old = format!("{:?}", self.status);
+        self.status = status;
+        let new = format!("{:?}", self.status);
+        self.state_span = match self.status {
+            Status::StandBy => info_span!(parent: &self.span, "StandBy"),
+            Status::Sending => info_span!(parent: &self.span, "Sending"),
+            Status::Reset => info_span!(parent: &self.span, "Reset"),
+            Status::TimedOut => info_span!(parent: &self.span, "TimedOut"),
         };
+        self.state_span.in_scope(|| info!(from = %old, "status changed"));
+        self.log(
+            "connectivity:connection_state_updated",
+            json!({ "old": old, "new": new }),
+        );
+    }
+
+    // Record an event, if the connection is traced
+    fn log(&mut self, name: &str, data: Value) {
+        if let Some(qlog) = self.qlog.as_mut() {
+            qlog.event(self.clock.now(), self.connection_id, name, data);
+        }
+    }
 
-        // Get the hash value of the header
-        header.hash_value = header.calculate_header_hash();
-        let bytes = header.as_bytes();
+    // Record a packet sent or received
+    fn log_packet(&mut self, name: &str, header: &TcpHeader, len: usize) {
+        if self.qlog.is_some() {
+            self.log(name, json!({ "header": packet_header(header), "length": len }));
+        }
+    }
 
-        Self::send_data(&self.remote_host, &self.remote_port, &bytes, &self.socket);
-        self.seq_num = safe_increment(self.seq_num, 1);
+    // Record a packet that was thrown away
+    fn log_dropped(&mut self, trigger: &str) {
+        debug!(trigger, "packet dropped");
+        self.log("transport:packet_dropped", json!({ "trigger": trigger }));
     }
 
     // Helper function to send data to the sender
-    fn send_data(remote_host: &str, remote_port: &u16, packet_data: &[u8], socket: &UdpSocket) {
+    fn send_data(
+        remote_host: &str,
+        remote_port: &u16,
+        packet_data: &[u8],
+        socket: &dyn Transport,
+        stats: &mut Stats,
+    ) {
         loop {
-            match socket.send_to(packet_data, format!("{}:{}", remote_host, remote_port)) {
+            match socket.send_to(packet_data, &format!("{}:{}", remote_host, remote_port)) {
                 Ok(_) => {
+                    stats.sent(packet_data.len());
                     break;
                 }
                 Err(e) => {
-                    eprintln!("{} -> Failed to send packet at registration", e)
+                    warn!("{} -> Failed to send packet at registration", e)
                 }
             }
         }
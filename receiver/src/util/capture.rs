@@ -0,0 +1,215 @@
+use std::fs::File;
+use std::io::{self, Write};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, ToSocketAddrs};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+use crate::util::transport::{Clock, Transport};
+
+// pcapng block types
+const SECTION_HEADER: u32 = 0x0A0D_0D0A;
+const INTERFACE_DESCRIPTION: u32 = 0x0000_0001;
+const ENHANCED_PACKET: u32 = 0x0000_0006;
+const BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+
+// Raw IP packets, the version nibble tells IPv4 from IPv6
+const LINKTYPE_RAW: u16 = 101;
+
+// epb_flags option, its lowest two bits give the direction of the packet
+const EPB_FLAGS: u16 = 2;
+pub const INBOUND: u32 = 0b01;
+pub const OUTBOUND: u32 = 0b10;
+
+const UDP: u8 = 17;
+
+// Transport that writes every datagram it sends and receives to a pcapng file,
+// wrapped in made-up IP and UDP headers so standard tools can read it
+#[derive(Debug)]
+pub struct Capture {
+    inner: Box<dyn Transport>,
+    file: Mutex<File>,
+    clock: Arc<dyn Clock>,
+    start: (Instant, SystemTime), // Clock reading when the capture started and the wall time it stands for
+    local: SocketAddr,
+}
+
+impl Capture {
+    // Start capturing the datagrams of a transport, timestamps follow the given clock
+    pub fn new(inner: Box<dyn Transport>, path: &Path, clock: Arc<dyn Clock>) -> Result<Self, String> {
+        let local = inner
+            .local_addr()
+            .map_err(|e| format!("{e} -> Failed to get local port"))?;
+        let mut file = File::create(path)
+            .map_err(|e| format!("{e} -> Failed to create {}", path.display()))?;
+        file.write_all(&section_header())
+            .and_then(|_| file.write_all(&interface_description()))
+            .map_err(|e| format!("{e} -> Failed to write {}", path.display()))?;
+
+        Ok(Capture {
+            inner,
+            file: Mutex::new(file),
+            start: (clock.now(), SystemTime::now()),
+            clock,
+            local,
+        })
+    }
+
+    // Append a datagram, a capture that can't be written must not stop the transfer
+    fn record(&self, datagram: &[u8], src: SocketAddr, dst: SocketAddr, direction: u32) {
+        let elapsed = self.clock.now().saturating_duration_since(self.start.0);
+        let micros = (self.start.1 + elapsed)
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_micros() as u64);
+
+        let block = enhanced_packet(micros, &ip_packet(datagram, src, dst), direction);
+        if let Err(e) = self.file.lock().unwrap().write_all(&block) {
+            warn!("{e} -> Failed to write capture");
+        }
+    }
+}
+
+impl Transport for Capture {
+    fn send_to(&self, buf: &[u8], addr: &str) -> io::Result<usize> {
+        let sent = self.inner.send_to(buf, addr)?;
+        // Remote addresses are given as text, they are normally numeric already
+        let remote = addr
+            .parse()
+            .ok()
+            .or_else(|| addr.to_socket_addrs().ok()?.next())
+            .unwrap_or_else(|| SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0));
+        self.record(&buf[..sent], self.local, remote, OUTBOUND);
+        Ok(sent)
+    }
+
+    fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        let (len, addr) = self.inner.recv_from(buf)?;
+        self.record(&buf[..len], addr, self.local, INBOUND);
+        Ok((len, addr))
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.local)
+    }
+}
+
+// Block with the given body, padded to 32 bits and framed by its total length
+fn block(block_type: u32, body: &[u8]) -> Vec<u8> {
+    let padded = (body.len() + 3) & !3;
+    let total = (12 + padded) as u32;
+
+    let mut res = Vec::with_capacity(total as usize);
+    res.extend_from_slice(&block_type.to_le_bytes());
+    res.extend_from_slice(&total.to_le_bytes());
+    res.extend_from_slice(body);
+    res.resize(8 + padded, 0);
+    res.extend_from_slice(&total.to_le_bytes());
+    res
+}
+
+// Opens the file, the section length is left unknown so blocks can be appended as they come
+fn section_header() -> Vec<u8> {
+    let mut body = Vec::new();
+    body.extend_from_slice(&BYTE_ORDER_MAGIC.to_le_bytes());
+    body.extend_from_slice(&1u16.to_le_bytes()); // Major version
+    body.extend_from_slice(&0u16.to_le_bytes()); // Minor version
+    body.extend_from_slice(&(-1i64).to_le_bytes());
+    block(SECTION_HEADER, &body)
+}
+
+// The only interface of the file, timestamps are in microseconds (the default resolution)
+fn interface_description() -> Vec<u8> {
+    let mut body = Vec::new();
+    body.extend_from_slice(&LINKTYPE_RAW.to_le_bytes());
+    body.extend_from_slice(&0u16.to_le_bytes()); // Reserved
+    body.extend_from_slice(&0u32.to_le_bytes()); // No snapshot length limit
+    block(INTERFACE_DESCRIPTION, &body)
+}
+
+// One captured packet with its direction
+fn enhanced_packet(micros: u64, packet: &[u8], direction: u32) -> Vec<u8> {
+    let mut body = Vec::new();
+    body.extend_from_slice(&0u32.to_le_bytes()); // Interface ID
+    body.extend_from_slice(&((micros >> 32) as u32).to_le_bytes());
+    body.extend_from_slice(&(micros as u32).to_le_bytes());
+    body.extend_from_slice(&(packet.len() as u32).to_le_bytes()); // Captured length
+    body.extend_from_slice(&(packet.len() as u32).to_le_bytes()); // Original length
+    body.extend_from_slice(packet);
+    body.resize((body.len() + 3) & !3, 0);
+
+    body.extend_from_slice(&EPB_FLAGS.to_le_bytes());
+    body.extend_from_slice(&4u16.to_le_bytes());
+    body.extend_from_slice(&direction.to_le_bytes());
+    body.extend_from_slice(&[0; 4]); // End of options
+    block(ENHANCED_PACKET, &body)
+}
+
+// Wrap a datagram in UDP and IP headers, IPv4 unless one of the ends is IPv6
+fn ip_packet(datagram: &[u8], src: SocketAddr, dst: SocketAddr) -> Vec<u8> {
+    let udp_len = (8 + datagram.len()) as u16;
+    let mut udp = Vec::with_capacity(udp_len as usize);
+    udp.extend_from_slice(&src.port().to_be_bytes());
+    udp.extend_from_slice(&dst.port().to_be_bytes());
+    udp.extend_from_slice(&udp_len.to_be_bytes());
+    udp.extend_from_slice(&[0, 0]); // Checksum, filled in below
+    udp.extend_from_slice(datagram);
+
+    let mut res = Vec::with_capacity(40 + udp.len());
+    let mut pseudo = Vec::with_capacity(36);
+    match (src.ip(), dst.ip()) {
+        (IpAddr::V4(src), IpAddr::V4(dst)) => {
+            res.extend_from_slice(&[0x45, 0]); // Version and header length, DSCP
+            res.extend_from_slice(&(20 + udp_len).to_be_bytes());
+            res.extend_from_slice(&[0, 0, 0x40, 0]); // ID, don't fragment
+            res.extend_from_slice(&[64, UDP, 0, 0]); // TTL, protocol, checksum
+            res.extend_from_slice(&src.octets());
+            res.extend_from_slice(&dst.octets());
+            let checksum = internet_checksum(&res).to_be_bytes();
+            res[10..12].copy_from_slice(&checksum);
+
+            pseudo.extend_from_slice(&src.octets());
+            pseudo.extend_from_slice(&dst.octets());
+            pseudo.extend_from_slice(&[0, UDP]);
+            pseudo.extend_from_slice(&udp_len.to_be_bytes());
+        }
+        (src, dst) => {
+            let v6 = |ip: IpAddr| match ip {
+                IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+                IpAddr::V6(ip) => ip,
+            };
+            res.extend_from_slice(&[0x60, 0, 0, 0]); // Version, traffic class and flow label
+            res.extend_from_slice(&udp_len.to_be_bytes());
+            res.extend_from_slice(&[UDP, 64]); // Next header, hop limit
+            res.extend_from_slice(&v6(src).octets());
+            res.extend_from_slice(&v6(dst).octets());
+
+            pseudo.extend_from_slice(&v6(src).octets());
+            pseudo.extend_from_slice(&v6(dst).octets());
+            pseudo.extend_from_slice(&(udp_len as u32).to_be_bytes());
+            pseudo.extend_from_slice(&[0, 0, 0, UDP]);
+        }
+    }
+
+    pseudo.extend_from_slice(&udp);
+    // A computed checksum of 0 is sent as all ones, 0 means no checksum
+    let checksum = match internet_checksum(&pseudo) {
+        0 => 0xFFFF,
+        sum => sum,
+    };
+    udp[6..8].copy_from_slice(&checksum.to_be_bytes());
+    res.extend_from_slice(&udp);
+    res
+}
+
+// One's complement of the one's complement sum of 16-bit words (RFC 1071)
+fn internet_checksum(data: &[u8]) -> u16 {
+    let mut sum: u32 = data
+        .chunks(2)
+        .map(|ch| u16::from_be_bytes([ch[0], *ch.get(1).unwrap_or(&0)]) as u32)
+        .sum();
+    while sum > 0xFFFF {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    !(sum as u16)
+}
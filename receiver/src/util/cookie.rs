@@ -0,0 +1,95 @@
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+
+use crate::util::crypto::COOKIE_SIZE;
+use crate::util::seq::SeqNum;
+
+// A cookie is good for the window it was issued in and the next one
+const WINDOW: Duration = Duration::from_secs(30);
+
+// Stateless address validation: the SYN-ACK carries a cookie only we can make, and the connection
+// is only set up once the ACK that completes the handshake echoes it from the same address
+pub struct Cookies {
+    secret: [u8; 32],
+    start: Instant, // Windows are counted from here
+}
+
+impl Cookies {
+    pub fn new(secret: [u8; 32], start: Instant) -> Self {
+        Cookies { secret, start }
+    }
+
+    // Cookie for a SYN from this address, with the key material it carried
+    pub fn issue(
+        &self,
+        now: Instant,
+        addr: SocketAddr,
+        connection_id: u64,
+        seq: SeqNum,
+        material: &[u8],
+    ) -> [u8; COOKIE_SIZE] {
+        let tag = self.mac(self.window(now), addr, connection_id, seq, material).finalize();
+        tag.into_bytes()[..COOKIE_SIZE].try_into().unwrap()
+    }
+
+    // Whether the cookie was issued for this SYN recently, compared in constant time
+    pub fn check(
+        &self,
+        now: Instant,
+        cookie: &[u8],
+        addr: SocketAddr,
+        connection_id: u64,
+        seq: SeqNum,
+        material: &[u8],
+    ) -> bool {
+        let window = self.window(now);
+        [Some(window), window.checked_sub(1)].into_iter().flatten().any(|window| {
+            self.mac(window, addr, connection_id, seq, material)
+                .verify_truncated_left(cookie)
+                .is_ok()
+        })
+    }
+
+    // Secret tied to a cookie, so what the SYN-ACK carries can be made again when the cookie comes back
+    pub fn derive(&self, cookie: &[u8], label: &[u8]) -> [u8; 32] {
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&self.secret).unwrap();
+        mac.update(label);
+        mac.update(cookie);
+        mac.finalize().into_bytes().into()
+    }
+
+    fn window(&self, now: Instant) -> u64 {
+        now.saturating_duration_since(self.start).as_secs() / WINDOW.as_secs()
+    }
+
+    fn mac(
+        &self,
+        window: u64,
+        addr: SocketAddr,
+        connection_id: u64,
+        seq: SeqNum,
+        material: &[u8],
+    ) -> Hmac<Sha256> {
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&self.secret).unwrap();
+        mac.update(&window.to_be_bytes());
+        let ip = match addr.ip() {
+            IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+            IpAddr::V6(ip) => ip,
+        };
+        mac.update(&ip.octets());
+        mac.update(&addr.port().to_be_bytes());
+        mac.update(&connection_id.to_be_bytes());
+        mac.update(&seq.0.to_be_bytes());
+        mac.update(material);
+        mac
+    }
+}
+
+impl fmt::Debug for Cookies {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Cookies(..)")
+    }
+}
@@ -0,0 +1,257 @@
+use chacha20poly1305::aead::{AeadInPlace, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce, Tag};
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::util::tcp_header::{TcpHeader, HEADER_SIZE, PATH_CHALLENGE, PATH_RESPONSE};
+
+// Random value each end puts in its SYN or SYN-ACK, both go into the keys of the connection
+pub const RANDOM_SIZE: usize = 32;
+// Shortest pre-shared key accepted
+pub const MIN_PSK_SIZE: usize = 16;
+// Address validation cookie at the start of the SYN-ACK's payload, echoed by the ACK that completes the handshake
+pub const COOKIE_SIZE: usize = 16;
+// Resumption token a receiver hands out in its SYN-ACK, a later SYN brings it back to send data right behind it
+pub const TOKEN_SIZE: usize = 24;
+
+const SYN: u8 = 0b0000_0010;
+const TAG_SIZE: usize = 16;
+// Header bytes before the hash, authenticated along with every packet
+const AAD_SIZE: usize = HEADER_SIZE - 32;
+
+// Key both ends were configured with, it never leaves this struct
+#[derive(Clone)]
+pub struct Psk {
+    secret: Vec<u8>,
+    mac_key: [u8; 32], // Authenticates the packets that carry no connection keys yet
+}
+
+impl Psk {
+    pub fn new(secret: &[u8]) -> Result<Self, String> {
+        if secret.len() < MIN_PSK_SIZE {
+            return Err(format!(
+                "The pre-shared key must be at least {MIN_PSK_SIZE} bytes, got {}",
+                secret.len()
+            ));
+        }
+        let mut mac_key = [0; 32];
+        Hkdf::<Sha256>::new(None, secret)
+            .expand(b"transport handshake", &mut mac_key)
+            .unwrap();
+        Ok(Psk {
+            secret: secret.to_vec(),
+            mac_key,
+        })
+    }
+
+    // Read the key from a file, surrounding whitespace is not part of it
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let data = fs::read(path)
+            .map_err(|e| format!("{e} -> Failed to read pre-shared key {}", path.display()))?;
+        let start = data.iter().position(|b| !b.is_ascii_whitespace()).unwrap_or(data.len());
+        let end = data.iter().rposition(|b| !b.is_ascii_whitespace()).map_or(start, |i| i + 1);
+        Self::new(&data[start..end])
+    }
+
+}
+
+impl fmt::Debug for Psk {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Psk(..)")
+    }
+}
+
+// AEAD keys of a connection, one per direction
+#[derive(Clone)]
+pub struct Keys {
+    seal: ChaCha20Poly1305,
+    open: ChaCha20Poly1305,
+    mac_key: [u8; 32], // Authenticates path validation, which stays readable
+}
+
+impl Keys {
+    // Keys agreed on some other way, such as a Noise handshake
+    pub fn new(seal: &[u8; 32], open: &[u8; 32], mac_key: [u8; 32]) -> Self {
+        Keys {
+            seal: ChaCha20Poly1305::new(Key::from_slice(seal)),
+            open: ChaCha20Poly1305::new(Key::from_slice(open)),
+            mac_key,
+        }
+    }
+
+    // Derive the keys from the pre-shared key and the randoms of the SYN and the SYN-ACK
+    pub fn derive(psk: &Psk, client_random: &[u8], server_random: &[u8], client: bool) -> Self {
+        let salt = [client_random, server_random].concat();
+        let hkdf = Hkdf::<Sha256>::new(Some(&salt), &psk.secret);
+        let key = |info: &[u8]| {
+            let mut key = Key::default();
+            hkdf.expand(info, &mut key).unwrap();
+            ChaCha20Poly1305::new(&key)
+        };
+        let (to_server, to_client) = (key(b"transport client to server"), key(b"transport server to client"));
+        let (seal, open) = match client {
+            true => (to_server, to_client),
+            false => (to_client, to_server),
+        };
+        Keys { seal, open, mac_key: psk.mac_key }
+    }
+}
+
+impl fmt::Debug for Keys {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Keys(..)")
+    }
+}
+
+// How the packets of a connection are protected: an unkeyed hash on the handshake and a MAC under
+// the session key after it by default, with a pre-shared key a MAC on the handshake and path validation,
+// and everything else sealed once the keys are derived. Keys from a Noise handshake seal the same
+// packets, the handshake itself protects its messages.
+#[derive(Debug, Default)]
+pub struct Protection {
+    pub psk: Option<Psk>,
+    pub keys: Option<Keys>,
+    pub session: Option<[u8; 32]>,
+}
+
+impl Protection {
+    // Fill in the hash field of the header, returns the packet with its payload, encrypted if sealed
+    pub fn seal(&self, header: &mut TcpHeader, data: &[u8]) -> Vec<u8> {
+        match self.mode(header) {
+            Mode::Hash => header.hash_value = header.calculate_header_data_hash(data),
+            Mode::Mac(key) => header.hash_value = mac(key, &aad(header), data).finalize().into_bytes().into(),
+            Mode::Aead(keys) => {
+                let mut sealed = data.to_vec();
+                let tag = keys
+                    .seal
+                    .encrypt_in_place_detached(&nonce(header, data.len()), &aad(header), &mut sealed)
+                    .unwrap();
+                header.hash_value = [0; 32];
+                header.hash_value[..TAG_SIZE].copy_from_slice(&tag);
+                let mut packet = header.as_bytes();
+                packet.extend_from_slice(&sealed);
+                return packet;
+            }
+        }
+        let mut packet = header.as_bytes();
+        packet.extend_from_slice(data);
+        packet
+    }
+
+    // Check a packet and return its payload in the clear, None if it was tampered with or isn't ours
+    pub fn open(&self, header: &TcpHeader, data: &[u8]) -> Option<Vec<u8>> {
+        let valid = match self.mode(header) {
+            Mode::Hash => header.calculate_header_data_hash(data) == header.hash_value,
+            // In constant time, so a forger learns nothing from how long the check takes
+            Mode::Mac(key) => mac(key, &aad(header), data).verify_slice(&header.hash_value).is_ok(),
+            Mode::Aead(keys) => {
+                // The rest of the hash field is unused, it must not carry anything either
+                if header.hash_value[TAG_SIZE..].iter().any(|&b| b != 0) {
+                    return None;
+                }
+                let mut opened = data.to_vec();
+                let tag = Tag::from_slice(&header.hash_value[..TAG_SIZE]);
+                return keys
+                    .open
+                    .decrypt_in_place_detached(&nonce(header, data.len()), &aad(header), &mut opened, tag)
+                    .ok()
+                    .map(|_| opened);
+            }
+        };
+        valid.then(|| data.to_vec())
+    }
+
+    // Open a packet we sealed ourselves, as a segment in flight that gets cut smaller
+    pub fn open_own(&self, header: &TcpHeader, data: &[u8]) -> Option<Vec<u8>> {
+        match &self.keys {
+            Some(keys) if matches!(self.mode(header), Mode::Aead(_)) => {
+                let own = Protection {
+                    psk: None,
+                    keys: Some(Keys {
+                        seal: keys.open.clone(),
+                        open: keys.seal.clone(),
+                        mac_key: keys.mac_key,
+                    }),
+                    session: None,
+                };
+                own.open(header, data)
+            }
+            _ => self.open(header, data),
+        }
+    }
+
+    // Seal a packet for an end that may not know the keys of the connection, as a RST
+    pub fn seal_unkeyed(&self, header: &mut TcpHeader, data: &[u8]) -> Vec<u8> {
+        self.unkeyed().seal(header, data)
+    }
+
+    // Check a packet sent before the keys of the connection were known, as the ACK that completes the handshake
+    pub fn open_unkeyed(&self, header: &TcpHeader, data: &[u8]) -> Option<Vec<u8>> {
+        self.unkeyed().open(header, data)
+    }
+
+    // Only the pre-shared key, which outlives connections
+    fn unkeyed(&self) -> Protection {
+        Protection {
+            psk: self.psk.clone(),
+            keys: None,
+            session: None,
+        }
+    }
+
+    fn mode(&self, header: &TcpHeader) -> Mode<'_> {
+        let handshake = header.flags & (SYN | PATH_CHALLENGE | PATH_RESPONSE) != 0;
+        match (&self.psk, &self.keys, &self.session) {
+            (_, Some(keys), _) if !handshake => Mode::Aead(keys),
+            (_, Some(keys), _) if header.flags & SYN == 0 => Mode::Mac(&keys.mac_key),
+            (_, None, Some(session)) if header.flags & SYN == 0 => Mode::Mac(session),
+            (Some(psk), _, _) => Mode::Mac(&psk.mac_key),
+            (None, _, _) => Mode::Hash,
+        }
+    }
+}
+
+enum Mode<'a> {
+    Hash,
+    Mac(&'a [u8; 32]),
+    Aead(&'a Keys),
+}
+
+// Key of a connection with neither a pre-shared key nor Noise, from the cookie both ends saw in the handshake.
+// Anyone on the path knows it too, but a sender that didn't see the handshake can't forge a packet.
+pub fn session_key(cookie: &[u8], connection_id: u64) -> [u8; 32] {
+    let mut key = [0; 32];
+    Hkdf::<Sha256>::new(Some(&connection_id.to_be_bytes()), cookie)
+        .expand(b"transport session", &mut key)
+        .unwrap();
+    key
+}
+
+fn mac(key: &[u8; 32], header: &[u8], data: &[u8]) -> Hmac<Sha256> {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key).unwrap();
+    mac.update(header);
+    mac.update(data);
+    mac
+}
+
+fn aad(header: &TcpHeader) -> Vec<u8> {
+    header.as_bytes()[..AAD_SIZE].to_vec()
+}
+
+// The sequence number tells the packets of one direction apart, with the flags so that a skip marker
+// doesn't share a nonce with the segment it replaces, nor a keepalive probe with the handshake ACK, and the
+// stream sequence number so that keepalive probes at the same sequence number don't either. The length
+// tells a segment apart from its first piece once it is cut smaller. Resent packets are resent byte for byte.
+fn nonce(header: &TcpHeader, len: usize) -> Nonce {
+    let mut nonce = Nonce::default();
+    nonce[0] = header.flags;
+    nonce[1] = header.frame_flags;
+    nonce[2..4].copy_from_slice(&(len as u16).to_be_bytes());
+    nonce[4..8].copy_from_slice(&header.stream_seq.0.to_be_bytes());
+    nonce[8..].copy_from_slice(&header.sequence_number.0.to_be_bytes());
+    nonce
+}
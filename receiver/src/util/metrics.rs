@@ -0,0 +1,119 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+// A scraper that doesn't send its request in time is dropped, the receiver can't wait on it
+const SCRAPE_TIMEOUT: Duration = Duration::from_millis(200);
+
+// Where the metrics go: served to scrapers over HTTP, or written to a file for node_exporter's textfile collector
+#[derive(Debug)]
+pub enum Exporter {
+    Http(TcpListener),
+    Textfile {
+        path: PathBuf,
+        interval: Duration,
+        written: Option<Instant>, // Last time the file was rewritten
+    },
+}
+
+impl Exporter {
+    // Serve the metrics on the given port of localhost, 0 picks a free one
+    pub fn http(port: u16) -> Result<Self, String> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))
+            .map_err(|e| format!("{e} -> Failed to bind to 127.0.0.1:{port}"))?;
+        listener
+            .set_nonblocking(true)
+            .map_err(|e| format!("{e} -> Failed to switch to non-blocking mode"))?;
+        Ok(Exporter::Http(listener))
+    }
+
+    // Rewrite the given file with the metrics at every interval
+    pub fn textfile(path: PathBuf, interval: Duration) -> Self {
+        Exporter::Textfile {
+            path,
+            interval,
+            written: None,
+        }
+    }
+
+    // Address scrapers connect to, if serving over HTTP
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        match self {
+            Exporter::Http(listener) => listener.local_addr().ok(),
+            Exporter::Textfile { .. } => None,
+        }
+    }
+
+    // Answer waiting scrapers or rewrite the file if it's due, the metrics are only rendered when needed
+    pub fn poll(&mut self, now: Instant, render: impl Fn() -> String) {
+        match self {
+            Exporter::Http(listener) => {
+                while let Ok((stream, addr)) = listener.accept() {
+                    if let Err(e) = serve(stream, &render) {
+                        debug!(%addr, "{e} -> Failed to serve metrics");
+                    }
+                }
+            }
+            Exporter::Textfile {
+                path,
+                interval,
+                written,
+            } => {
+                if written.is_some_and(|at| now.saturating_duration_since(at) < *interval) {
+                    return;
+                }
+                *written = Some(now);
+                // Readers never see a half-written file
+                let tmp = path.with_extension("tmp");
+                if let Err(e) = fs::write(&tmp, render()).and_then(|_| fs::rename(&tmp, &*path)) {
+                    warn!("{e} -> Failed to write metrics to {}", path.display());
+                }
+            }
+        }
+    }
+}
+
+// Answer one HTTP request, every path but /metrics is not found
+fn serve(mut stream: TcpStream, render: &impl Fn() -> String) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(SCRAPE_TIMEOUT))?;
+    stream.set_write_timeout(Some(SCRAPE_TIMEOUT))?;
+
+    // Only the request line matters, the rest of the head is read and ignored
+    let mut request = Vec::new();
+    let mut buf = [0; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") && request.len() < 8192 {
+        let len = stream.read(&mut buf)?;
+        if len == 0 {
+            break;
+        }
+        request.extend_from_slice(&buf[..len]);
+    }
+    let request = String::from_utf8_lossy(&request);
+    let mut words = request.split_whitespace();
+    let (method, target) = (words.next(), words.next());
+
+    let (status, body) = match (method, target) {
+        (Some("GET"), Some("/metrics")) => ("200 OK", render()),
+        (Some("GET"), _) => ("404 Not Found", "Not found\n".to_string()),
+        _ => ("405 Method Not Allowed", "Only GET is supported\n".to_string()),
+    };
+    let head = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: text/plain; version=0.0.4\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    );
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(body.as_bytes())
+}
+
+// Append a metric in the Prometheus text format
+pub fn metric(out: &mut String, name: &str, kind: &str, help: &str, value: u64) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+    let _ = writeln!(out, "{name} {value}");
+}
@@ -1,2 +1,13 @@
+pub mod capture;
+pub mod cookie;
+pub mod crypto;
+pub mod metrics;
+pub mod noise;
+pub mod qlog;
+pub mod seq;
+pub mod stats;
 pub mod tcp_header;
-pub mod util;
\ No newline at end of file
+pub mod token;
+pub mod transport;
+#[allow(clippy::module_inception)]
+pub mod util;
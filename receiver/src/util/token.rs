@@ -0,0 +1,86 @@
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::fmt;
+use std::fs;
+use std::net::IpAddr;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::util::crypto::{MIN_PSK_SIZE, TOKEN_SIZE};
+
+// A token is taken back for this long after it was handed out
+const LIFETIME: Duration = Duration::from_secs(24 * 60 * 60);
+// Seconds since the epoch when the token was handed out, then the MAC
+const STAMP_SIZE: usize = 8;
+
+// Resumption tokens: the SYN-ACK hands the sender one for its address, and a later SYN from there that brings it
+// back shows the sender got our answers at that address before, so the connection is set up on the SYN itself.
+// Tokens carry the wall-clock time they were handed out, receivers sharing the key take each other's.
+#[derive(Clone)]
+pub struct ResumptionKey {
+    key: [u8; 32],
+}
+
+impl ResumptionKey {
+    pub fn new(secret: &[u8]) -> Result<Self, String> {
+        if secret.len() < MIN_PSK_SIZE {
+            return Err(format!(
+                "The resumption key must be at least {MIN_PSK_SIZE} bytes, got {}",
+                secret.len()
+            ));
+        }
+        let mut key = [0; 32];
+        Hkdf::<Sha256>::new(None, secret)
+            .expand(b"transport resumption", &mut key)
+            .unwrap();
+        Ok(ResumptionKey { key })
+    }
+
+    // Read the key from a file, surrounding whitespace is not part of it
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let data = fs::read(path)
+            .map_err(|e| format!("{e} -> Failed to read resumption key {}", path.display()))?;
+        let start = data.iter().position(|b| !b.is_ascii_whitespace()).unwrap_or(data.len());
+        let end = data.iter().rposition(|b| !b.is_ascii_whitespace()).map_or(start, |i| i + 1);
+        Self::new(&data[start..end])
+    }
+
+    // Token for a sender at this address
+    pub fn issue(&self, ip: IpAddr, now: SystemTime) -> [u8; TOKEN_SIZE] {
+        let stamp = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs().to_be_bytes();
+        let tag = self.mac(&stamp, ip).finalize().into_bytes();
+        let mut token = [0; TOKEN_SIZE];
+        token[..STAMP_SIZE].copy_from_slice(&stamp);
+        token[STAMP_SIZE..].copy_from_slice(&tag[..TOKEN_SIZE - STAMP_SIZE]);
+        token
+    }
+
+    // Whether we handed the token out to this address, and not too long ago. The MAC is compared in constant time.
+    pub fn check(&self, token: &[u8], ip: IpAddr, now: SystemTime) -> bool {
+        if token.len() != TOKEN_SIZE {
+            return false;
+        }
+        let (stamp, tag) = token.split_at(STAMP_SIZE);
+        let issued = UNIX_EPOCH + Duration::from_secs(u64::from_be_bytes(stamp.try_into().unwrap()));
+        let fresh = now.duration_since(issued).is_ok_and(|age| age < LIFETIME);
+        fresh && self.mac(stamp, ip).verify_truncated_left(tag).is_ok()
+    }
+
+    fn mac(&self, stamp: &[u8], ip: IpAddr) -> Hmac<Sha256> {
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&self.key).unwrap();
+        mac.update(stamp);
+        let ip = match ip {
+            IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+            IpAddr::V6(ip) => ip,
+        };
+        mac.update(&ip.octets());
+        mac
+    }
+}
+
+impl fmt::Debug for ResumptionKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("ResumptionKey(..)")
+    }
+}
@@ -1,3 +1,6 @@
+use crate::util::seq::SeqNum;
+use crate::util::tcp_header::FORWARD;
+
 // Read in a buffer and return a string up to the first null byte
 pub fn read_to_string(buf: &[u8]) -> String {
     for ind in 0..buf.len() {
@@ -5,19 +8,36 @@ pub fn read_to_string(buf: &[u8]) -> String {
             return String::from_utf8_lossy(&buf[..ind]).to_string();
         }
     }
-    String::from_utf8_lossy(&buf).to_string()
+    String::from_utf8_lossy(buf).to_string()
 }
 
-// Safely increment a sequence number
-pub fn safe_increment(cur_seq: u32, add_bytes: u32) -> u32 {
-    // Calculate how far we are from wrapping around
-    let dst_to_wrap = u32::MAX - cur_seq;
+// Sequence numbers taken by a segment, a skip marker carries the length of the slot it replaces
+pub fn segment_len(frame_flags: u8, data: &[u8]) -> u32 {
+    if frame_flags & FORWARD != 0 && data.len() >= 4 {
+        return u32::from_be_bytes(data[..4].try_into().unwrap());
+    }
+    // An empty segment still takes one sequence number
+    (data.len() as u32).max(1)
+}
 
-    // Overflow
-    if dst_to_wrap < add_bytes {
-        add_bytes - dst_to_wrap
-    } // No overflow 
-    else {
-        cur_seq + add_bytes
+// Serialize SACK blocks, each one is the [start, end) range of sequence numbers received above the cumulative ACK
+pub fn encode_sack(blocks: &[(SeqNum, SeqNum)]) -> Vec<u8> {
+    let mut res = Vec::with_capacity(blocks.len() * 8);
+    for (start, end) in blocks {
+        res.extend_from_slice(&start.0.to_be_bytes());
+        res.extend_from_slice(&end.0.to_be_bytes());
     }
-}
\ No newline at end of file
+    res
+}
+
+// Parse the SACK blocks carried in the payload of an ACK
+pub fn decode_sack(buf: &[u8]) -> Vec<(SeqNum, SeqNum)> {
+    buf.chunks_exact(8)
+        .map(|ch| {
+            (
+                SeqNum(u32::from_be_bytes(ch[..4].try_into().unwrap())),
+                SeqNum(u32::from_be_bytes(ch[4..].try_into().unwrap())),
+            )
+        })
+        .collect()
+}
@@ -0,0 +1,85 @@
+// Helpers shared by the tests, each test file uses some of them
+#![allow(dead_code)]
+
+use std::net::UdpSocket;
+use std::sync::Arc;
+use std::time::Duration;
+
+use receiver::util::crypto::{session_key, Protection};
+use receiver::util::seq::SeqNum;
+use receiver::util::tcp_header::{TcpHeader, HEADER_SIZE};
+use receiver::{Receiver, SystemClock};
+
+// Picked by the tests' sender, with the port its headers carry
+pub const CONNECTION_ID: u64 = 0x5eed;
+pub const PORT: u16 = 4000;
+
+pub fn header(seq: SeqNum, ack: SeqNum, flags: u8, ports: (u16, u16)) -> TcpHeader {
+    TcpHeader {
+        source_port: ports.0,
+        destination_port: ports.1,
+        sequence_number: seq,
+        ack_number: ack,
+        header_length: 4,
+        frame_flags: 0,
+        flags,
+        window_size: 65340,
+        connection_id: CONNECTION_ID,
+        stream_id: 0,
+        stream_seq: SeqNum(0),
+        hash_value: [0; 32],
+    }
+}
+
+// A receiver and the socket of the sender talking to it, which the test plays
+pub fn receiver() -> (Receiver, UdpSocket) {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket.set_nonblocking(true).unwrap();
+    let receiver = Receiver::with_transport(Box::new(socket), Arc::new(SystemClock), 1).unwrap();
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+    client.connect(("127.0.0.1", receiver.local_port())).unwrap();
+    (receiver, client)
+}
+
+// Read everything sent to the receiver, until it stops with an error
+pub fn poll(receiver: &mut Receiver) -> Result<(), String> {
+    (0..100).try_for_each(|_| receiver.poll())
+}
+
+// Everything sent to the receiver so far, and what it handed over on the stream
+pub fn delivered(receiver: &mut Receiver) -> Vec<u8> {
+    poll(receiver).unwrap();
+    receiver.read(0)
+}
+
+// Set up a connection from the client. Returns the first sequence number of its data, the key it seals
+// the data with and the receiver's ACK of the handshake.
+pub fn connect(receiver: &mut Receiver, client: &UdpSocket) -> (SeqNum, Protection, TcpHeader) {
+    let ports = (PORT, receiver.local_port());
+    let unkeyed = Protection::default();
+    let seq = SeqNum(1000);
+    client
+        .send(&unkeyed.seal(&mut header(seq, SeqNum(0), 0b0000_0010, ports), &[]))
+        .unwrap();
+    poll(receiver).unwrap();
+
+    let mut buf = [0; 1500];
+    let len = client.recv(&mut buf).unwrap();
+    let syn_ack = TcpHeader::new(&buf[..len]).unwrap();
+    assert_eq!(syn_ack.destination_port, PORT);
+    // The cookie, then the largest datagram the receiver takes
+    let cookie = buf[HEADER_SIZE..len - 2].to_vec();
+    let mut ack = header(seq + 1, syn_ack.sequence_number + 1, 0b0001_0000, ports);
+    client.send(&unkeyed.seal(&mut ack, &cookie)).unwrap();
+    poll(receiver).unwrap();
+    let len = client.recv(&mut buf).unwrap();
+    let last_ack = TcpHeader::new(&buf[..len]).unwrap();
+
+    let protection = Protection {
+        session: Some(session_key(&cookie, CONNECTION_ID)),
+        ..Protection::default()
+    };
+    // The handshake ACK takes a sequence number
+    (seq + 2, protection, last_ack)
+}
@@ -0,0 +1,66 @@
+use std::net::UdpSocket;
+use std::sync::Arc;
+use std::time::Duration;
+
+use receiver::util::crypto::Protection;
+use receiver::util::seq::SeqNum;
+use receiver::util::tcp_header::{TcpHeader, HEADER_SIZE, MSS};
+use receiver::{Receiver, SystemClock};
+
+mod common;
+use common::header;
+
+fn packet(seq: SeqNum, ack: SeqNum, flags: u8, payload: &[u8]) -> Vec<u8> {
+    Protection::default().seal(&mut header(seq, ack, flags, (0, 0)), payload)
+}
+
+fn peer() -> UdpSocket {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+    socket
+}
+
+// Whether the receiver set up a connection, after reading everything sent to it
+fn connected(receiver: &mut Receiver) -> bool {
+    for _ in 0..100 {
+        receiver.poll().unwrap();
+    }
+    receiver.metrics().contains("transport_receiver_active_connections 1")
+}
+
+#[test]
+fn nothing_is_kept_until_the_cookie_comes_back_from_the_same_address() {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket.set_nonblocking(true).unwrap();
+    let mut receiver = Receiver::with_transport(Box::new(socket), Arc::new(SystemClock), 1).unwrap();
+    let to = ("127.0.0.1", receiver.local_port());
+    let (client, spoofer) = (peer(), peer());
+
+    let seq = SeqNum(1000);
+    client.send_to(&packet(seq, SeqNum(0), 0b0000_0010, &[]), to).unwrap();
+    assert!(!connected(&mut receiver));
+
+    // The SYN-ACK carries the cookie, then the largest datagram the receiver takes
+    let mut buf = [0; 1500];
+    let len = client.recv(&mut buf).unwrap();
+    let syn_ack = TcpHeader::new(&buf[..len]).unwrap();
+    assert_eq!(syn_ack.ack_number, seq + 1);
+    assert_eq!(syn_ack.frame_flags, MSS);
+    let (cookie, mss) = buf[HEADER_SIZE..len].split_at(16);
+    assert_eq!(mss, 1500u16.to_be_bytes());
+    let cookie = cookie.to_vec();
+    let ack = |cookie: &[u8]| packet(seq + 1, syn_ack.sequence_number + 1, 0b0001_0000, cookie);
+
+    // Echoed from another address
+    spoofer.send_to(&ack(&cookie), to).unwrap();
+    assert!(!connected(&mut receiver));
+
+    // Guessed
+    let mut forged = cookie.clone();
+    forged[0] ^= 1;
+    client.send_to(&ack(&forged), to).unwrap();
+    assert!(!connected(&mut receiver));
+
+    client.send_to(&ack(&cookie), to).unwrap();
+    assert!(connected(&mut receiver));
+}
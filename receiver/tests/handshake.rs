@@ -0,0 +1,51 @@
+use std::time::Duration;
+
+use receiver::util::crypto::{session_key, Protection};
+use receiver::util::seq::SeqNum;
+use receiver::util::tcp_header::{TcpHeader, HEADER_SIZE};
+
+mod common;
+use common::{delivered, header, receiver, CONNECTION_ID, PORT};
+
+#[test]
+fn data_ahead_of_the_handshake_ack_is_dropped_without_an_answer() {
+    let (mut receiver, client) = receiver();
+    client.set_read_timeout(Some(Duration::from_millis(100))).unwrap();
+    let port = receiver.local_port();
+
+    let unkeyed = Protection::default();
+    let seq = SeqNum(1000);
+    let mut syn = header(seq, SeqNum(0), 0b0000_0010, (PORT, port));
+    let syn = unkeyed.seal(&mut syn, &[]);
+    client.send(&syn).unwrap();
+    delivered(&mut receiver);
+    let mut buf = [0; 1500];
+    let len = client.recv(&mut buf).unwrap();
+    let syn_ack = TcpHeader::new(&buf[..len]).unwrap();
+    // The cookie, then the largest datagram the receiver takes
+    let cookie = buf[HEADER_SIZE..len - 2].to_vec();
+
+    // The data overtakes the handshake ACK, the receiver doesn't have the connection yet and keeps nothing
+    let protection = Protection {
+        session: Some(session_key(&cookie, CONNECTION_ID)),
+        ..Protection::default()
+    };
+    let mut data = header(seq + 2, syn_ack.sequence_number + 1, 24, (PORT, port));
+    let data = protection.seal(&mut data, b"early");
+    client.send(&data).unwrap();
+    assert!(delivered(&mut receiver).is_empty());
+    assert!(client.recv(&mut buf).is_err());
+
+    let mut ack = header(seq + 1, syn_ack.sequence_number + 1, 0b0001_0000, (PORT, port));
+    client.send(&unkeyed.seal(&mut ack, &cookie)).unwrap();
+    assert!(delivered(&mut receiver).is_empty());
+    // The sender resends it once it times out
+    client.send(&data).unwrap();
+    assert_eq!(delivered(&mut receiver), b"early");
+
+    // A delayed copy of the SYN is not answered again
+    while client.recv(&mut buf).is_ok() {}
+    client.send(&syn).unwrap();
+    delivered(&mut receiver);
+    assert!(client.recv(&mut buf).is_err());
+}
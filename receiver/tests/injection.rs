@@ -0,0 +1,50 @@
+use receiver::util::crypto::Protection;
+use receiver::util::seq::SeqNum;
+
+mod common;
+use common::{connect, delivered, header, receiver, PORT};
+
+#[test]
+fn only_segments_under_the_session_key_with_the_handshake_ports_are_delivered() {
+    let (mut receiver, client) = receiver();
+    let (seq, protection, _) = connect(&mut receiver, &client);
+    let port = receiver.local_port();
+    let data = b"hello";
+
+    // Someone who didn't see the cookie can only put an unkeyed hash on the segment
+    let unkeyed = Protection::default();
+    client
+        .send(&unkeyed.seal(&mut header(seq, SeqNum(0), 24, (PORT, port)), data))
+        .unwrap();
+    assert!(delivered(&mut receiver).is_empty());
+
+    // Ports other than the handshake's
+    client
+        .send(&protection.seal(&mut header(seq, SeqNum(0), 24, (PORT + 1, port)), data))
+        .unwrap();
+    assert!(delivered(&mut receiver).is_empty());
+
+    client
+        .send(&protection.seal(&mut header(seq, SeqNum(0), 24, (PORT, port)), data))
+        .unwrap();
+    assert_eq!(delivered(&mut receiver), data);
+}
+
+#[test]
+fn segments_more_than_a_window_behind_are_not_acknowledged() {
+    let (mut receiver, client) = receiver();
+    let (seq, protection, _) = connect(&mut receiver, &client);
+    let port = receiver.local_port();
+
+    let mut old = header(SeqNum(seq.0.wrapping_sub(100_000)), SeqNum(0), 24, (PORT, port));
+    client.send(&protection.seal(&mut old, b"replayed")).unwrap();
+    assert!(delivered(&mut receiver).is_empty());
+    let mut buf = [0; 1500];
+    assert!(client.recv(&mut buf).is_err());
+
+    // One just behind the cumulative ACK is a duplicate, acknowledged again
+    let mut duplicate = header(seq + u32::MAX, SeqNum(0), 24, (PORT, port));
+    client.send(&protection.seal(&mut duplicate, b"again")).unwrap();
+    assert!(delivered(&mut receiver).is_empty());
+    assert!(client.recv(&mut buf).is_ok());
+}
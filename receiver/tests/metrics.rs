@@ -0,0 +1,74 @@
+use std::fs;
+use std::io::{Read, Write};
+use std::net::{TcpStream, UdpSocket};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use receiver::{Exporter, Receiver, SystemClock};
+
+fn receiver() -> Receiver {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket.set_nonblocking(true).unwrap();
+    Receiver::with_transport(Box::new(socket), Arc::new(SystemClock), 1).unwrap()
+}
+
+// Value of a sample line, checking the metric is described too
+fn value(text: &str, name: &str) -> u64 {
+    assert!(text.contains(&format!("# TYPE {name} ")), "{name} has no type");
+    text.lines()
+        .find_map(|line| line.strip_prefix(&format!("{name} ")))
+        .unwrap_or_else(|| panic!("{name} missing"))
+        .parse()
+        .unwrap()
+}
+
+#[test]
+fn metrics_are_served_over_http() {
+    let mut receiver = receiver();
+    let exporter = Exporter::http(0).unwrap();
+    let addr = exporter.local_addr().unwrap();
+    receiver.export_metrics(exporter);
+
+    let get = |path: &'static str| {
+        thread::spawn(move || {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            write!(stream, "GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        })
+    };
+    for (path, status) in [("/metrics", "200 OK"), ("/", "404 Not Found")] {
+        let scrape = get(path);
+        while !scrape.is_finished() {
+            receiver.poll().unwrap();
+        }
+        let response = scrape.join().unwrap();
+        assert!(response.starts_with(&format!("HTTP/1.1 {status}\r\n")), "{response}");
+        if status == "200 OK" {
+            let body = response.split("\r\n\r\n").nth(1).unwrap();
+            assert_eq!(body, receiver.metrics());
+        }
+    }
+}
+
+#[test]
+fn metrics_file_is_rewritten() {
+    let path = std::env::temp_dir().join(format!("receiver-metrics-{}.prom", std::process::id()));
+    let mut receiver = receiver();
+    receiver.export_metrics(Exporter::textfile(path.clone(), Duration::from_secs(60)));
+    receiver.poll().unwrap();
+
+    let text = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(value(&text, "transport_receiver_active_connections"), 0);
+    assert_eq!(value(&text, "transport_receiver_delivered_bytes_total"), 0);
+    assert_eq!(value(&text, "transport_receiver_buffered_bytes"), 0);
+    assert_eq!(value(&text, "transport_receiver_hash_mismatch_drops_total"), 0);
+    assert_eq!(value(&text, "transport_receiver_acks_sent_total"), 0);
+
+    // Not due again for a minute
+    receiver.poll().unwrap();
+    assert!(!path.exists());
+}
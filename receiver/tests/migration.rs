@@ -0,0 +1,91 @@
+use std::net::UdpSocket;
+use std::time::Duration;
+
+use receiver::util::crypto::Protection;
+use receiver::util::seq::SeqNum;
+use receiver::util::tcp_header::{TcpHeader, HEADER_SIZE, PATH_CHALLENGE, PATH_RESPONSE};
+use receiver::Receiver;
+
+mod common;
+use common::{connect, delivered, header, receiver, PORT};
+
+// Long enough for a datagram on loopback, well short of the receiver's RTO that spaces its challenges
+const WAIT: Duration = Duration::from_millis(50);
+
+// Another socket of the client, as if its address changed
+fn socket(receiver: &Receiver) -> UdpSocket {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket.set_read_timeout(Some(WAIT)).unwrap();
+    socket.connect(("127.0.0.1", receiver.local_port())).unwrap();
+    socket
+}
+
+// Send the next segment of the stream from the socket and check it's delivered
+fn send(receiver: &mut Receiver, socket: &UdpSocket, protection: &Protection, first: SeqNum, sent: &mut u32) {
+    let ports = (PORT, receiver.local_port());
+    let mut segment = header(first + *sent, SeqNum(0), 24, ports);
+    segment.stream_seq = SeqNum(*sent);
+    socket.send(&protection.seal(&mut segment, b"data")).unwrap();
+    assert_eq!(delivered(receiver), b"data");
+    *sent += 4;
+}
+
+fn response(receiver: &Receiver, protection: &Protection, token: &[u8]) -> Vec<u8> {
+    let ports = (PORT, receiver.local_port());
+    protection.seal(&mut header(SeqNum(0), SeqNum(0), PATH_RESPONSE, ports), token)
+}
+
+// What the receiver sent to the socket: the tokens of its path challenges, and how many other datagrams
+fn drain(socket: &UdpSocket, protection: &Protection) -> (Vec<Vec<u8>>, usize) {
+    let mut buf = [0; 1500];
+    let (mut tokens, mut others) = (Vec::new(), 0);
+    while let Ok(len) = socket.recv(&mut buf) {
+        let header = TcpHeader::new(&buf[..len]).unwrap();
+        match header.flags {
+            PATH_CHALLENGE => tokens.push(protection.open(&header, &buf[HEADER_SIZE..len]).unwrap()),
+            _ => others += 1,
+        }
+    }
+    (tokens, others)
+}
+
+#[test]
+fn only_the_token_of_the_challenge_from_its_address_moves_the_path() {
+    let (mut receiver, client) = receiver();
+    let (first, protection, _) = connect(&mut receiver, &client);
+    let mut sent = 0;
+    client.set_read_timeout(Some(WAIT)).unwrap();
+    let (moved, third) = (socket(&receiver), socket(&receiver));
+
+    // Data from a new address is still acknowledged on the old path, the new one only gets a challenge
+    send(&mut receiver, &moved, &protection, first, &mut sent);
+    assert_eq!(drain(&client, &protection), (vec![], 1));
+    let (tokens, acks) = drain(&moved, &protection);
+    assert_eq!((tokens.len(), acks), (1, 0));
+    let token = &tokens[0];
+
+    // A wrong token, or the right one from another address
+    let mut wrong = token.clone();
+    wrong[0] ^= 1;
+    moved.send(&response(&receiver, &protection, &wrong)).unwrap();
+    client.send(&response(&receiver, &protection, token)).unwrap();
+    send(&mut receiver, &moved, &protection, first, &mut sent);
+    assert_eq!(drain(&client, &protection), (vec![], 1));
+    assert_eq!(drain(&moved, &protection), (vec![], 0));
+
+    // The right one from the new address moves the path
+    let answer = response(&receiver, &protection, token);
+    moved.send(&answer).unwrap();
+    send(&mut receiver, &moved, &protection, first, &mut sent);
+    assert_eq!(drain(&client, &protection), (vec![], 0));
+    assert_eq!(drain(&moved, &protection), (vec![], 1));
+
+    // Played back from yet another address, it doesn't answer the challenge sent there
+    send(&mut receiver, &third, &protection, first, &mut sent);
+    let (tokens, acks) = drain(&third, &protection);
+    assert_eq!((tokens.len(), acks), (1, 0));
+    third.send(&answer).unwrap();
+    send(&mut receiver, &third, &protection, first, &mut sent);
+    assert_eq!(drain(&moved, &protection), (vec![], 2));
+    assert_eq!(drain(&third, &protection), (vec![], 0));
+}
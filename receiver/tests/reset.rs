@@ -0,0 +1,69 @@
+use std::io;
+use std::time::Duration;
+
+use receiver::util::crypto::Protection;
+use receiver::util::seq::SeqNum;
+use receiver::util::tcp_header::{TcpHeader, HEADER_SIZE, RST};
+
+mod common;
+use common::{connect, header, poll, receiver, CONNECTION_ID, PORT};
+
+#[test]
+fn data_of_no_connection_is_dropped_until_one_is_set_up_and_then_gets_a_reset() {
+    let (mut receiver, client) = receiver();
+    client.set_read_timeout(Some(Duration::from_millis(100))).unwrap();
+    let port = receiver.local_port();
+
+    // Its address isn't validated yet, an answer could go to someone else
+    let mut data = header(SeqNum(1000), SeqNum(2000), 24, (PORT, port));
+    data.connection_id = CONNECTION_ID + 1;
+    client.send(&Protection::default().seal(&mut data, b"lost")).unwrap();
+    poll(&mut receiver).unwrap();
+    let mut buf = [0; 1500];
+    assert!(client.recv(&mut buf).is_err());
+
+    connect(&mut receiver, &client);
+    client.send(&Protection::default().seal(&mut data, b"lost")).unwrap();
+    poll(&mut receiver).unwrap();
+    let len = client.recv(&mut buf).unwrap();
+    assert_eq!(len, HEADER_SIZE);
+    let reset = TcpHeader::new(&buf[..len]).unwrap();
+    assert_eq!(reset.as_bytes(), Protection::default().seal(&mut data.reset(), &[]));
+    assert_eq!(reset.flags, RST);
+    assert_eq!((reset.source_port, reset.destination_port), (port, PORT));
+    assert_eq!((reset.sequence_number, reset.ack_number), (SeqNum(2000), SeqNum(1000)));
+    assert_eq!(reset.connection_id, CONNECTION_ID + 1);
+}
+
+#[test]
+fn a_reset_in_the_window_ends_the_connection() {
+    let (mut receiver, client) = receiver();
+    let port = receiver.local_port();
+
+    // Handshake, the ACK that completes it echoes the cookie
+    let (_, protection, last_ack) = connect(&mut receiver, &client);
+    let unkeyed = Protection::default();
+    let mut buf = [0; 1500];
+
+    // Data of another connection is answered, but doesn't touch ours
+    let mut stray = header(SeqNum(1), SeqNum(1), 24, (PORT, port));
+    stray.connection_id = CONNECTION_ID + 1;
+    client.send(&protection.seal(&mut stray, b"stray")).unwrap();
+    poll(&mut receiver).unwrap();
+    let len = client.recv(&mut buf).unwrap();
+    assert_eq!(TcpHeader::new(&buf[..len]).unwrap().flags, RST);
+
+    // Outside the window, or under the session key an end that lost the connection doesn't have
+    let mut outside = last_ack.reset();
+    outside.sequence_number += 100_000;
+    client.send(&unkeyed.seal(&mut outside, &[])).unwrap();
+    client.send(&protection.seal(&mut last_ack.reset(), &[])).unwrap();
+    poll(&mut receiver).unwrap();
+    assert_eq!(receiver.error(), None);
+
+    client.send(&unkeyed.seal(&mut last_ack.reset(), &[])).unwrap();
+    let err = poll(&mut receiver).unwrap_err();
+    assert!(err.contains("reset"), "{err}");
+    assert_eq!(receiver.error(), Some(io::ErrorKind::ConnectionReset));
+    assert!(receiver.metrics().contains("transport_receiver_active_connections 0"));
+}
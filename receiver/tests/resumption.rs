@@ -0,0 +1,82 @@
+use std::net::UdpSocket;
+use std::time::{Duration, SystemTime};
+
+use receiver::util::crypto::{session_key, Protection};
+use receiver::util::seq::SeqNum;
+use receiver::util::tcp_header::{TcpHeader, MSS, RESUMED, TOKEN};
+use receiver::{Receiver, ResumptionKey};
+
+mod common;
+use common::{delivered, header, receiver, CONNECTION_ID, PORT};
+
+const KEY: &[u8] = b"correct horse battery staple";
+
+fn listen(key: &[u8]) -> (Receiver, UdpSocket) {
+    let (mut receiver, client) = receiver();
+    receiver.set_resumption_key(ResumptionKey::new(key).unwrap()).unwrap();
+    client.set_read_timeout(Some(Duration::from_millis(100))).unwrap();
+    (receiver, client)
+}
+
+// Send a SYN bringing the token back with data right behind it, returns the frame flags of the SYN-ACK
+// and what the receiver delivered
+fn resume(receiver: &mut Receiver, client: &UdpSocket, token: &[u8]) -> (u8, Vec<u8>) {
+    let ports = (PORT, receiver.local_port());
+    let seq = SeqNum(1000);
+    let mut syn = header(seq, SeqNum(0), 0b0000_0010, ports);
+    syn.frame_flags = TOKEN;
+    client.send(&Protection::default().seal(&mut syn, token)).unwrap();
+    let protection = Protection {
+        session: Some(session_key(token, CONNECTION_ID)),
+        ..Protection::default()
+    };
+    client
+        .send(&protection.seal(&mut header(seq + 2, SeqNum(0), 24, ports), b"request"))
+        .unwrap();
+    let data = delivered(receiver);
+
+    let mut buf = [0; 1500];
+    let len = client.recv(&mut buf).unwrap();
+    let syn_ack = TcpHeader::new(&buf[..len]).unwrap();
+    assert_eq!(syn_ack.flags, 0b0001_0010);
+    (syn_ack.frame_flags, data)
+}
+
+#[test]
+fn a_token_is_only_taken_back_from_its_address_while_fresh() {
+    let key = ResumptionKey::new(KEY).unwrap();
+    let (ip, now) = ("10.0.0.1".parse().unwrap(), SystemTime::now());
+    let token = key.issue(ip, now);
+    assert!(key.check(&token, ip, now + Duration::from_secs(3600)));
+
+    assert!(!key.check(&token, "10.0.0.2".parse().unwrap(), now));
+    assert!(!key.check(&token, ip, now + Duration::from_secs(25 * 3600)));
+    assert!(!ResumptionKey::new(b"the key of another receiver").unwrap().check(&token, ip, now));
+    let mut forged = token;
+    forged[0] ^= 1;
+    assert!(!key.check(&forged, ip, now));
+}
+
+#[test]
+fn a_syn_with_a_token_sets_the_connection_up_and_can_be_replayed() {
+    let token = ResumptionKey::new(KEY)
+        .unwrap()
+        .issue("127.0.0.1".parse().unwrap(), SystemTime::now());
+
+    let (mut receiver, client) = listen(KEY);
+    let (flags, data) = resume(&mut receiver, &client, &token);
+    assert_eq!(flags, TOKEN | RESUMED | MSS);
+    assert_eq!(data, b"request");
+    assert!(receiver.metrics().contains("transport_receiver_active_connections 1"));
+
+    // The same SYN and data, sent again to a receiver with the same key, are taken again
+    let (mut replayed, client) = listen(KEY);
+    assert_eq!(resume(&mut replayed, &client, &token), (TOKEN | RESUMED | MSS, b"request".to_vec()));
+
+    // A receiver with another key hands out a token of its own, and waits for the handshake ACK without
+    // answering the data
+    let (mut other, client) = listen(b"the key of another receiver");
+    assert_eq!(resume(&mut other, &client, &token), (TOKEN | MSS, Vec::new()));
+    let mut buf = [0; 1500];
+    assert!(client.recv(&mut buf).is_err());
+}
@@ -0,0 +1,40 @@
+use std::io;
+use std::thread;
+use std::time::Duration;
+
+use receiver::util::seq::SeqNum;
+use receiver::util::tcp_header::TcpHeader;
+
+mod common;
+use common::{connect, header, poll, receiver, PORT};
+
+#[test]
+fn a_quiet_sender_times_the_connection_out_unless_it_probes() {
+    let (mut receiver, client) = receiver();
+    receiver.set_idle_timeout(Some(Duration::from_millis(200)));
+    let port = receiver.local_port();
+
+    // Nothing to time out before a connection is set up
+    thread::sleep(Duration::from_millis(300));
+    poll(&mut receiver).unwrap();
+
+    let (seq, protection, _) = connect(&mut receiver, &client);
+
+    // Keepalive probes, each answered, keep the connection open past the timeout
+    let mut buf = [0; 1500];
+    for probe in 1..=3 {
+        thread::sleep(Duration::from_millis(100));
+        let mut keepalive = header(seq, SeqNum(0), 0b0001_0000, (PORT, port));
+        keepalive.stream_seq = SeqNum(probe);
+        client.send(&protection.seal(&mut keepalive, &[])).unwrap();
+        poll(&mut receiver).unwrap();
+        let len = client.recv(&mut buf).unwrap();
+        assert_eq!(TcpHeader::new(&buf[..len]).unwrap().flags, 0b0001_0000);
+    }
+
+    thread::sleep(Duration::from_millis(300));
+    let err = poll(&mut receiver).unwrap_err();
+    assert!(err.contains("timed out"), "{err}");
+    assert_eq!(receiver.error(), Some(io::ErrorKind::TimedOut));
+    assert!(receiver.metrics().contains("transport_receiver_active_connections 0"));
+}
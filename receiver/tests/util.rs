@@ -0,0 +1,17 @@
+// The receiver keeps its own copy of some of the sender's util modules. Their tests live with the sender,
+// in sender/tests, and hold here as long as the copies stay the same.
+#[test]
+fn util_modules_are_copies_of_the_senders() {
+    let copies = [
+        ("seq.rs", include_str!("../src/util/seq.rs"), include_str!("../../sender/src/util/seq.rs")),
+        ("capture.rs", include_str!("../src/util/capture.rs"), include_str!("../../sender/src/util/capture.rs")),
+        (
+            "tcp_header.rs",
+            include_str!("../src/util/tcp_header.rs"),
+            include_str!("../../sender/src/util/tcp_header.rs"),
+        ),
+    ];
+    for (name, ours, senders) in copies {
+        assert!(ours == senders, "receiver/src/util/{name} differs from the sender's");
+    }
+}
@@ -0,0 +1,9 @@
+pub mod tcp_sender;
+pub mod util;
+
+pub use tcp_sender::{Reliability, Sender, MAX_UNORDERED};
+pub use util::capture::Capture;
+pub use util::crypto::Psk;
+pub use util::noise::{parse_key, to_hex, Identity};
+pub use util::stats::Stats;
+pub use util::transport::{Clock, SystemClock, Transport};
@@ -1,31 +1,156 @@
-mod util;
-mod tcp_sender;
-use std::any::TypeId;
-use std::io::{self, BufRead, Stdin, StdinLock};
-use clap::Parser;
-use std::net::UdpSocket;
-use std::thread::{JoinHandle, spawn};
-use util::tcp_header::TcpHeader;
-use util::util::*;
-use tcp_sender::Sender;
+use clap::{Parser, ValueEnum};
+use sender::{Identity, Psk, Sender, Stats};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::Duration;
+use tracing::info;
+use tracing_subscriber::EnvFilter;
 
 // Command line arguments
 #[derive(Parser, Debug)]
 #[command(author, about, long_about = None)]
 struct Cli {
     recv_host: String,
-    recv_port: String
+    recv_port: String,
+    // Write every datagram sent and received to this pcapng file
+    #[arg(long)]
+    pcap: Option<PathBuf>,
+    // Write a JSON-lines trace of the connection's events to this file
+    #[arg(long)]
+    qlog: Option<PathBuf>,
+    // Encrypt and authenticate the connection with the pre-shared key in this file, the other end needs the same
+    #[arg(long)]
+    psk_file: Option<PathBuf>,
+    // Encrypt the connection with keys from a Noise handshake, under a fresh static key
+    #[arg(long, conflicts_with = "psk_file")]
+    noise: bool,
+    // Same, under the static key in this file, 64 hex digits
+    #[arg(long, conflicts_with = "psk_file")]
+    noise_key: Option<PathBuf>,
+    // Only talk to a receiver with this static public key, 64 hex digits
+    #[arg(long, conflicts_with = "psk_file")]
+    pin: Option<String>,
+    // Diagnostics to print, a level (error, warn, info, debug, trace) or RUST_LOG directives
+    #[arg(long)]
+    log_level: Option<String>,
+    // Format of the statistics printed at the end of the transfer
+    #[arg(long, value_enum, default_value_t = StatsFormat::Text)]
+    stats: StatsFormat,
+    // Give up once the receiver went quiet for this many seconds
+    #[arg(long)]
+    idle_timeout: Option<f64>,
+    // Give up instead of resending a packet more than this many times
+    #[arg(long)]
+    max_retransmits: Option<u32>,
+    // Resume with the token in this file if there is one, and keep the token the receiver hands out in it.
+    // Data sent right behind the SYN can be replayed, see Sender::set_resumption_token
+    #[arg(long, conflicts_with_all = ["noise", "noise_key", "pin"])]
+    token: Option<PathBuf>,
+    // Largest datagram to send and take, segments stay within it and what the receiver takes
+    #[arg(long, default_value_t = 1500)]
+    max_datagram_size: usize,
 }
 
+// Exit codes when the receiver reset the connection or went quiet, other errors exit with 1
+const EXIT_RESET: i32 = 3;
+const EXIT_TIMED_OUT: i32 = 4;
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum StatsFormat {
+    Text,
+    Json,
+}
+
+// Diagnostics go to stderr, filtered by --log-level, or by RUST_LOG without it, at info by default
+fn init_logging(level: Option<&str>) -> Result<(), String> {
+    let filter = match level {
+        Some(level) => EnvFilter::try_new(level),
+        None => EnvFilter::try_from_default_env().or_else(|_| EnvFilter::try_new("info")),
+    }
+    .map_err(|e| format!("{e} -> Invalid log level"))?;
+
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(io::stderr)
+        .with_ansi(false)
+        .with_target(false)
+        .without_time()
+        .init();
+    Ok(())
+}
+
+// Statistics go to stderr, the receiver's stdout is the data
+fn print_stats(stats: &Stats, format: StatsFormat) {
+    match format {
+        StatsFormat::Text => eprintln!("{stats}"),
+        StatsFormat::Json => eprintln!("{}", stats.to_json()),
+    }
+}
+
+// Token of an earlier connection, none if the file doesn't exist yet
+fn read_token(path: &Path) -> Result<Option<Vec<u8>>, String> {
+    match fs::read(path) {
+        Ok(token) => Ok(Some(token)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(format!("{e} -> Failed to read resumption token {}", path.display())),
+    }
+}
 
 fn main() -> Result<(), String> {
     // Parse command line arguments
     let cli = Cli::parse();
+    init_logging(cli.log_level.as_deref())?;
     let port = cli.recv_port.parse::<u16>().unwrap();
     // Get the sender ready
-    let mut sender = Sender::new(cli.recv_host, port, "127.0.0.1".to_string(), 65340, 4).unwrap();
+    let mut sender = Sender::new(
+        cli.recv_host,
+        port,
+        "127.0.0.1".to_string(),
+        65340,
+        4,
+        cli.pcap.as_deref(),
+    )
+    .unwrap();
+    if let Some(path) = cli.psk_file {
+        sender.set_psk(Psk::from_file(&path)?)?;
+    }
+    if cli.noise || cli.noise_key.is_some() || cli.pin.is_some() {
+        let identity = match &cli.noise_key {
+            Some(path) => Identity::from_file(path)?,
+            None => Identity::generate(),
+        };
+        let pinned = cli.pin.as_deref().map(sender::parse_key).transpose()?;
+        info!(key = %sender::to_hex(&identity.public()), "Noise static key");
+        sender.set_identity(identity, pinned)?;
+    }
+    if let Some(path) = cli.qlog {
+        sender.log_events(&path)?;
+    }
+    sender.set_idle_timeout(cli.idle_timeout.map(Duration::from_secs_f64));
+    sender.set_max_retransmits(cli.max_retransmits);
+    sender.set_max_datagram_size(cli.max_datagram_size)?;
+    if let Some(token) = cli.token.as_deref().map(read_token).transpose()?.flatten() {
+        sender.set_resumption_token(&token)?;
+    }
     // Start the sender
-    sender.start().expect("Failed to start the sender");
+    let res = sender.start();
+    if let (Some(path), Some(token)) = (&cli.token, sender.resumption_token()) {
+        fs::write(path, token)
+            .map_err(|e| format!("{e} -> Failed to write resumption token {}", path.display()))?;
+    }
+    if let Err(e) = res {
+        let code = match sender.error() {
+            Some(io::ErrorKind::ConnectionReset) => EXIT_RESET,
+            Some(io::ErrorKind::TimedOut) => EXIT_TIMED_OUT,
+            _ => return Err(e),
+        };
+        eprintln!("Error: {e}");
+        print_stats(&sender.stats(), cli.stats);
+        process::exit(code);
+    }
+    print_stats(&sender.stats(), cli.stats);
 
     Ok(())
 }
@@ -1,40 +1,189 @@
 use rand::prelude::*;
-use std::collections::HashMap;
-use std::collections::VecDeque;
-use std::fmt::format;
-use std::io::{self, BufRead, Stdin, StdinLock};
+use rand::rngs::StdRng;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, VecDeque};
+use std::io::{self, Read};
+use std::mem;
 use std::net::UdpSocket;
-use std::thread::sleep;
+use std::path::Path;
+use std::sync::Arc;
 use std::time::Duration;
 use std::time::Instant;
+use tracing::{debug, info, info_span, trace, warn, Span};
 
-use generic_array::GenericArray;
-use sha2::{Digest, Sha256};
-use typenum::U32;
+use crate::util::capture::Capture;
+use crate::util::crypto::{session_key, Keys, Protection, Psk, COOKIE_SIZE, RANDOM_SIZE, TOKEN_SIZE};
+use crate::util::noise::{to_hex, Handshake, Identity, KEY_SIZE, MAX_MESSAGE};
+use crate::util::qlog::{packet_header, EventLog};
+use crate::util::seq::SeqNum;
+use crate::util::stats::Stats;
+use crate::util::tcp_header::{
+    TcpHeader, FORWARD, HEADER_SIZE, KEEPALIVE, MSG_END, MSG_START, MSS, PATH_CHALLENGE, PATH_RESPONSE,
+    RESUMED, RETRY, RST, TOKEN, UNORDERED,
+};
+use crate::util::transport::{Clock, SystemClock, Transport};
+use crate::util::util::{decode_sack, segment_len};
 
-use crate::util::tcp_header::TcpHeader;
-use crate::{read_to_string, safe_increment};
-
-const DATASIZE: u16 = 1452;
+// Largest datagram taken by default, and taken to be what a receiver that doesn't say takes
+const MAX_DATAGRAM: u16 = 1500;
+// Datagram size every path is taken to carry, segments are cut to it until a probe shows more gets through
+const BASE_PLPMTU: u16 = 1200;
+// Largest unordered record, records are never cut so they must fit the base size. Less with a receiver that
+// takes smaller datagrams.
+pub const MAX_UNORDERED: usize = BASE_PLPMTU as usize - HEADER_SIZE;
+// Smallest datagram a receiver may announce, a header and a byte of data
+const MIN_PLPMTU: u16 = HEADER_SIZE as u16 + 1;
+// Probes of a size lost before the size is taken not to get through
+const MAX_PROBES: u32 = 3;
+// The search for the path MTU stops once what got through and what didn't are this close
+const PROBE_GRANULARITY: u16 = 16;
+// A finished search starts over after this long, the path may carry more by then
+const RAISE_TIMER: Duration = Duration::from_secs(600);
+// Timeouts of a segment larger than the base size before the path is suspected of dropping large datagrams
+const BLACK_HOLE_RETRANSMITS: u32 = 2;
+// Sequence numbers a sealed connection may use, past them a nonce would come back
+const NONCE_LIMIT: u32 = 1 << 31;
+// The SYN doubles its timeout on every retransmission, up to this long
+const MAX_SYN_TIMEOUT: Duration = Duration::from_secs(2);
 
 // Sender status
 #[derive(Debug)]
 enum Status {
-    StandBy, // Waiting for stdin
+    StandBy, // Not connected yet
     Handshake,
     Sending,  // Last SEND will send FIN packet
-    Finished, // After sending
+    Finished, // Everything queued has been acknowledged
+    Reset,    // The receiver reset the connection
+    TimedOut, // The receiver stopped answering
 }
 
 // Packet struct
 #[derive(Clone, Debug)]
 struct Packet {
     timestamp: Instant, // time when packet is sent
+    first_sent: Instant, // When the packet was sent before any retransmission
     data: Vec<u8>,
-    seq_num: u32,
-    ack_num: u32,
-    confirm_ack: u32, // Ack number supposed to be, used for retransmission
+    seq_num: SeqNum,
+    confirm_ack: SeqNum, // Ack number supposed to be, used for retransmission
     data_len: u16,    // length for data
+    stream_id: Option<u16>, // Stream of the payload, None for handshake packets
+    message: Option<MessageInfo>, // Partially reliable message of the payload
+    retransmits: u32, // Times the packet is resent after a timeout
+    sacked: bool, // Selectively acknowledged, waiting for the cumulative ACK to catch up
+}
+
+// How long a message is worth retransmitting
+#[derive(Clone, Copy, Debug)]
+pub enum Reliability {
+    Reliable,            // Retransmit until acknowledged
+    Lifetime(Duration),  // Abandon once the message has been queued for this long
+    MaxRetransmits(u32), // Abandon after this many retransmissions of a segment
+}
+
+// Partially reliable message a segment belongs to
+#[derive(Clone, Copy, Debug)]
+struct MessageInfo {
+    id: u32, // Counts messages on the stream
+    reliability: Reliability,
+    queued: Instant, // time when the message is queued
+}
+
+impl MessageInfo {
+    // Whether the message is stale and should be skipped instead of sent,
+    // retransmits counts the retransmissions including the one about to happen
+    fn expired(&self, retransmits: u32, now: Instant) -> bool {
+        match self.reliability {
+            Reliability::Reliable => false,
+            Reliability::Lifetime(lifetime) => now.duration_since(self.queued) >= lifetime,
+            Reliability::MaxRetransmits(max) => retransmits > max,
+        }
+    }
+}
+
+// Segment waiting to be sent on a stream
+#[derive(Debug)]
+struct Segment {
+    data: Vec<u8>,
+    frame_flags: u8, // Message boundaries, if the segment is part of a message
+    message: Option<MessageInfo>,
+}
+
+impl Segment {
+    // Whether the next segment queued carries on where this one stops, in the byte stream or the same message
+    fn continues_into(&self, next: &Segment) -> bool {
+        self.frame_flags & (MSG_END | UNORDERED | FORWARD) == 0
+            && next.frame_flags & (MSG_START | UNORDERED | FORWARD) == 0
+            && self.message.map(|m| m.id) == next.message.map(|m| m.id)
+    }
+}
+
+// Outgoing stream, every stream has its own sequence space and flow-control credit
+#[derive(Debug)]
+struct SendStream {
+    data: VecDeque<Segment>, // Data that been segmented
+    seq_num: SeqNum,         // Stream sequence number of the next segment
+    in_flight: u32,          // Bytes of this stream sent but not acknowledged
+    credit: u32,             // Bytes the receiver is willing to buffer for this stream
+    next_message: u32,       // ID given to the next partially reliable message
+}
+
+impl SendStream {
+    fn new(credit: u32) -> Self {
+        SendStream {
+            data: VecDeque::new(),
+            seq_num: SeqNum(0),
+            in_flight: 0,
+            credit,
+            next_message: 0,
+        }
+    }
+
+    // Flow control: a stream with nothing in flight may always send one segment,
+    // so a closed window is probed instead of waiting for an update forever.
+    fn can_send(&self) -> bool {
+        match self.data.front() {
+            Some(segment) => {
+                self.in_flight == 0 || self.in_flight + segment.data.len() as u32 <= self.credit
+            }
+            None => false,
+        }
+    }
+
+    // Take the next segment, size bytes of it at most. Segments queued behind it that continue it fill it up,
+    // message boundaries stay where they were. Unordered records and skip markers go as they are, they fit.
+    fn pop_segment(&mut self, size: usize) -> Segment {
+        let mut segment = self.data.pop_front().unwrap();
+        while segment.data.len() < size && self.data.front().is_some_and(|next| segment.continues_into(next)) {
+            let next = &mut self.data[0];
+            let len = next.data.len().min(size - segment.data.len());
+            segment.data.extend(next.data.drain(..len));
+            if next.data.is_empty() {
+                segment.frame_flags |= next.frame_flags;
+                self.data.pop_front();
+            }
+        }
+        if segment.data.len() > size {
+            self.data.push_front(Segment {
+                data: segment.data.split_off(size),
+                frame_flags: segment.frame_flags & !MSG_START,
+                message: segment.message,
+            });
+            segment.frame_flags &= !MSG_END;
+        }
+        segment
+    }
+
+    // Payload bytes of the segment pop_segment would take
+    fn next_len(&self, size: usize) -> usize {
+        let mut len = self.data[0].data.len();
+        for pair in self.data.iter().zip(self.data.iter().skip(1)) {
+            if len >= size || !pair.0.continues_into(pair.1) {
+                break;
+            }
+            len += pair.1.data.len();
+        }
+        len.min(size)
+    }
 }
 
 // Sender struct
@@ -42,16 +191,20 @@ struct Packet {
 pub struct Sender {
     remote_host: String,
     remote_port: u16,
-    local_host: String,
     local_port: u16,
+    peer_port: u16, // Source port of the receiver's headers, from its SYN-ACK
     status: Status,
-    seq_num: u32,
-    ack_num: u32,
-    data: VecDeque<String>, // Data that been segmented
-    init_seq: u32,          // not used
-    socket: UdpSocket,
+    seq_num: SeqNum,
+    ack_num: SeqNum,
+    streams: BTreeMap<u16, SendStream>, // Streams multiplexed on this connection
+    next_stream: u16,                   // ID given to the next opened stream
+    last_stream: u16,                   // Stream that sent last, for round-robin scheduling
+    init_seq: SeqNum,                   // Sequence number of the SYN, the nonce limit counts from it
+    socket: Box<dyn Transport>,
+    clock: Arc<dyn Clock>,
     rto: u64, // 2 * RTT
     rtt: u64,
+    backed_off: bool, // A resent packet timed out again, the RTO is doubled until new data is acknowledged
     in_flight: VecDeque<Packet>, // Packets that are in flight
     wnd_size: u16,               // Initial window size
     cur_wnd: u16,                // Current window size
@@ -59,47 +212,119 @@ pub struct Sender {
     cwnd: u16,    // Congestion window size
     count: u8,    // For duplicate ack
     cur_buf: u16, // Length of data in flight (only data, not including header)
-    pre_ack: u32, // Latest ACK that received
+    pre_ack: SeqNum, // Latest ACK that received
+    connection_id: u64, // Identifies this connection to the receiver, even if our address changes
+    qlog: Option<EventLog>, // Structured trace of the connection, if asked for
+    span: Span,             // Diagnostics of this connection
+    state_span: Span,       // Diagnostics of the current status, inside the connection's span
+    stats: Stats,
+    started: Option<Instant>,  // When the SYN was first sent
+    finished: Option<Instant>, // When everything queued was acknowledged
+    established: Option<Instant>, // When the receiver acknowledged our handshake ACK
+    idle_timeout: Option<Duration>, // Give up once nothing was heard from the receiver for this long
+    max_retransmits: Option<u32>,   // Give up instead of resending a packet once more than this
+    keepalive: Option<Duration>,    // Probe the receiver when nothing was heard from it for this long
+    last_heard: Option<Instant>,    // When the latest packet from the receiver arrived, or the SYN was sent
+    last_probe: Option<Instant>,    // When the latest keepalive probe was sent
+    keepalive_seq: u32,             // Keepalive probes sent, the stream sequence number of the last one
+    protection: Protection,
+    random: [u8; RANDOM_SIZE], // Sent in the SYN with a pre-shared key, goes into the connection's keys
+    identity: Option<Identity>,       // Our static key, the connection's keys come from a Noise handshake with it
+    pinned: Option<[u8; KEY_SIZE]>,   // Static key the receiver must have, if any
+    handshake: Option<Handshake>,     // Noise handshake between the SYN and the SYN-ACK
+    token: Option<Vec<u8>>,           // Resumption token of an earlier connection, brought back in the SYN
+    issued_token: Option<Vec<u8>>,    // Resumption token the receiver handed out on this connection
+    max_datagram: u16,                // Largest datagram we take, announced in the SYN
+    buf: Vec<u8>,                     // Receive buffer, max_datagram bytes
+    max_plpmtu: u16,                  // Largest datagram both ends take, the path MTU search goes no further
+    plpmtu: u16,                      // Largest datagram the path is known to carry, segments are cut to fit
+    mtu_ceiling: u16,                 // Largest datagram the path may carry, the search probes in between
+    mtu_probe: Option<SeqNum>,        // Segment in flight that probes the path, if any
+    mtu_lost: u32,                    // Probes of the size searched for lost so far
+    mtu_raise: Option<Instant>,       // When a finished search starts over, None while searching
+    mtu_heard: Option<Instant>,       // When a segment above the base size last got through
 }
 
 impl Sender {
-    // Constructor
+    // Constructor, every datagram goes to the capture file if one is given
     pub fn new(
         remote_host: String,
         remote_port: u16,
         local_host: String,
         default_wnd_size: u16,
         default_cwnd: u16,
+        capture: Option<&Path>,
     ) -> Result<Self, String> {
-        // Generate a random sequence number
-        let mut rng = rand::thread_rng();
-        let seq_num: u32 = rng.gen();
-
         // Bind socket to a random port
         let socket = UdpSocket::bind(format!("{}:{}", local_host, 0))
             .map_err(|e| format!("{} -> Failed to bind to {}:{}", e, local_host, 0))?;
-        // Get the local address
-        let local = socket
-            .local_addr()
-            .map_err(|e| format!("{e} -> Failed to get local port"))?;
         // Switch to non-blocking
         socket
             .set_nonblocking(true)
             .map_err(|e| format!("{e} -> Failed to switch to non-blocking mode"))?;
 
+        let clock: Arc<dyn Clock> = Arc::new(SystemClock);
+        let socket: Box<dyn Transport> = match capture {
+            Some(path) => Box::new(Capture::new(Box::new(socket), path, clock.clone())?),
+            None => Box::new(socket),
+        };
+
+        Self::with_transport(
+            remote_host,
+            remote_port,
+            socket,
+            clock,
+            rand::thread_rng().gen(),
+            default_wnd_size,
+            default_cwnd,
+        )
+    }
+
+    // Constructor over any transport and clock, the seed makes the connection reproducible
+    pub fn with_transport(
+        remote_host: String,
+        remote_port: u16,
+        socket: Box<dyn Transport>,
+        clock: Arc<dyn Clock>,
+        seed: u64,
+        default_wnd_size: u16,
+        default_cwnd: u16,
+    ) -> Result<Self, String> {
+        // Generate a random sequence number
+        let mut rng = StdRng::seed_from_u64(seed);
+        let seq_num = SeqNum(rng.gen());
+        // Generate a random connection ID
+        let connection_id: u64 = rng.gen();
+
+        // Get the local address
+        let local = socket
+            .local_addr()
+            .map_err(|e| format!("{e} -> Failed to get local port"))?;
+
+        // Stream 0 is always open
+        let mut streams = BTreeMap::new();
+        streams.insert(0, SendStream::new(default_wnd_size as u32));
+
+        let span = info_span!("connection", id = %format!("{connection_id:016x}"));
+        let state_span = info_span!(parent: &span, "StandBy");
+
         Ok(Sender {
             remote_host,
             remote_port,
-            local_host,
             local_port: local.port(),
+            peer_port: 0,
             status: Status::StandBy,
             init_seq: seq_num,
             seq_num,
-            ack_num: 0,
-            data: VecDeque::new(),
+            ack_num: SeqNum(0),
+            streams,
+            next_stream: 1,
+            last_stream: 0,
             socket,
+            clock,
             rto: 800, // Initial RTO
             rtt: 400,         // Initial RTT
+            backed_off: false,
             in_flight: VecDeque::new(),
             wnd_size: default_wnd_size,
             ssthresh: 32,
@@ -107,316 +332,1240 @@ impl Sender {
             cur_wnd: 5808,
             cwnd: default_cwnd,
             cur_buf: 1,
-            pre_ack: 0,
+            pre_ack: seq_num,
+            connection_id,
+            qlog: None,
+            span,
+            state_span,
+            stats: Stats::default(),
+            started: None,
+            finished: None,
+            established: None,
+            idle_timeout: None,
+            max_retransmits: None,
+            keepalive: None,
+            last_heard: None,
+            last_probe: None,
+            keepalive_seq: 0,
+            protection: Protection::default(),
+            random: [0; RANDOM_SIZE],
+            identity: None,
+            pinned: None,
+            handshake: None,
+            token: None,
+            issued_token: None,
+            max_datagram: MAX_DATAGRAM,
+            buf: vec![0; MAX_DATAGRAM as usize],
+            max_plpmtu: BASE_PLPMTU,
+            plpmtu: BASE_PLPMTU,
+            mtu_ceiling: BASE_PLPMTU,
+            mtu_probe: None,
+            mtu_lost: 0,
+            mtu_raise: None,
+            mtu_heard: None,
         })
     }
 
-    // Start the sender
+    // Use the given initial sequence number instead of the random one, only before the handshake
+    pub fn set_initial_seq(&mut self, seq: u32) -> Result<(), String> {
+        if !matches!(self.status, Status::StandBy) {
+            return Err("The connection is already open".to_string());
+        }
+        self.init_seq = SeqNum(seq);
+        self.seq_num = SeqNum(seq);
+        self.pre_ack = SeqNum(seq);
+        Ok(())
+    }
+
+    // Encrypt and authenticate the connection with a key the receiver shares, only before the handshake
+    pub fn set_psk(&mut self, psk: Psk) -> Result<(), String> {
+        if !matches!(self.status, Status::StandBy) {
+            return Err("The connection is already open".to_string());
+        }
+        if self.identity.is_some() {
+            return Err("The connection already uses a Noise handshake".to_string());
+        }
+        self.protection.psk = Some(psk);
+        self.random = rand::thread_rng().gen();
+        Ok(())
+    }
+
+    // Encrypt the connection with keys from a Noise handshake under our static key, and only
+    // talk to a receiver holding the pinned key if one is given. Only before the handshake.
+    pub fn set_identity(&mut self, identity: Identity, pinned: Option<[u8; KEY_SIZE]>) -> Result<(), String> {
+        if !matches!(self.status, Status::StandBy) {
+            return Err("The connection is already open".to_string());
+        }
+        if self.protection.psk.is_some() {
+            return Err("The connection already uses a pre-shared key".to_string());
+        }
+        if self.token.is_some() {
+            return Err("The connection already resumes with a token".to_string());
+        }
+        self.identity = Some(identity);
+        self.pinned = pinned;
+        Ok(())
+    }
+
+    // Bring back the resumption token a receiver handed out on an earlier connection from this address.
+    // If it still takes the token, the receiver sets the connection up on the SYN, and the first window of data
+    // goes out right behind it instead of a round trip later. If not, the handshake goes on as usual and that
+    // data is sent again. Only before the handshake, and not with a Noise handshake.
+    // That data can be replayed: whoever recorded the SYN and what followed it can send them again while
+    // the token is fresh, and the receiver delivers them again. Keep it to idempotent requests.
+    pub fn set_resumption_token(&mut self, token: &[u8]) -> Result<(), String> {
+        if !matches!(self.status, Status::StandBy) {
+            return Err("The connection is already open".to_string());
+        }
+        if self.identity.is_some() {
+            return Err("Resumption tokens don't work with a Noise handshake".to_string());
+        }
+        if token.len() != TOKEN_SIZE {
+            return Err(format!("A resumption token is {TOKEN_SIZE} bytes, got {}", token.len()));
+        }
+        self.token = Some(token.to_vec());
+        Ok(())
+    }
+
+    // Send and take datagrams of up to this many bytes, announced to the receiver in the SYN. Segments grow to
+    // the smaller of this and what the receiver announces, as far as the path carries. Only before the handshake.
+    pub fn set_max_datagram_size(&mut self, size: usize) -> Result<(), String> {
+        if !matches!(self.status, Status::StandBy) {
+            return Err("The connection is already open".to_string());
+        }
+        if !(BASE_PLPMTU as usize..=u16::MAX as usize).contains(&size) {
+            return Err(format!(
+                "The largest datagram must be {BASE_PLPMTU} to {} bytes, got {size}",
+                u16::MAX
+            ));
+        }
+        self.max_datagram = size as u16;
+        self.buf.resize(size, 0);
+        Ok(())
+    }
+
+    // Resumption token the receiver handed out on this connection, for the next one
+    pub fn resumption_token(&self) -> Option<Vec<u8>> {
+        self.issued_token.clone()
+    }
+
+    // Fail with TimedOut once nothing was heard from the receiver for this long while waiting for it
+    pub fn set_idle_timeout(&mut self, timeout: Option<Duration>) {
+        self.idle_timeout = timeout;
+    }
+
+    // Fail with TimedOut instead of resending a packet more than this many times
+    pub fn set_max_retransmits(&mut self, max: Option<u32>) {
+        self.max_retransmits = max;
+    }
+
+    // Once everything is acknowledged, probe the receiver when nothing was heard from it for this long,
+    // so that an idle connection stays open with an idle timeout on either end and a dead one is noticed
+    pub fn set_keepalive(&mut self, interval: Option<Duration>) {
+        self.keepalive = interval;
+    }
+
+    // Write a structured trace of the connection to the given file, one JSON event per line
+    pub fn log_events(&mut self, path: &Path) -> Result<(), String> {
+        self.qlog = Some(EventLog::new(path, "client", self.clock.now())?);
+        Ok(())
+    }
+
+    // Counters of the connection so far, the transfer time stops once everything is acknowledged
+    pub fn stats(&self) -> Stats {
+        let mut stats = self.stats.clone();
+        if let Some(started) = self.started {
+            let end = self.finished.unwrap_or_else(|| self.clock.now());
+            stats.elapsed = end.saturating_duration_since(started);
+        }
+        stats
+    }
+
+    // Open a new stream, data on it is delivered independently of the other streams
+    pub fn open_stream(&mut self) -> u16 {
+        let stream_id = self.next_stream;
+        self.next_stream += 1;
+        self.streams.insert(stream_id, SendStream::new(self.wnd_size as u32));
+        stream_id
+    }
+
+    // Queue data on a stream, it goes out while polling
+    pub fn send(&mut self, stream_id: u16, data: &[u8]) -> Result<(), String> {
+        let stream = self
+            .streams
+            .get_mut(&stream_id)
+            .ok_or_else(|| format!("Stream {stream_id} is not open"))?;
+        // split into chunks of the largest payload both ends take
+        let max = self.max_plpmtu as usize - HEADER_SIZE;
+        stream.data.extend(data.chunks(max).map(|ch| Segment {
+            data: ch.to_vec(),
+            frame_flags: 0,
+            message: None,
+        }));
+
+        if let Status::Finished = self.status {
+            self.set_status(Status::Sending);
+        }
+        Ok(())
+    }
+
+    // Queue a message on a stream, the receiver gets it back whole from a single recv_message
+    pub fn send_message(&mut self, stream_id: u16, msg: &[u8]) -> Result<(), String> {
+        self.send_message_with(stream_id, msg, Reliability::Reliable)
+    }
+
+    // Queue a message that is abandoned, and skipped by the receiver, once its reliability runs out
+    pub fn send_message_with(
+        &mut self,
+        stream_id: u16,
+        msg: &[u8],
+        reliability: Reliability,
+    ) -> Result<(), String> {
+        let max = self.max_plpmtu as usize - HEADER_SIZE;
+        let stream = self
+            .streams
+            .get_mut(&stream_id)
+            .ok_or_else(|| format!("Stream {stream_id} is not open"))?;
+
+        let message = match reliability {
+            Reliability::Reliable => None,
+            _ => {
+                stream.next_message = stream.next_message.wrapping_add(1);
+                Some(MessageInfo {
+                    id: stream.next_message,
+                    reliability,
+                    queued: self.clock.now(),
+                })
+            }
+        };
+
+        // Fragment the message, an empty message still takes one segment
+        let mut fragments: Vec<Segment> = msg
+            .chunks(max)
+            .map(|ch| Segment {
+                data: ch.to_vec(),
+                frame_flags: 0,
+                message,
+            })
+            .collect();
+        if fragments.is_empty() {
+            fragments.push(Segment {
+                data: Vec::new(),
+                frame_flags: 0,
+                message,
+            });
+        }
+        fragments[0].frame_flags |= MSG_START;
+        fragments.last_mut().unwrap().frame_flags |= MSG_END;
+        stream.data.extend(fragments);
+
+        if let Status::Finished = self.status {
+            self.set_status(Status::Sending);
+        }
+        Ok(())
+    }
+
+    // Queue an independent record, the receiver hands it out as soon as it arrives. Records of more than
+    // MAX_UNORDERED bytes, or of more than fits what the receiver takes once the handshake says, are refused.
+    // Those queued before the handshake that don't fit what the receiver takes are dropped when it completes.
+    pub fn send_unordered(&mut self, stream_id: u16, record: &[u8]) -> Result<(), String> {
+        // Records are never cut, they must fit whatever the path carries
+        let max = self.max_unordered();
+        if record.len() > max {
+            return Err(format!(
+                "Unordered record of {} bytes doesn't fit in a segment of {} bytes",
+                record.len(),
+                max
+            ));
+        }
+        let stream = self
+            .streams
+            .get_mut(&stream_id)
+            .ok_or_else(|| format!("Stream {stream_id} is not open"))?;
+        stream.data.push_back(Segment {
+            data: record.to_vec(),
+            frame_flags: UNORDERED | MSG_START | MSG_END,
+            message: None,
+        });
+
+        if let Status::Finished = self.status {
+            self.set_status(Status::Sending);
+        }
+        Ok(())
+    }
+
+    // Start the sender, send stdin on stream 0 and return once it's acknowledged
     pub fn start(&mut self) -> Result<(), String> {
-        loop {
-            eprintln!("seq# {}, ack# {}", self.seq_num, self.ack_num);
-
-            match self.status {
-                // Read from stdin
-                Status::StandBy => {
-                    // Read from stdin
-                    eprintln!("Standby");
-                    let mut buffer = String::new();
-                    let stdin = io::stdin();
-                    let mut handle = stdin.lock(); // ensure exclusive access to stdin
-                    handle
-                        .read_line(&mut buffer)
-                        .map_err(|e| format!("{e} -> Failed to read stdin"))?;
-                    eprintln!("{}", buffer.len());
-
-
-                    self.data = buffer
-                        .as_bytes() // convert string to bytes
-                        .chunks(DATASIZE as usize) // split into chunks of 1440 bytes and return an iterator
-                        .map(|ch| String::from_utf8_lossy(ch).to_string()) // Turn each chunk into a string
-                        .collect();
-                    eprintln!("data length: {}", self.data.len());
-                    self.status = Status::Handshake;
+        let _entered = self.span.clone().entered();
+        // Read from stdin
+        let mut buffer = Vec::new();
+        let stdin = io::stdin();
+        let mut handle = stdin.lock(); // ensure exclusive access to stdin
+        handle
+            .read_to_end(&mut buffer)
+            .map_err(|e| format!("{e} -> Failed to read stdin"))?;
+        info!(bytes = buffer.len(), "read stdin");
+
+        self.send(0, &buffer)?;
+        debug!(segments = self.streams[&0].data.len(), "queued on stream 0");
+
+        while !self.poll()? {}
+
+        info!(rto_ms = self.rto, "done");
+        Ok(())
+    }
+
+    // Make progress without blocking, returns true once all queued data is acknowledged
+    pub fn poll(&mut self) -> Result<bool, String> {
+        let span = self.state_span.clone();
+        let _entered = span.enter();
+
+        if self.idle_deadline().is_some_and(|deadline| self.clock.now() >= deadline) {
+            warn!(timeout_ms = self.idle_timeout.unwrap().as_millis() as u64, "nothing heard from the receiver");
+            self.set_status(Status::TimedOut);
+        }
+
+        match self.status {
+            // Send the SYN packet
+            Status::StandBy => {
+                let mut header = self.new_header(0b0000_0010, 0, SeqNum(0));
+                // With a pre-shared key, the SYN carries our half of the key material,
+                // with an identity the first message of the Noise handshake
+                let mut payload = match (&self.protection.psk, &self.identity) {
+                    (Some(_), _) => self.random.to_vec(),
+                    (None, Some(identity)) => {
+                        let mut handshake = Handshake::initiator(identity);
+                        let msg = handshake.write()?;
+                        self.handshake = Some(handshake);
+                        msg
+                    }
+                    (None, None) => Vec::new(),
+                };
+                if let Some(token) = &self.token {
+                    header.frame_flags = TOKEN;
+                    payload.extend_from_slice(token);
                 }
-                // Handshake
-                Status::Handshake => {
-                    eprintln!("Handshake");
-                    let mut header = TcpHeader {
-                        source_port: self.local_port,
-                        destination_port: self.remote_port, // simulator's port
-                        sequence_number: self.seq_num,
-                        ack_number: self.ack_num,
-                        header_length: 4, // unit of 4 bytes
-                        flags: 0b0000_0010,
-                        window_size: self.wnd_size,
-                        hash_value: [0; 32].into(), // testing
-                    };
-
-                    // Get the hash value of the header
-                    header.hash_value = header.calculate_header_hash();
-                    // Prepare the packet to in flight, and send it
-                    self.register_packet(header, "");
-
-                    let mut buf: [u8; 1500] = [0; 1500];
-                    // Get the SYN-ACK packet
-                    loop {
-                        match self.socket.recv(&mut buf) {
-                            Ok(_) => {
-                                // The first 16 bytes of the buffer are used to create a new TcpHeader instance.
-                                let header = TcpHeader::new(&buf[..48]);
-                                buf.fill(0);
-
-                                // Check if the hash value of the header matches the hash value in the header
-                                if !Self::check_hash(&header) {
-                                    continue;
-                                }
-
-                                if header.ack_number != self.in_flight[0].confirm_ack {
-                                    continue;
-                                }
-
-                                if header.flags != 18 {
-                                    // ACK, SYN = 18
-                                    continue;
-                                }
-                                // Set window size to minimum of receiver adv window and sender's adv window size
-                                let adv_wnd = self.wnd_size.min(header.window_size);
-                                // Set sshtresh to adv_wnd / 1440
-                                self.ssthresh = adv_wnd / DATASIZE;
-                                self.cur_wnd = self.cwnd * DATASIZE;
-                                let packet = self.in_flight.pop_front().unwrap();
-                                let cur_time = Instant::now();
-                                // Calculate the initial rtt
-                                self.rtt = cur_time.duration_since(packet.timestamp).as_millis() as u64;
-                                self.update_rto(self.rtt as u128);
-                                self.ack_num = safe_increment(header.sequence_number, 1);
-                                // After handshake, send data
-                                let mut header = TcpHeader {
-                                    source_port: self.local_port,
-                                    destination_port: self.remote_port,
-                                    sequence_number: self.seq_num,
-                                    ack_number: self.ack_num,
-                                    header_length: 4,
-                                    flags: 0b0001_0000,
-                                    window_size: self.wnd_size,
-                                    hash_value: [0; 32].into(), // testing
-                                };
-                                // Get the hash value of the header
-                                header.hash_value = header.calculate_header_hash();
-
-                                self.register_packet(header, "");
-                                self.status = Status::Sending; // Change status to sending
-                                break;
-                            }
-                            Err(_) => {}
-                        }
-
-                        self.check_retransmission();
+                // Then the largest datagram we take
+                header.frame_flags |= MSS;
+                payload.extend_from_slice(&self.max_datagram.to_be_bytes());
+
+                // Prepare the packet to in flight, and send it
+                self.started = Some(self.clock.now());
+                self.last_heard = self.started;
+                self.register_packet(header, &payload, None);
+                // With a token, data follows the SYN under keys from the token, past the handshake ACK's slot
+                if let Some(token) = self.token.clone() {
+                    self.seq_num += 1;
+                    match &self.protection.psk {
+                        Some(psk) => self.protection.keys = Some(Keys::derive(psk, &self.random, &token, true)),
+                        None => self.protection.session = Some(session_key(&token, self.connection_id)),
                     }
+                    self.send_segments();
+                }
+                self.set_status(Status::Handshake);
+            }
+            // Wait for the SYN-ACK packet
+            Status::Handshake => {
+                self.receive(Self::handle_syn_ack).transpose()?;
+
+                self.check_retransmission();
+                if self.token.is_some() && matches!(self.status, Status::Handshake) {
+                    self.send_segments();
+                }
+            }
+            // Sending data
+            Status::Sending => {
+                if self.protection.keys.is_some() && self.seq_num - self.init_seq >= NONCE_LIMIT {
+                    return Err("The sealed connection ran out of nonces".to_string());
+                }
+                self.check_retransmission();
+
+                self.receive(Self::handle_ack);
+
+                self.send_segments();
+
+                if self.in_flight.is_empty() && self.streams.values().all(|s| s.data.is_empty()) {
+                    self.set_status(Status::Finished);
+                }
+            }
+            // After sending all data, wait for more to be queued, probing the receiver if asked
+            Status::Finished => {
+                if self.keepalive.is_some() {
+                    self.receive(Self::handle_ack);
+                    self.send_keepalive();
+                }
+            }
+            Status::Reset | Status::TimedOut => {}
+        }
+
+        match self.status {
+            Status::Reset => Err("The connection was reset by the receiver".to_string()),
+            Status::TimedOut => Err("The connection timed out".to_string()),
+            _ => Ok(matches!(self.status, Status::Finished)),
+        }
+    }
+
+    // Why the connection ended before its data got through, if it did
+    pub fn error(&self) -> Option<io::ErrorKind> {
+        match self.status {
+            Status::Reset => Some(io::ErrorKind::ConnectionReset),
+            Status::TimedOut => Some(io::ErrorKind::TimedOut),
+            _ => None,
+        }
+    }
+
+    // Time when the next retransmission is due, if anything is waiting for an ACK.
+    // Handshake packets have their own timers, and retransmission of data stops at the first segment
+    // that isn't due, so that segment decides. The idle timeout and keepalive probes have their own deadlines.
+    pub fn next_timeout(&self) -> Option<Instant> {
+        // The SYN is all there is in flight until the SYN-ACK
+        let syn = matches!(self.status, Status::Handshake);
+        let unsacked = self.in_flight.iter().filter(|packet| !packet.sacked);
+        let handshake = unsacked.clone().filter(|packet| packet.stream_id.is_none());
+        // A path MTU probe only times out as the last segment in flight
+        let last = self.in_flight.len().saturating_sub(1);
+        let data = self.in_flight.iter().enumerate().find_map(|(i, packet)| {
+            let probe = self.mtu_probe == Some(packet.seq_num) && i < last;
+            (!packet.sacked && !probe && packet.stream_id.is_some()).then_some(packet)
+        });
+        let retransmission = handshake
+            .chain(data)
+            .map(|packet| packet.timestamp + Self::timeout(self.current_rto(), packet, syn))
+            .min();
+        [retransmission, self.idle_deadline(), self.keepalive_due()]
+            .into_iter()
+            .flatten()
+            .min()
+    }
+
+    // When to give up on a receiver that went quiet: while waiting for it, or at any time with keepalives
+    fn idle_deadline(&self) -> Option<Instant> {
+        let waiting = match self.status {
+            Status::Handshake | Status::Sending => true,
+            Status::Finished => self.keepalive.is_some(),
+            _ => false,
+        };
+        match (self.idle_timeout, self.last_heard) {
+            (Some(timeout), Some(last_heard)) if waiting => Some(last_heard + timeout),
+            _ => None,
+        }
+    }
+
+    // When the next keepalive probe is due, once everything is acknowledged
+    fn keepalive_due(&self) -> Option<Instant> {
+        let interval = self.keepalive?;
+        if !matches!(self.status, Status::Finished) {
+            return None;
+        }
+        let last = self.last_heard.max(self.last_probe)?;
+        Some(last + interval)
+    }
+
+    // Hand the next datagram waiting on the socket, if any, to the given handler
+    fn receive<R>(&mut self, handle: impl FnOnce(&mut Self, &[u8]) -> R) -> Option<R> {
+        // Out of the struct while the handler runs, so it can still take &mut self
+        let mut buf = mem::take(&mut self.buf);
+        let handled = self.socket.recv_from(&mut buf).ok().map(|(len, _)| {
+            self.stats.received(len);
+            handle(self, &buf[..len])
+        });
+        self.buf = buf;
+        handled
+    }
+
+    // Handle a packet received during the handshake, a receiver that isn't the pinned one is an error
+    fn handle_syn_ack(&mut self, buf: &[u8]) -> Result<(), String> {
+        // The first HEADER_SIZE bytes of the buffer are used to create a new TcpHeader instance.
+        let header = match TcpHeader::new(buf) {
+            Ok(header) => header,
+            Err(_) => return Ok(()),
+        };
+        self.log_packet("transport:packet_received", &header, buf.len(), None);
+
+        // An ACK left over from an earlier connection on this port
+        if header.flags == 16 && header.connection_id != self.connection_id {
+            self.log_dropped("connection_id_mismatch");
+            self.send_reset(&header);
+            return Ok(());
+        }
+
+        // Check the hash, or the MAC with a pre-shared key
+        let payload = match self.protection.open(&header, &buf[HEADER_SIZE..]) {
+            Some(payload) => payload,
+            None => {
+                self.stats.corrupted += 1;
+                self.log_dropped("hash_mismatch");
+                return Ok(());
+            }
+        };
+
+        if header.connection_id != self.connection_id {
+            self.log_dropped("connection_id_mismatch");
+            return Ok(());
+        }
+
+        if header.ack_number != self.in_flight[0].confirm_ack {
+            return Ok(());
+        }
+
+        if header.flags != 18 {
+            // ACK, SYN = 18
+            return Ok(());
+        }
+
+        if header.destination_port != self.local_port {
+            self.log_dropped("port_mismatch");
+            return Ok(());
+        }
+
+        // The receiver's cookie, a resumption token if it hands them out, then its half of the key material if any,
+        // and the largest datagram it takes if it says
+        let token_len = match header.frame_flags & TOKEN {
+            0 => 0,
+            _ => TOKEN_SIZE,
+        };
+        let mss_len = match header.frame_flags & MSS {
+            0 => 0,
+            _ => 2,
+        };
+        if payload.len() < COOKIE_SIZE + token_len + mss_len {
+            return Ok(());
+        }
+        let (payload, mss) = payload.split_at(payload.len() - mss_len);
+        let (cookie, rest) = payload.split_at(COOKIE_SIZE);
+        // The receiver starts a Noise handshake only once we show we got its cookie, a copy of the retry finds
+        // our SYN echoing it already
+        if header.frame_flags & RETRY != 0 {
+            let retried = TcpHeader::new(&self.in_flight[0].data).is_ok_and(|syn| syn.frame_flags & RETRY != 0);
+            if self.handshake.is_some() && !retried {
+                self.retry_syn(cookie);
+            }
+            return Ok(());
+        }
+        let (token, material) = rest.split_at(token_len);
+        let remote_max = match mss {
+            [hi, lo] => u16::from_be_bytes([*hi, *lo]),
+            _ => MAX_DATAGRAM,
+        };
+        // The receiver took our token, it has the connection and our data is on its way under the token's keys
+        let resumed = header.frame_flags & RESUMED != 0 && self.token.is_some();
+        // The receiver keeps nothing until our ACK echoes the cookie and the key material our SYN carried
+        let syn = &self.in_flight[0].data[HEADER_SIZE..][..self.material_len()];
+        let mut reply = [cookie, syn].concat();
+
+        // Everything after the handshake is sealed with keys from both randoms
+        let mut keys = None;
+        if let Some(psk) = self.protection.psk.as_ref().filter(|_| !resumed) {
+            if material.len() != RANDOM_SIZE {
+                return Ok(());
+            }
+            keys = Some(Keys::derive(psk, &self.random, material, true));
+        }
+        // Or with keys from the Noise handshake, which our reply completes
+        if let Some(handshake) = self.handshake.as_mut() {
+            if handshake.read(material).is_err() {
+                self.log_dropped("handshake_failed");
+                return Ok(());
+            }
+            let remote = handshake.remote_static().unwrap();
+            if self.pinned.is_some_and(|pinned| pinned != remote) {
+                return Err(format!("The receiver's key {} is not the pinned one", to_hex(&remote)));
+            }
+            info!(key = %to_hex(&remote), "receiver authenticated");
+            reply.extend(handshake.write()?);
+        }
+        // Segments grow up to the largest datagram both ends take, the path MTU search starts. A receiver that
+        // takes less than the base size gets what it takes from the start.
+        self.max_plpmtu = self.max_datagram.min(remote_max.max(MIN_PLPMTU));
+        self.plpmtu = self.plpmtu.min(self.max_plpmtu);
+        // Unordered records queued before it said are never cut, those that don't fit are dropped and the
+        // handshake goes on. The error comes once it's done.
+        let dropped = self.drop_unordered(self.max_unordered());
+        self.mtu_ceiling = self.max_plpmtu;
+        self.mtu_raise = None;
+        // Set window size to minimum of receiver adv window and sender's adv window size
+        let adv_wnd = self.wnd_size.min(header.window_size);
+        // Set sshtresh to adv_wnd / 1440
+        self.ssthresh = adv_wnd / self.mss();
+        self.cur_wnd = self.cwnd * self.mss();
+        self.stats.peak_cwnd = self.stats.peak_cwnd.max(self.cur_wnd as u32);
+        // Every stream starts with the receiver's advertised window as credit
+        for stream in self.streams.values_mut() {
+            stream.credit = adv_wnd as u32;
+        }
+        let packet = self.in_flight.pop_front().unwrap();
+        let cur_time = self.clock.now();
+        // Calculate the initial rtt, unless the SYN was resent (Karn)
+        let mut sample = None;
+        if packet.retransmits == 0 {
+            self.stats.rtt(cur_time.duration_since(packet.timestamp));
+            self.rtt = cur_time.duration_since(packet.timestamp).as_millis() as u64;
+            self.update_rto(self.rtt as u128);
+            sample = Some(self.rtt as u128);
+        }
+        self.log_metrics(sample);
+        self.ack_num = header.sequence_number + 1;
+        self.last_heard = Some(cur_time);
+        // Everything before the SYN's ACK is acknowledged
+        self.pre_ack = header.ack_number;
+        self.peer_port = header.source_port;
+        if !token.is_empty() {
+            self.issued_token = Some(token.to_vec());
+        }
+        if resumed {
+            debug!("resumed");
+            // No handshake ACK to wait for
+            self.cur_buf -= 1;
+            self.established = Some(cur_time);
+            self.set_status(Status::Sending);
+            return dropped;
+        }
+
+        // After handshake, send data. The ACK goes out before the keys are in use, the receiver
+        // derives them from what it carries.
+        // Data sent under the keys of our token is opened again the way the receiver would have
+        let early = self.token.as_ref().map(|token| Protection {
+            psk: self.protection.psk.clone(),
+            keys: (self.protection.psk.as_ref()).map(|psk| Keys::derive(psk, &self.random, token, false)),
+            session: self.protection.session.take(),
+        });
+        self.protection.keys = None;
+        let next_seq = self.seq_num;
+        let mut header = self.new_header(0b0001_0000, 0, SeqNum(0));
+        header.sequence_number = packet.seq_num + 1;
+        self.register_packet(header, &reply, None);
+        self.protection.keys = keys.or_else(|| self.handshake.take().map(Handshake::into_keys));
+        // Without keys, the rest of the connection is authenticated under a key from the cookie
+        if self.protection.keys.is_none() {
+            self.protection.session = Some(session_key(cookie, self.connection_id));
+        }
+        // The receiver didn't take our token, data sent along with the SYN goes again behind the ACK
+        if let Some(early) = early {
+            debug!("resumption token not taken");
+            self.seq_num = next_seq;
+            self.in_flight.rotate_right(1);
+            self.resend_early(&early);
+        }
+        self.set_status(Status::Sending); // Change status to sending
+        dropped
+    }
+
+    // Drop the queued unordered records of more than max bytes, the error names the largest and the count
+    fn drop_unordered(&mut self, max: usize) -> Result<(), String> {
+        let mut dropped = Vec::new();
+        for stream in self.streams.values_mut() {
+            stream.data.retain(|segment| {
+                let fits = segment.frame_flags & UNORDERED == 0 || segment.data.len() <= max;
+                if !fits {
+                    dropped.push(segment.data.len());
+                }
+                fits
+            });
+        }
+        match dropped.iter().max() {
+            None => Ok(()),
+            Some(largest) => Err(format!(
+                "Unordered record of {largest} bytes doesn't fit in a segment of {max} bytes, {} dropped",
+                dropped.len()
+            )),
+        }
+    }
+
+    // Send the SYN again, same sequence number and key material, with the receiver's cookie. It is padded to
+    // the size of the SYN-ACK that answers it: the cookie, the largest Noise message and the datagram size.
+    fn retry_syn(&mut self, cookie: &[u8]) {
+        let syn = self.in_flight.pop_front().unwrap();
+        let mut payload = syn.data[HEADER_SIZE..][..self.material_len()].to_vec();
+        payload.extend_from_slice(cookie);
+        payload.resize(payload.len().max(COOKIE_SIZE + MAX_MESSAGE), 0);
+        payload.extend_from_slice(&self.max_datagram.to_be_bytes());
+        debug!("retry");
+        self.seq_num = syn.seq_num;
+        let mut header = self.new_header(0b0000_0010, 0, SeqNum(0));
+        header.frame_flags = RETRY | MSS;
+        self.register_packet(header, &payload, None);
+    }
+
+    // Size of the key material our SYN starts with: our random with a pre-shared key, the first Noise message
+    // with an identity
+    fn material_len(&self) -> usize {
+        match (&self.protection.psk, &self.identity) {
+            (Some(_), _) => RANDOM_SIZE,
+            (None, Some(_)) => KEY_SIZE,
+            (None, None) => 0,
+        }
+    }
+
+    // Seal the data sent under the keys of a token the receiver didn't take again under the connection's keys,
+    // and send it right away. The receiver dropped it.
+    fn resend_early(&mut self, early: &Protection) {
+        for packet in self.in_flight.iter_mut().filter(|packet| packet.stream_id.is_some()) {
+            // Packets in flight were built by us, they always start with a header
+            let mut header = TcpHeader::new(&packet.data).unwrap();
+            let data = early.open(&header, &packet.data[HEADER_SIZE..]).unwrap();
+            packet.data = self.protection.seal(&mut header, &data);
+            packet.timestamp = self.clock.now();
+            Self::send_data(
+                &self.remote_host,
+                &self.remote_port,
+                packet.data.as_slice(),
+                self.socket.as_ref(),
+                &mut self.stats,
+            );
+        }
+    }
+
+    // Handle a packet received while sending data
+    fn handle_ack(&mut self, buf: &[u8]) {
+        let header = match TcpHeader::new(buf) {
+            Ok(header) => header,
+            Err(_) => return,
+        };
+        self.log_packet("transport:packet_received", &header, buf.len(), None);
+
+        if header.flags == RST {
+            self.handle_reset(&header, &buf[HEADER_SIZE..]);
+            return;
+        }
+
+        // An ACK of a connection we don't have, its receiver would wait for it forever
+        if header.flags == 16 && header.connection_id != self.connection_id {
+            self.log_dropped("connection_id_mismatch");
+            self.send_reset(&header);
+            return;
+        }
+
+        // The receiver is validating our (new) address, echo the challenge back
+        if header.flags == PATH_CHALLENGE {
+            if header.connection_id == self.connection_id {
+                if let Some(token) = self.protection.open(&header, &buf[HEADER_SIZE..]) {
+                    self.send_path_response(&token);
                 }
-                // Sending data
-                Status::Sending => {
-                    eprintln!("Sending");
-                    while !self.in_flight.is_empty() || !self.data.is_empty() {
-                        self.check_retransmission();
-
-                        let mut buf: [u8; 1500] = [0; 1500];
-                        match self.socket.recv(&mut buf) {
-                            Ok(_) => {
-                                let header = TcpHeader::new(&buf[..48]);
-
-                                // Check if the hash value of the header matches the hash value in the header
-                                if !Self::check_hash(&header) {
-                                    eprintln!("Sending hash mismatch");
-                                    continue;
-                                }
-
-                                if header.flags != 16 {
-                                    // ACK = 16
-                                    eprintln!("Sending flag mismatch");
-                                    continue;
-                                }
-                                // Adjust cwnd and ssthresh
-                                if header.ack_number == self.pre_ack {
-                                    self.count += 1;
-                                    if self.count >= 3 {
-                                        Self::send_data(
-                                            &self.remote_host,
-                                            &self.remote_port,
-                                            self.in_flight[0].data.as_slice(),
-                                            &self.socket,
-                                        );
-                                        self.update_cwnd(self.cwnd / 2);
-                                        self.count = 0;
-                                    }
-                                }
-                                // if not duplicate ack
-                                else {
-                                    self.count = 0;
-
-                                    if self.cwnd > self.ssthresh {
-                                        self.update_cwnd(self.cwnd + 2);
-                                    } else {
-                                        self.update_cwnd(self.cwnd << 1);
-                                    }
-
-                                    // Based on the acknowledgment number in the received packet, pop the packet in the in_flight queue.
-                                    match Self::find_packet_index(
-                                        &self.in_flight,
-                                        header.ack_number,
-                                    ) {
-                                        Ok(ind) => {
-                                            let cur_time = Instant::now();
-                                            let mut rtt = 0;
-                                            // oops through and removes all packets up to and including the packet that was acknowledged.
-                                            for i in 0..=ind {
-                                                let packet = self.in_flight.pop_front().unwrap();
-                                                self.cur_buf -= packet.data_len;
-                                                rtt += cur_time.duration_since(packet.timestamp).as_millis();
-                                            }
-                                            
-                                            // Calculate the average rtt
-                                            rtt = rtt / (ind as u128 + 1);
-                                            eprintln!("rtt: {}ms", rtt);
-                                            self.update_rto(rtt);
-                                            // Updates pre_ack to the acknowledgment number from the received packet.
-                                            self.pre_ack = header.ack_number;
-                                        }
-                                        Err(_) => {}
-                                    }
-                                    // Converts any payload data in the received packet (beyond the TCP header) to a string.
-                                    let fragment = read_to_string(&buf[16..]);
-                                    self.ack_num =
-                                        safe_increment(self.ack_num, fragment.len() as u32);
-                                }
-
-                                eprintln!("cwnd: {}", self.cwnd);
-                                eprintln!("cur_wnd: {}", self.cur_wnd);
-                                eprintln!("cur_buf: {}", self.cur_buf);
-                                eprintln!("pre_ack: {}", self.pre_ack);
-                                eprintln!("in flight: {}", self.in_flight.len());
-                                eprintln!("ssthresh: {}", self.ssthresh);
-
-                                buf.fill(0);
-                            }
-                            Err(_) => {}
-                        }
-
-                        // Send data if there is enough space in sliding window
-                        while !self.data.is_empty()
-                            && self.cur_wnd > self.cur_buf
-                            && (self.cur_wnd - self.cur_buf) > self.data[0].len() as u16
-                        {
-                            let packet_data = self.data.pop_front().unwrap();
-                            let mut header = TcpHeader {
-                                source_port: self.local_port,
-                                destination_port: self.remote_port,
-                                sequence_number: self.seq_num,
-                                ack_number: self.ack_num,
-                                header_length: 4,
-                                flags: 0b0001_1000,
-                                window_size: self.wnd_size,
-                                hash_value: [0; 32].into(), // testing
-                            };
-
-                            // Hash header and data
-                            header.hash_value =
-                                header.calculate_header_data_hash(packet_data.as_bytes());
-
-                            self.register_packet(header, &packet_data);
-                            self.cur_buf += packet_data.len() as u16;
-                        }
+            }
+            return;
+        }
+
+        // The receiver answered a copy of our SYN again, our handshake ACK may be lost
+        if header.flags == 0b0001_0010 {
+            self.handle_duplicate_syn_ack(&header, &buf[HEADER_SIZE..]);
+            return;
+        }
+
+        // Check the hash of the header and SACK blocks, or open them with a pre-shared key
+        let sack = match self.protection.open(&header, &buf[HEADER_SIZE..]) {
+            Some(sack) => sack,
+            None => {
+                self.stats.corrupted += 1;
+                self.log_dropped("hash_mismatch");
+                return;
+            }
+        };
+
+        if header.connection_id != self.connection_id {
+            self.log_dropped("connection_id_mismatch");
+            return;
+        }
+
+        if header.flags != 16 {
+            // ACK = 16
+            debug!(flags = header.flags, "unexpected flags");
+            return;
+        }
+
+        // The ports stay those of the handshake, whatever the addresses of the datagrams
+        if header.source_port != self.peer_port || header.destination_port != self.local_port {
+            self.log_dropped("port_mismatch");
+            return;
+        }
+
+        // An ACK behind the latest one arrived late and carries no news
+        if header.ack_number < self.pre_ack {
+            return;
+        }
+
+        // The ACK carries the flow-control credit of the stream it was sent for
+        let mut credit_changed = false;
+        self.last_heard = Some(self.clock.now());
+        if let Some(stream) = self.streams.get_mut(&header.stream_id) {
+            credit_changed = stream.credit != header.window_size as u32;
+            stream.credit = header.window_size as u32;
+        }
+
+        // Adjust cwnd and ssthresh
+        if header.ack_number == self.pre_ack {
+            // A pure window update is not a sign of loss, nor the answer to a keepalive probe
+            if !credit_changed && !self.in_flight.is_empty() {
+                self.count += 1;
+                self.stats.duplicates += 1;
+            }
+            if self.count >= 3 {
+                // Nothing to resend if the duplicates only came while we wait for credit
+                if let Some(packet) = self.in_flight.front() {
+                    Self::send_data(
+                        &self.remote_host,
+                        &self.remote_port,
+                        packet.data.as_slice(),
+                        self.socket.as_ref(),
+                        &mut self.stats,
+                    );
+                    self.stats.fast_retransmits += 1;
+                    debug!(seq = %packet.seq_num, "fast retransmit");
+                    let resent = TcpHeader::new(&packet.data).unwrap();
+                    let len = packet.data.len();
+                    self.log_lost(&resent, len, "fast_retransmit");
+                }
+                self.update_cwnd(self.cwnd / 2);
+                self.count = 0;
+                self.log_metrics(None);
+            }
+        }
+        // if not duplicate ack
+        else {
+            self.count = 0;
+
+            if self.cwnd > self.ssthresh {
+                self.update_cwnd(self.cwnd + 2);
+            } else {
+                self.update_cwnd(self.cwnd << 1);
+            }
+
+            // Based on the acknowledgment number in the received packet, pop the packet in the in_flight queue.
+            if let Some(ind) = Self::find_packet_index(&self.in_flight, header.ack_number) {
+                let cur_time = self.clock.now();
+                let mut rtt = 0;
+                let mut samples = 0;
+                let mut probe = None;
+                // oops through and removes all packets up to and including the packet that was acknowledged.
+                for _ in 0..=ind {
+                    let packet = self.in_flight.pop_front().unwrap();
+                    self.cur_buf -= packet.data_len;
+                    if let Some(stream) = packet.stream_id.and_then(|id| self.streams.get_mut(&id)) {
+                        stream.in_flight -= packet.data_len as u32;
+                    }
+                    // We can't tell which copy of a retransmitted packet is acknowledged (Karn), and a packet
+                    // that arrived before the ACK was sent waited for a hole to fill rather than for the path
+                    if packet.retransmits == 0 && !packet.sacked && packet.confirm_ack == header.ack_number {
+                        self.stats.rtt(cur_time.duration_since(packet.timestamp));
+                        rtt += cur_time.duration_since(packet.timestamp).as_millis();
+                        samples += 1;
+                    }
+                    // Skip markers stand for data that was given up on
+                    if packet.stream_id.is_some()
+                        && TcpHeader::new(&packet.data).is_ok_and(|h| h.frame_flags & FORWARD == 0)
+                    {
+                        self.stats.payload_bytes += (packet.data.len() - HEADER_SIZE) as u64;
+                    }
+                    if packet.stream_id.is_none() {
+                        self.established = Some(cur_time);
+                    }
+                    if self.mtu_probe == Some(packet.seq_num) {
+                        probe = Some(packet.data.len() as u16);
+                    }
+                    if packet.data.len() > BASE_PLPMTU as usize {
+                        self.mtu_heard = Some(cur_time);
                     }
-                    self.status = Status::Finished;
                 }
-                // After sending all data, send last packet to tell receiver that it's finished
-                Status::Finished => {
-                    eprintln!("Finished");
-                    eprintln!("rto: {}ms", self.rto);
-                    break;
+                if let Some(size) = probe {
+                    self.mtu_probe_acked(size);
                 }
+
+                // Calculate the average rtt
+                let sample = rtt.checked_div(samples);
+                if let Some(rtt) = sample {
+                    debug!(rtt_ms = rtt as u64, "RTT sample");
+                    self.update_rto(rtt);
+                }
+                self.log_metrics(sample);
+                // Updates pre_ack to the acknowledgment number from the received packet.
+                self.pre_ack = header.ack_number;
+                self.backed_off = false;
             }
         }
-        Ok(())
+
+        // Segments the receiver already holds beyond the cumulative ACK are not retransmitted
+        let mut probe = None;
+        let mut sample = None;
+        for (start, end) in decode_sack(&sack) {
+            for packet in self.in_flight.iter_mut() {
+                if packet.seq_num.in_range(start, end) && packet.confirm_ack <= end {
+                    // Newly held segments are what the ACK answers, they time the path while the cumulative ACK
+                    // waits for a hole
+                    if !packet.sacked && packet.retransmits == 0 {
+                        sample = Some(self.clock.now().duration_since(packet.timestamp));
+                    }
+                    packet.sacked = true;
+                    if self.mtu_probe == Some(packet.seq_num) {
+                        probe = Some(packet.data.len() as u16);
+                    }
+                    if packet.data.len() > BASE_PLPMTU as usize {
+                        self.mtu_heard = Some(self.clock.now());
+                    }
+                }
+            }
+        }
+        if let Some(size) = probe {
+            self.mtu_probe_acked(size);
+        }
+        if let Some(rtt) = sample {
+            debug!(rtt_ms = rtt.as_millis() as u64, "RTT sample");
+            self.stats.rtt(rtt);
+            self.update_rto(rtt.as_millis());
+            self.log_metrics(Some(rtt.as_millis()));
+        }
+
+        trace!(
+            cwnd = self.cwnd,
+            cur_wnd = self.cur_wnd,
+            cur_buf = self.cur_buf,
+            pre_ack = %self.pre_ack,
+            in_flight = self.in_flight.len(),
+            ssthresh = self.ssthresh,
+            "ACK handled"
+        );
     }
 
-    // Helper function to check if the hash value of the header matches the hash value in the header
-    fn check_hash(header: &TcpHeader) -> bool {
-        let hash = header.calculate_header_hash();
-        hash == header.hash_value
+    // Send data if there is enough space in sliding window, taking turns between streams
+    fn send_segments(&mut self) {
+        self.update_mtu_search();
+        while let Some(stream_id) = self.next_sendable_stream() {
+            let now = self.clock.now();
+            let size = self.segment_size(&self.streams[&stream_id]);
+            let stream = self.streams.get_mut(&stream_id).unwrap();
+
+            // Don't bother sending a message that is already stale
+            if let Some(message) = stream.data[0].message.filter(|m| m.expired(0, now)) {
+                self.abandon_message(stream_id, message.id);
+                continue;
+            }
+
+            let segment = stream.pop_segment(size);
+            let packet_data = segment.data;
+            // Like on the connection, an empty segment still takes one sequence number
+            let seg_len = segment_len(segment.frame_flags, &packet_data) as u16;
+            let stream_seq = stream.seq_num;
+            stream.seq_num += seg_len as u32;
+            stream.in_flight += seg_len as u32;
+
+            let mut header = self.new_header(0b0001_1000, stream_id, stream_seq);
+            header.frame_flags = segment.frame_flags;
+            self.register_packet(header, &packet_data, Some(stream_id));
+            self.in_flight.back_mut().unwrap().message = segment.message;
+            self.cur_buf += seg_len;
+            self.last_stream = stream_id;
+            if size > self.mss() as usize {
+                debug!(size = HEADER_SIZE + size, "path MTU probe");
+                self.mtu_probe = Some(self.in_flight.back().unwrap().seq_num);
+            }
+        }
     }
 
-    // Helper function to check if the hash value of the header and data matches the hash value in the header
-    fn check_header_data_hash(header: &TcpHeader, data: &[u8]) -> bool {
-        let hash = header.calculate_header_data_hash(data);
-        hash == header.hash_value
+    // Pick the stream after the last one served that has data, credit and room in the congestion window
+    fn next_sendable_stream(&self) -> Option<u16> {
+        let fits = |stream: &SendStream| {
+            let len = stream.next_len(self.segment_size(stream)) as u16;
+            self.cur_wnd > self.cur_buf && (self.cur_wnd - self.cur_buf) > len
+        };
+
+        self.streams
+            .range(self.last_stream.saturating_add(1)..)
+            .chain(self.streams.range(..=self.last_stream))
+            .find(|(_, stream)| stream.can_send() && fits(stream))
+            .map(|(id, _)| *id)
+    }
+
+    // Build a header for this connection
+    fn new_header(&self, flags: u8, stream_id: u16, stream_seq: SeqNum) -> TcpHeader {
+        TcpHeader {
+            source_port: self.local_port,
+            destination_port: self.remote_port, // simulator's port
+            sequence_number: self.seq_num,
+            ack_number: self.ack_num,
+            header_length: 4, // unit of 4 bytes
+            frame_flags: 0,
+            flags,
+            window_size: self.wnd_size,
+            connection_id: self.connection_id,
+            stream_id,
+            stream_seq,
+            hash_value: [0; 32], // testing
+        }
     }
 
     // Update RTO using RTT
     fn update_rto(&mut self, rtt: u128) {
         self.rtt = (self.rtt * 85 / 100) + (rtt * 15 / 100) as u64;
         // Upper and lower bound of the rtt
-        if self.rtt < 5 {
-            self.rtt = 5;
-        } else if self.rtt > 1200 {
-            self.rtt = 1200;
-        }
+        self.rtt = self.rtt.clamp(5, 1200);
         // Calculate rto more aggressively
         self.rto = self.rtt * 9 / 5;
     }
 
-    // Find the index of the packet with the given ack number
-    fn find_packet_index(in_flight: &VecDeque<Packet>, ack_num: u32) -> Result<usize, ()> {
-        for (ind, packet) in in_flight.iter().enumerate() {
-            if packet.confirm_ack == ack_num {
-                return Ok(ind);
-            }
+    // RTO of the packets in flight, doubled while resent packets keep timing out. They give no RTT sample (Karn),
+    // so once the path is slower than the RTO, on a long queue, everything in flight would otherwise time out over
+    // and over without the RTO ever catching up.
+    fn current_rto(&self) -> u64 {
+        if self.backed_off {
+            self.rto * 2
+        } else {
+            self.rto
         }
-        Err(())
+    }
+
+    // Find the index of the last packet covered by the given cumulative ack number
+    fn find_packet_index(in_flight: &VecDeque<Packet>, ack_num: SeqNum) -> Option<usize> {
+        in_flight
+            .iter()
+            .rposition(|packet| packet.confirm_ack <= ack_num)
     }
 
     // Prepare and send a packet
-    fn register_packet(&mut self, header: TcpHeader, data: &str) {
-        let mut packet_data: Vec<u8> = Vec::new();
+    fn register_packet(&mut self, mut header: TcpHeader, data: &[u8], stream_id: Option<u16>) {
         let seq_num = header.sequence_number;
-        let ack_num = header.ack_number;
 
-        // Converts the TCP header into its byte representation and appends each byte to packet_data.
-        for d in header.as_bytes() {
-            packet_data.push(d);
-        }
+        // Hash or seal the packet, its bytes are kept as they are for retransmission
+        let packet_data = self.protection.seal(&mut header, data);
 
-        // Encodes the packet's data (payload) into bytes and appends it to packet_data, while also calculating the data length.
-        let mut data_len: u16 = 0;
-        for d in data.as_bytes() {
-            packet_data.push(*d);
-            data_len += 1;
-        }
-
-        if data_len == 0 {
-            data_len = 1;
-        }
+        // Handshake packets take one sequence number, whatever key material they carry
+        let data_len = match stream_id {
+            Some(_) => segment_len(header.frame_flags, data) as u16,
+            None => 1,
+        };
 
         let packet = Packet {
-            timestamp: Instant::now(),
+            timestamp: self.clock.now(),
+            first_sent: self.clock.now(),
             data: packet_data.clone(),
             seq_num,
-            ack_num,
-            confirm_ack: safe_increment(seq_num, data_len as u32), // Ack number supposed to be, used for retransmission
-            data_len,                                              // length for data
+            confirm_ack: seq_num + data_len as u32, // Ack number supposed to be, used for retransmission
+            data_len,                               // length for data
+            stream_id,
+            message: None,
+            retransmits: 0,
+            sacked: false,
         };
 
         // Adds the constructed packet to a queue (in_flight) of packets that have been sent but not yet acknowledged.
         self.in_flight.push_back(packet);
+        self.log_packet("transport:packet_sent", &header, packet_data.len(), None);
+
+        Self::send_data(
+            &self.remote_host,
+            &self.remote_port,
+            packet_data.as_slice(),
+            self.socket.as_ref(),
+            &mut self.stats,
+        );
 
+        self.seq_num = seq_num + data_len as u32;
+    }
+
+    // Answer a path challenge from the receiver by echoing its token
+    fn send_path_response(&mut self, token: &[u8]) {
+        let mut header = self.new_header(PATH_RESPONSE, 0, SeqNum(0));
+        let packet_data = self.protection.seal(&mut header, token);
         Self::send_data(
             &self.remote_host,
             &self.remote_port,
             packet_data.as_slice(),
-            &self.socket,
+            self.socket.as_ref(),
+            &mut self.stats,
         );
+        self.log_packet("transport:packet_sent", &header, packet_data.len(), None);
+    }
+
+    // Give up on the connection once the receiver reset it. The RST must be for our connection and its ports,
+    // pass the check it can make without the connection's keys, and carry a sequence number in our window.
+    fn handle_reset(&mut self, header: &TcpHeader, data: &[u8]) {
+        let valid = header.connection_id == self.connection_id
+            && header.source_port == self.peer_port
+            && header.destination_port == self.local_port
+            && header.sequence_number.in_range(self.ack_num, self.ack_num + self.wnd_size as u32)
+            && self.protection.open_unkeyed(header, data).is_some();
+        if !valid {
+            self.log_dropped("invalid_reset");
+            return;
+        }
+        // It must answer a segment still in flight, first sent once the receiver acknowledged our handshake ACK:
+        // before that, the receiver answers nothing but the handshake ACK itself, with a RST if it doesn't let
+        // us in. A copy of the segment resent later can't tell a RST of the connection from an older one.
+        let answered = self.in_flight.iter().find(|packet| packet.seq_num == header.ack_number);
+        if answered.is_some_and(|packet| packet.stream_id.is_none()) && self.established.is_none() {
+            warn!("handshake refused by the receiver");
+            self.set_status(Status::Reset);
+            return;
+        }
+        let fresh = answered.zip(self.established).is_some_and(|(packet, at)| packet.first_sent >= at);
+        if !fresh {
+            self.log_dropped("stale_reset");
+            return;
+        }
+        warn!("connection reset by the receiver");
+        self.set_status(Status::Reset);
+    }
+
+    // Answer a segment that belongs to no connection of ours with a RST
+    fn send_reset(&mut self, segment: &TcpHeader) {
+        let mut header = segment.reset();
+        let packet_data = self.protection.seal_unkeyed(&mut header, &[]);
+        Self::send_data(
+            &self.remote_host,
+            &self.remote_port,
+            packet_data.as_slice(),
+            self.socket.as_ref(),
+            &mut self.stats,
+        );
+        self.log_packet("transport:packet_sent", &header, packet_data.len(), Some("reset"));
+    }
+
+    // Probe the receiver with a pure ACK, it answers with an ACK of its own
+    fn send_keepalive(&mut self) {
+        if self.keepalive_due().is_none_or(|due| self.clock.now() < due) {
+            return;
+        }
+        // Probes have nonces of their own: the frame flag sets them apart from the handshake ACK, and the
+        // stream sequence number counts them. It never comes around, the idle timeout takes over instead.
+        let Some(seq) = self.keepalive_seq.checked_add(1) else {
+            return;
+        };
+        self.keepalive_seq = seq;
+        let mut header = self.new_header(0b0001_0000, 0, SeqNum(seq));
+        header.frame_flags = KEEPALIVE;
+        let packet_data = self.protection.seal(&mut header, &[]);
+        Self::send_data(
+            &self.remote_host,
+            &self.remote_port,
+            packet_data.as_slice(),
+            self.socket.as_ref(),
+            &mut self.stats,
+        );
+        self.last_probe = Some(self.clock.now());
+        self.log_packet("transport:packet_sent", &header, packet_data.len(), Some("keepalive"));
+    }
 
-        self.seq_num = safe_increment(seq_num, data_len as u32);
+    // Payload bytes of the largest unordered record, it fits the base size and what the receiver takes
+    fn max_unordered(&self) -> usize {
+        MAX_UNORDERED.min(self.max_plpmtu as usize - HEADER_SIZE)
+    }
+
+    // Payload bytes that fit in a datagram of the path MTU
+    fn mss(&self) -> u16 {
+        self.plpmtu - HEADER_SIZE as u16
+    }
+
+    // Path MTU discovery with the data itself: while searching, a segment of the size searched for goes out
+    // once a stream has the data to fill it, one at a time. The largest size first, then halfway between
+    // what got through and what didn't.
+    fn probe_size(&self) -> Option<usize> {
+        if self.mtu_probe.is_some() || self.mtu_raise.is_some() {
+            return None;
+        }
+        let size = match self.mtu_ceiling {
+            ceiling if ceiling == self.max_plpmtu => ceiling as usize,
+            ceiling => (self.plpmtu as usize + ceiling as usize).div_ceil(2),
+        };
+        Some(size - HEADER_SIZE)
+    }
+
+    // Payload bytes of the next segment of a stream: a probe if the search is after one, what fits the path otherwise
+    fn segment_size(&self, stream: &SendStream) -> usize {
+        match self.probe_size() {
+            Some(size) if stream.next_len(size) == size => size,
+            _ => self.mss() as usize,
+        }
+    }
+
+    // The search is over once what got through and what didn't are this close, and starts over once the path
+    // may carry more
+    fn update_mtu_search(&mut self) {
+        let now = self.clock.now();
+        match self.mtu_raise {
+            Some(raise) if now < raise => return,
+            Some(_) => {
+                self.mtu_raise = None;
+                self.mtu_ceiling = self.max_plpmtu;
+            }
+            None => {}
+        }
+        if self.mtu_ceiling < self.plpmtu.saturating_add(PROBE_GRANULARITY) {
+            debug!(plpmtu = self.plpmtu, "path MTU search done");
+            self.mtu_raise = Some(now + RAISE_TIMER);
+        }
+    }
+
+    // The receiver holds a probe, datagrams of its size get through
+    fn mtu_probe_acked(&mut self, size: u16) {
+        self.mtu_probe = None;
+        self.mtu_lost = 0;
+        self.mtu_ceiling = self.mtu_ceiling.max(size);
+        self.set_plpmtu(size);
+    }
+
+    // A probe went missing, lost like any segment or too large for the path. Its data goes again in pieces that fit,
+    // without counting as congestion. After MAX_PROBES of a size, the size is taken not to get through.
+    fn mtu_probe_lost(&mut self, seq_num: SeqNum, size: u16) {
+        debug!(size, "path MTU probe lost");
+        self.mtu_probe = None;
+        self.mtu_lost += 1;
+        if self.mtu_lost >= MAX_PROBES {
+            self.mtu_ceiling = size - 1;
+            self.mtu_lost = 0;
+        }
+        self.split(&[seq_num]);
+    }
+
+    fn set_plpmtu(&mut self, plpmtu: u16) {
+        debug!(old = self.plpmtu, new = plpmtu, "path MTU updated");
+        self.log("connectivity:mtu_updated", json!({ "old": self.plpmtu, "new": plpmtu }));
+        self.plpmtu = plpmtu;
+        // The congestion window counts segments, its bytes follow their size
+        self.update_cwnd(self.cwnd);
+    }
+
+    // Cut these segments in flight, which don't fit the path, into pieces that do. The pieces go out right away.
+    fn split(&mut self, seq_nums: &[SeqNum]) {
+        let size = self.mss() as usize;
+        let now = self.clock.now();
+        let mut in_flight = VecDeque::with_capacity(self.in_flight.len());
+        for packet in mem::take(&mut self.in_flight) {
+            if !seq_nums.contains(&packet.seq_num) {
+                in_flight.push_back(packet);
+                continue;
+            }
+            // Packets in flight were built by us, they always start with a header
+            let header = TcpHeader::new(&packet.data).unwrap();
+            let data = self.protection.open_own(&header, &packet.data[HEADER_SIZE..]).unwrap();
+            let pieces = data.len().div_ceil(size);
+            for (i, piece) in data.chunks(size).enumerate() {
+                let offset = (i * size) as u32;
+                let mut header = TcpHeader::new(&packet.data).unwrap();
+                header.sequence_number += offset;
+                header.stream_seq += offset;
+                if i > 0 {
+                    header.frame_flags &= !MSG_START;
+                }
+                if i + 1 < pieces {
+                    header.frame_flags &= !MSG_END;
+                }
+                let packet_data = self.protection.seal(&mut header, piece);
+                Self::send_data(
+                    &self.remote_host,
+                    &self.remote_port,
+                    packet_data.as_slice(),
+                    self.socket.as_ref(),
+                    &mut self.stats,
+                );
+                self.log_packet("transport:packet_sent", &header, packet_data.len(), Some("mtu_split"));
+                in_flight.push_back(Packet {
+                    timestamp: now,
+                    data: packet_data,
+                    seq_num: header.sequence_number,
+                    confirm_ack: header.sequence_number + piece.len() as u32,
+                    data_len: piece.len() as u16,
+                    ..packet.clone()
+                });
+            }
+        }
+        self.in_flight = in_flight;
     }
 
     // Manage the retransmission of packets that have not been acknowledged within a certain timeout period.
@@ -425,25 +1574,84 @@ impl Sender {
         // Keep track of the number of the packets being re-transmitted
         let mut cnt: u16 = 0;
 
+        // Messages that ran out of reliability while retransmitting
+        let mut abandoned = Vec::new();
+        // Packet resent as many times as allowed, if any
+        let mut gave_up = None;
+        // Headers and lengths of the packets resent, for the event log
+        let mut resent = Vec::new();
+        // A segment above the base size keeps timing out, the path may have stopped carrying it
+        let mut black_hole = false;
+        let syn = matches!(self.status, Status::Handshake);
+
+        // Segments that don't fit the path anymore, they go again in pieces
+        let mut too_large = Vec::new();
+
+        // A probe is lost once the receiver holds data sent after it. Timeouts fire early on a slow queue, so only
+        // the last segment in flight, which nothing can overtake, is lost when it times out.
+        if let Some(i) = self.mtu_probe.and_then(|seq| self.in_flight.iter().position(|p| p.seq_num == seq)) {
+            let now = self.clock.now();
+            let probe = &self.in_flight[i];
+            let overtaken = self.in_flight.iter().skip(i + 1).any(|packet| packet.sacked);
+            let timed_out = i + 1 == self.in_flight.len()
+                && now.duration_since(probe.timestamp) >= Self::timeout(self.current_rto(), probe, syn);
+            if !probe.sacked && (overtaken || timed_out) {
+                self.mtu_probe_lost(probe.seq_num, probe.data.len() as u16);
+            }
+        }
+
         // Iterates over the packets currently in flight (sent but not yet acknowledged) with mutable access.
+        let rto = self.current_rto();
+        let mut timed_out_again = false;
         for packet in self.in_flight.iter_mut() {
             // Current time
-            let instant = Instant::now();
-            let duration = instant.duration_since(packet.timestamp.clone());
+            let instant = self.clock.now();
+            let duration = instant.duration_since(packet.timestamp);
 
-            if duration >= Duration::from_millis(self.rto) {
-                Self::send_data(
-                    &self.remote_host,
-                    &self.remote_port,
-                    packet.data.as_slice(),
-                    &self.socket,
-                );
-                packet.timestamp = Instant::now();
-                eprintln!(
-                    "resent: {}, since last sent: {}, ret: {}",
-                    packet.confirm_ack,
-                    duration.as_millis(),
-                    self.rto
+            if packet.sacked || self.mtu_probe == Some(packet.seq_num) {
+                continue;
+            }
+
+            if duration >= Self::timeout(rto, packet, syn) {
+                // The receiver is gone, or the path to it
+                if self.max_retransmits.is_some_and(|max| packet.retransmits >= max) {
+                    gave_up = Some(packet.seq_num);
+                    break;
+                }
+
+                // Send a skip marker in place of a stale message
+                if let Some(message) = packet.message.filter(|m| m.expired(packet.retransmits + 1, instant)) {
+                    abandoned.push((packet.stream_id.unwrap(), message.id));
+                    Self::forward_packet(packet, &self.protection);
+                }
+                timed_out_again |= packet.retransmits > 0 && packet.stream_id.is_some();
+                packet.retransmits += 1;
+                // Unless segments as large got through since it was last sent
+                black_hole |= packet.retransmits >= BLACK_HOLE_RETRANSMITS
+                    && packet.data.len() > BASE_PLPMTU as usize
+                    && self.mtu_heard.is_none_or(|heard| heard < packet.timestamp);
+
+                self.stats.timeout_retransmits += 1;
+                if packet.data.len() > self.plpmtu as usize {
+                    too_large.push(packet.seq_num);
+                } else {
+                    Self::send_data(
+                        &self.remote_host,
+                        &self.remote_port,
+                        packet.data.as_slice(),
+                        self.socket.as_ref(),
+                        &mut self.stats,
+                    );
+                    packet.timestamp = instant;
+                    if self.qlog.is_some() {
+                        resent.push((TcpHeader::new(&packet.data).unwrap(), packet.data.len()));
+                    }
+                }
+                debug!(
+                    confirm_ack = %packet.confirm_ack,
+                    since_sent_ms = duration.as_millis() as u64,
+                    rto_ms = self.rto,
+                    "retransmit after timeout"
                 );
 
                 if is_first {
@@ -451,30 +1659,170 @@ impl Sender {
                     is_first = false;
                 }
 
-                // num -= 1;
                 cnt += 1;
 
                 if cnt == self.ssthresh {
                     break;
                 }
-            } else {
+            } else if packet.stream_id.is_some() {
                 break;
             }
         }
+        for (header, len) in resent {
+            self.log_lost(&header, len, "retransmit_timeout");
+        }
+        self.split(&too_large);
+        if let Some(seq) = gave_up {
+            warn!(%seq, max = self.max_retransmits.unwrap(), "too many retransmissions");
+            self.set_status(Status::TimedOut);
+        }
+
         // Reduce cwnd while retransmission happens
         if !is_first {
+            self.backed_off |= timed_out_again;
             self.update_cwnd(self.cwnd * 3 / 4);
+            self.log_metrics(None);
         }
+
+        for (stream_id, message_id) in abandoned {
+            self.abandon_message(stream_id, message_id);
+        }
+
+        // The path may have stopped carrying segments of the size in use: back to the base size, the search starts
+        // over from there. Segments in flight that are larger go again in pieces as they time out.
+        if black_hole && self.plpmtu > BASE_PLPMTU {
+            warn!(plpmtu = self.plpmtu, "large datagrams don't get through anymore");
+            self.mtu_ceiling = self.max_plpmtu;
+            self.mtu_probe = None;
+            self.mtu_lost = 0;
+            self.mtu_raise = None;
+            self.set_plpmtu(BASE_PLPMTU);
+        }
+    }
+
+    // How long a packet waits for its ACK. The SYN backs off, a receiver that doesn't answer it may be
+    // far away, down or overloaded. Everything after it, and data sent along with it, keeps the RTO of the
+    // connection, but our handshake ACK only waits a little over a round trip: the receiver drops what we
+    // send behind it until it arrives. It is also resent as soon as the receiver shows it is still waiting.
+    fn timeout(rto: u64, packet: &Packet, syn: bool) -> Duration {
+        let rto = Duration::from_millis(rto);
+        if syn && packet.stream_id.is_none() {
+            (rto * (1 << packet.retransmits.min(16))).min(MAX_SYN_TIMEOUT.max(rto))
+        } else if packet.stream_id.is_none() {
+            rto * 2 / 3
+        } else {
+            rto
+        }
+    }
+
+    // A SYN-ACK for our connection once it is set up answers a SYN resent before the first one arrived,
+    // or tells us the receiver never got our handshake ACK
+    fn handle_duplicate_syn_ack(&mut self, header: &TcpHeader, data: &[u8]) {
+        let valid = header.connection_id == self.connection_id
+            && header.source_port == self.peer_port
+            && header.destination_port == self.local_port
+            && header.sequence_number + 1 == self.ack_num
+            && header.frame_flags & RETRY == 0
+            && self.protection.open_unkeyed(header, data).is_some();
+        if !valid {
+            self.log_dropped("invalid_syn_ack");
+            return;
+        }
+        self.resend_handshake_ack("duplicate_syn_ack");
+    }
+
+    // Send our handshake ACK again without waiting for its timeout, if it is still in flight,
+    // at most once a round trip
+    fn resend_handshake_ack(&mut self, trigger: &str) {
+        let now = self.clock.now();
+        let rtt = Duration::from_millis(self.rtt);
+        let packet = match self.in_flight.iter_mut().find(|packet| packet.stream_id.is_none()) {
+            Some(packet) if now.duration_since(packet.timestamp) >= rtt => packet,
+            _ => return,
+        };
+        packet.retransmits += 1;
+        packet.timestamp = now;
+        Self::send_data(
+            &self.remote_host,
+            &self.remote_port,
+            packet.data.as_slice(),
+            self.socket.as_ref(),
+            &mut self.stats,
+        );
+        self.stats.fast_retransmits += 1;
+        debug!(trigger, "resend handshake ACK");
+        let resent = TcpHeader::new(&packet.data).unwrap();
+        let len = packet.data.len();
+        self.log_lost(&resent, len, trigger);
+    }
+
+    // Stop sending a message: its packets in flight become skip markers and its unsent segments are dropped
+    fn abandon_message(&mut self, stream_id: u16, message_id: u32) {
+        debug!(stream_id, message_id, "abandon message");
+        self.log(
+            "transport:message_abandoned",
+            json!({ "stream_id": stream_id, "message_id": message_id }),
+        );
+        for packet in self.in_flight.iter_mut() {
+            if packet.stream_id == Some(stream_id)
+                && packet.message.is_some_and(|m| m.id == message_id)
+            {
+                Self::forward_packet(packet, &self.protection);
+                // A skip marker tells nothing about the path
+                if self.mtu_probe == Some(packet.seq_num) {
+                    self.mtu_probe = None;
+                }
+            }
+        }
+
+        let stream = self.streams.get_mut(&stream_id).unwrap();
+        let queued = stream.data.len();
+        let mut started = true;
+        stream.data.retain(|segment| {
+            let ours = segment.message.is_some_and(|m| m.id == message_id);
+            if ours && segment.frame_flags & MSG_START != 0 {
+                started = false;
+            }
+            !ours
+        });
+
+        // Part of the message is out already, close it so the receiver stops skipping after it
+        if started && stream.data.len() < queued {
+            stream.data.push_front(Segment {
+                data: 1u32.to_be_bytes().to_vec(),
+                frame_flags: FORWARD | MSG_END,
+                message: None,
+            });
+        }
+    }
+
+    // Turn a packet in flight into a skip marker for the same slot, the marker itself is reliable
+    fn forward_packet(packet: &mut Packet, protection: &Protection) {
+        // Packets in flight were built by us, they always start with a header
+        let mut header = TcpHeader::new(&packet.data).unwrap();
+        let slot = (packet.data_len as u32).to_be_bytes();
+        header.frame_flags |= FORWARD;
+
+        packet.data = protection.seal(&mut header, &slot);
+        packet.message = None;
     }
+
     // Helper function to send data
-    fn send_data(remote_host: &str, remote_port: &u16, packet_data: &[u8], socket: &UdpSocket) {
+    fn send_data(
+        remote_host: &str,
+        remote_port: &u16,
+        packet_data: &[u8],
+        socket: &dyn Transport,
+        stats: &mut Stats,
+    ) {
         loop {
-            match socket.send_to(packet_data, format!("{}:{}", remote_host, remote_port)) {
+            match socket.send_to(packet_data, &format!("{}:{}", remote_host, remote_port)) {
                 Ok(_) => {
+                    stats.sent(packet_data.len());
                     break;
                 }
                 Err(e) => {
-                    eprintln!("{} -> Failed to send packet at registration", e)
+                    warn!("{} -> Failed to send packet at registration", e)
                 }
             }
         }
@@ -482,13 +1830,85 @@ impl Sender {
 
     fn update_cwnd(&mut self, mut new_value: u16) {
         // Upper and lower bound of cwnd
-        if new_value >= 45 {
-            new_value = 45;
-        } else if new_value < 2 {
-            new_value = 2;
-        }
+        new_value = new_value.clamp(2, 45);
 
         self.cwnd = new_value;
-        self.cur_wnd = new_value * DATASIZE;
+        self.cur_wnd = (new_value as u32 * self.mss() as u32).min(u16::MAX as u32) as u16;
+        self.stats.peak_cwnd = self.stats.peak_cwnd.max(self.cur_wnd as u32);
+    }
+
+    // Change status, the event log keeps track of the transitions
+    fn set_status(&mut self, status: Status) {
+        let old = format!("{:?}", self.status);
+        self.status = status;
+        let new = format!("{:?}", self.status);
+        self.state_span = match self.status {
+            Status::StandBy => info_span!(parent: &self.span, "StandBy"),
+            Status::Handshake => info_span!(parent: &self.span, "Handshake"),
+            Status::Sending => info_span!(parent: &self.span, "Sending"),
+            Status::Finished => info_span!(parent: &self.span, "Finished"),
+            Status::Reset => info_span!(parent: &self.span, "Reset"),
+            Status::TimedOut => info_span!(parent: &self.span, "TimedOut"),
+        };
+        // More data queued after finishing keeps the clock running
+        self.finished = match self.status {
+            Status::Finished => Some(self.clock.now()),
+            _ => None,
+        };
+        self.state_span.in_scope(|| info!(from = %old, "status changed"));
+        self.log(
+            "connectivity:connection_state_updated",
+            json!({ "old": old, "new": new }),
+        );
+    }
+
+    // Record an event, if the connection is traced
+    fn log(&mut self, name: &str, data: Value) {
+        if let Some(qlog) = self.qlog.as_mut() {
+            qlog.event(self.clock.now(), self.connection_id, name, data);
+        }
+    }
+
+    // Record a packet sent or received, with what made us send it
+    fn log_packet(&mut self, name: &str, header: &TcpHeader, len: usize, trigger: Option<&str>) {
+        if self.qlog.is_some() {
+            let mut data = json!({ "header": packet_header(header), "length": len });
+            if let Some(trigger) = trigger {
+                data["trigger"] = json!(trigger);
+            }
+            self.log(name, data);
+        }
+    }
+
+    // Record a packet that is declared lost and its retransmission
+    fn log_lost(&mut self, header: &TcpHeader, len: usize, trigger: &str) {
+        if self.qlog.is_some() {
+            let lost = json!({ "header": packet_header(header), "trigger": trigger });
+            self.log("recovery:packet_lost", lost);
+            self.log_packet("transport:packet_sent", header, len, Some(trigger));
+        }
+    }
+
+    // Record a packet that was thrown away
+    fn log_dropped(&mut self, trigger: &str) {
+        debug!(trigger, "packet dropped");
+        self.log("transport:packet_dropped", json!({ "trigger": trigger }));
+    }
+
+    // Record the congestion control and RTT state, with the RTT sample that changed it if any
+    fn log_metrics(&mut self, latest_rtt: Option<u128>) {
+        if self.qlog.is_some() {
+            let mut data = json!({
+                "congestion_window": self.cur_wnd,
+                "ssthresh": self.ssthresh as u32 * self.mss() as u32,
+                "bytes_in_flight": self.cur_buf,
+                "smoothed_rtt": self.rtt,
+                "rto": self.rto,
+            });
+            if let Some(rtt) = latest_rtt {
+                data["latest_rtt"] = json!(rtt as u64);
+            }
+            self.log("recovery:metrics_updated", data);
+        }
     }
 }
@@ -1,2 +1,10 @@
+pub mod capture;
+pub mod crypto;
+pub mod noise;
+pub mod qlog;
+pub mod seq;
+pub mod stats;
 pub mod tcp_header;
-pub mod util;
\ No newline at end of file
+pub mod transport;
+#[allow(clippy::module_inception)]
+pub mod util;
@@ -0,0 +1,151 @@
+use hkdf::Hkdf;
+use sha2::Sha256;
+use snow::params::{DHChoice, NoiseParams};
+use snow::resolvers::{CryptoResolver, DefaultResolver};
+use snow::{Builder, HandshakeState};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::util::crypto::Keys;
+
+// Both ends send their static key, encrypted, so neither needs to know the other beforehand
+// and either can check who it is talking to once the handshake is done
+const PATTERN: &str = "Noise_XX_25519_ChaChaPoly_SHA256";
+// Size of an X25519 key, public or private
+pub const KEY_SIZE: usize = 32;
+// Largest handshake message, the one in the SYN-ACK: ephemeral key, encrypted static key and an empty payload
+pub const MAX_MESSAGE: usize = 96;
+
+fn params() -> NoiseParams {
+    PATTERN.parse().unwrap()
+}
+
+// Static X25519 key pair an end is known by
+#[derive(Clone)]
+pub struct Identity {
+    private: [u8; KEY_SIZE],
+    public: [u8; KEY_SIZE],
+}
+
+impl Identity {
+    // A fresh key pair, only good for encryption since nobody can have pinned it
+    pub fn generate() -> Self {
+        let keypair = Builder::new(params()).generate_keypair().unwrap();
+        Self::new(&keypair.private).unwrap()
+    }
+
+    pub fn new(private: &[u8]) -> Result<Self, String> {
+        let private: [u8; KEY_SIZE] = private.try_into().map_err(|_| {
+            format!("A private key must be {KEY_SIZE} bytes, got {}", private.len())
+        })?;
+        let mut dh = DefaultResolver.resolve_dh(&DHChoice::Curve25519).unwrap();
+        dh.set(&private);
+        let public = dh.pubkey().try_into().unwrap();
+        Ok(Identity { private, public })
+    }
+
+    // Read the private key from a file, in hex
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("{e} -> Failed to read private key {}", path.display()))?;
+        Self::new(&parse_key(&text)?)
+    }
+
+    // Public key the other end can pin
+    pub fn public(&self) -> [u8; KEY_SIZE] {
+        self.public
+    }
+}
+
+impl fmt::Debug for Identity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Identity({})", to_hex(&self.public))
+    }
+}
+
+// Parse a key written in hex, surrounding whitespace is not part of it
+pub fn parse_key(text: &str) -> Result<[u8; KEY_SIZE], String> {
+    let text = text.trim();
+    if text.len() != KEY_SIZE * 2 || !text.is_ascii() {
+        return Err(format!("A key must be {} hex digits, got {text:?}", KEY_SIZE * 2));
+    }
+    let mut key = [0; KEY_SIZE];
+    for (i, byte) in key.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&text[i * 2..i * 2 + 2], 16)
+            .map_err(|e| format!("{e} -> Failed to parse key {text:?}"))?;
+    }
+    Ok(key)
+}
+
+// Key in hex, as parse_key reads it
+pub fn to_hex(key: &[u8]) -> String {
+    key.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+// Noise handshake in progress, its messages ride in the SYN, the SYN-ACK and the ACK that follows
+pub struct Handshake(HandshakeState);
+
+impl Handshake {
+    // The sender's side
+    pub fn initiator(identity: &Identity) -> Self {
+        let state = Builder::new(params())
+            .local_private_key(&identity.private)
+            .build_initiator()
+            .unwrap();
+        Handshake(state)
+    }
+
+    // The receiver's side, under a fresh ephemeral key
+    pub fn responder(identity: &Identity) -> Self {
+        let state = Builder::new(params())
+            .local_private_key(&identity.private)
+            .build_responder()
+            .unwrap();
+        Handshake(state)
+    }
+
+    // Our next message, with no payload of its own
+    pub fn write(&mut self) -> Result<Vec<u8>, String> {
+        let mut msg = vec![0; MAX_MESSAGE];
+        let len = self
+            .0
+            .write_message(&[], &mut msg)
+            .map_err(|e| format!("{e} -> Failed to write handshake message"))?;
+        msg.truncate(len);
+        Ok(msg)
+    }
+
+    // Take the other end's next message, a failed one leaves the handshake as it was
+    pub fn read(&mut self, msg: &[u8]) -> Result<(), String> {
+        let mut payload = [0; MAX_MESSAGE];
+        self.0
+            .read_message(msg, &mut payload)
+            .map(|_| ())
+            .map_err(|e| format!("{e} -> Failed to read handshake message"))
+    }
+
+    // Static key of the other end, once its message carrying it was read
+    pub fn remote_static(&self) -> Option<[u8; KEY_SIZE]> {
+        self.0.get_remote_static().and_then(|key| key.try_into().ok())
+    }
+
+    // Keys of the connection, one per direction, plus a MAC key bound to this handshake
+    pub fn into_keys(mut self) -> Keys {
+        let (to_responder, to_initiator) = self.0.dangerously_get_raw_split();
+        let mut mac_key = [0; 32];
+        Hkdf::<Sha256>::new(None, self.0.get_handshake_hash())
+            .expand(b"transport path", &mut mac_key)
+            .unwrap();
+        match self.0.is_initiator() {
+            true => Keys::new(&to_responder, &to_initiator, mac_key),
+            false => Keys::new(&to_initiator, &to_responder, mac_key),
+        }
+    }
+}
+
+impl fmt::Debug for Handshake {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Handshake(..)")
+    }
+}
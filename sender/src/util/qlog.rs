@@ -0,0 +1,62 @@
+use serde_json::{json, Value};
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+use crate::util::tcp_header::TcpHeader;
+
+// Structured trace of a connection in the spirit of qlog, one JSON object per line.
+// The first line describes the trace, every other one is an event.
+#[derive(Debug)]
+pub struct EventLog {
+    file: File,
+    start: Instant, // Event times are in milliseconds since then
+}
+
+impl EventLog {
+    // Create the log file, vantage_point tells which end wrote it
+    pub fn new(path: &Path, vantage_point: &str, start: Instant) -> Result<Self, String> {
+        let mut file = File::create(path)
+            .map_err(|e| format!("{e} -> Failed to create {}", path.display()))?;
+        let reference_time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis() as u64);
+        let header = json!({
+            "qlog_format": "JSON-SEQ",
+            "title": path.file_stem().map(|stem| stem.to_string_lossy()),
+            "vantage_point": { "type": vantage_point },
+            "reference_time": reference_time,
+        });
+        writeln!(file, "{header}").map_err(|e| format!("{e} -> Failed to write {}", path.display()))?;
+
+        Ok(EventLog { file, start })
+    }
+
+    // Append an event, lines are written right away so a killed process leaves a complete log
+    pub fn event(&mut self, now: Instant, connection_id: u64, name: &str, data: Value) {
+        let event = json!({
+            "time": now.saturating_duration_since(self.start).as_secs_f64() * 1000.0,
+            "connection_id": format!("{connection_id:016x}"),
+            "name": name,
+            "data": data,
+        });
+        if let Err(e) = writeln!(self.file, "{event}") {
+            warn!("{e} -> Failed to write event log");
+        }
+    }
+}
+
+// Fields of a packet header as they appear in packet events
+pub fn packet_header(header: &TcpHeader) -> Value {
+    json!({
+        "flags": header.flags,
+        "frame_flags": header.frame_flags,
+        "seq": header.sequence_number.0,
+        "ack": header.ack_number.0,
+        "window": header.window_size,
+        "stream_id": header.stream_id,
+        "stream_seq": header.stream_seq.0,
+    })
+}
@@ -0,0 +1,57 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Add, AddAssign, Sub};
+
+// Sequence number in a space that wraps around at 2^32.
+// Ordered like RFC 1982 serial numbers: a comes before b if b is less than 2^31 ahead of a,
+// two numbers exactly 2^31 apart are not ordered.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct SeqNum(pub u32);
+
+impl SeqNum {
+    // Whether the number lies in [start, end)
+    pub fn in_range(self, start: SeqNum, end: SeqNum) -> bool {
+        self - start < end - start
+    }
+}
+
+impl PartialOrd for SeqNum {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match other.0.wrapping_sub(self.0) {
+            0 => Some(Ordering::Equal),
+            ahead if ahead < 1 << 31 => Some(Ordering::Less),
+            ahead if ahead > 1 << 31 => Some(Ordering::Greater),
+            _ => None,
+        }
+    }
+}
+
+// Advance by a number of sequence numbers
+impl Add<u32> for SeqNum {
+    type Output = SeqNum;
+
+    fn add(self, len: u32) -> SeqNum {
+        SeqNum(self.0.wrapping_add(len))
+    }
+}
+
+impl AddAssign<u32> for SeqNum {
+    fn add_assign(&mut self, len: u32) {
+        *self = *self + len;
+    }
+}
+
+// How far self is ahead of other, going forward from other
+impl Sub for SeqNum {
+    type Output = u32;
+
+    fn sub(self, other: SeqNum) -> u32 {
+        self.0.wrapping_sub(other.0)
+    }
+}
+
+impl fmt::Display for SeqNum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
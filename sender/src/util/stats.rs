@@ -0,0 +1,120 @@
+use serde_json::{json, Value};
+use std::fmt;
+use std::time::Duration;
+
+// Counters of a connection, reported at the end of a transfer
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub packets_sent: u64,
+    pub bytes_sent: u64, // Whole datagrams, headers included
+    pub packets_received: u64,
+    pub bytes_received: u64,
+    pub timeout_retransmits: u64, // Packets resent because their RTO expired
+    pub fast_retransmits: u64,    // Packets resent after three duplicate ACKs
+    pub duplicates: u64,          // Duplicate ACKs on the sender, data segments received again on the receiver
+    pub corrupted: u64,           // Packets discarded because their hash didn't match
+    pub payload_bytes: u64,       // Stream bytes acknowledged on the sender, read by the application on the receiver
+    pub rtt_min: Option<Duration>,
+    pub rtt_max: Option<Duration>,
+    pub rtt_total: Duration,
+    pub rtt_samples: u64,
+    pub peak_cwnd: u32,    // Largest congestion window in bytes, the receiver has none
+    pub elapsed: Duration, // From the start of the handshake to the end of the transfer
+}
+
+impl Stats {
+    // Count a datagram sent
+    pub fn sent(&mut self, len: usize) {
+        self.packets_sent += 1;
+        self.bytes_sent += len as u64;
+    }
+
+    // Count a datagram received
+    pub fn received(&mut self, len: usize) {
+        self.packets_received += 1;
+        self.bytes_received += len as u64;
+    }
+
+    // Count an RTT sample
+    pub fn rtt(&mut self, rtt: Duration) {
+        self.rtt_min = Some(self.rtt_min.map_or(rtt, |min| min.min(rtt)));
+        self.rtt_max = Some(self.rtt_max.map_or(rtt, |max| max.max(rtt)));
+        self.rtt_total += rtt;
+        self.rtt_samples += 1;
+    }
+
+    pub fn rtt_avg(&self) -> Option<Duration> {
+        (self.rtt_samples > 0).then(|| self.rtt_total / self.rtt_samples as u32)
+    }
+
+    // Payload bytes per second over the transfer
+    pub fn goodput(&self) -> f64 {
+        match self.elapsed.as_secs_f64() {
+            secs if secs > 0.0 => self.payload_bytes as f64 / secs,
+            _ => 0.0,
+        }
+    }
+
+    // Machine-readable report, durations in milliseconds
+    pub fn to_json(&self) -> Value {
+        let ms = |d: Option<Duration>| d.map(|d| d.as_secs_f64() * 1000.0);
+        json!({
+            "elapsed_ms": self.elapsed.as_secs_f64() * 1000.0,
+            "packets_sent": self.packets_sent,
+            "bytes_sent": self.bytes_sent,
+            "packets_received": self.packets_received,
+            "bytes_received": self.bytes_received,
+            "retransmits": {
+                "timeout": self.timeout_retransmits,
+                "fast": self.fast_retransmits,
+            },
+            "duplicates": self.duplicates,
+            "corrupted": self.corrupted,
+            "rtt_ms": {
+                "min": ms(self.rtt_min),
+                "avg": ms(self.rtt_avg()),
+                "max": ms(self.rtt_max),
+                "samples": self.rtt_samples,
+            },
+            "payload_bytes": self.payload_bytes,
+            "goodput_bytes_per_sec": self.goodput(),
+            "peak_cwnd": self.peak_cwnd,
+        })
+    }
+}
+
+// Report for people, lines that don't apply to this end are left out
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        writeln!(f, "Statistics after {:.3}s", self.elapsed.as_secs_f64())?;
+        writeln!(f, "  sent:        {} packets, {} bytes", self.packets_sent, self.bytes_sent)?;
+        writeln!(f, "  received:    {} packets, {} bytes", self.packets_received, self.bytes_received)?;
+        writeln!(
+            f,
+            "  retransmits: {} after a timeout, {} fast",
+            self.timeout_retransmits, self.fast_retransmits
+        )?;
+        writeln!(f, "  duplicates:  {}", self.duplicates)?;
+        writeln!(f, "  corrupted:   {}", self.corrupted)?;
+        if let (Some(min), Some(avg), Some(max)) = (self.rtt_min, self.rtt_avg(), self.rtt_max) {
+            writeln!(
+                f,
+                "  RTT:         min {:.1}ms, avg {:.1}ms, max {:.1}ms ({} samples)",
+                ms(min),
+                ms(avg),
+                ms(max),
+                self.rtt_samples
+            )?;
+        }
+        if self.peak_cwnd > 0 {
+            writeln!(f, "  peak cwnd:   {} bytes", self.peak_cwnd)?;
+        }
+        write!(
+            f,
+            "  goodput:     {:.0} bytes/s ({} bytes)",
+            self.goodput(),
+            self.payload_bytes
+        )
+    }
+}
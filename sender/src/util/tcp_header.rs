@@ -1,64 +1,118 @@
 use sha2::{Sha256, Digest};
-use generic_array::GenericArray;
-use typenum::U32;
 
-// TCP header struct, total 16 bytes
+use crate::util::seq::SeqNum;
+
+// Size of the serialized header in bytes (including the hash value)
+pub const HEADER_SIZE: usize = 62;
+
+// Flags used for path validation, carried in the two bits above FIN..URG
+pub const PATH_CHALLENGE: u8 = 0b0100_0000;
+pub const PATH_RESPONSE: u8 = 0b1000_0000;
+
+// Abortive close, answers a segment that belongs to no connection
+pub const RST: u8 = 0b0000_0100;
+
+// Frame flags, carried in the 4 bits after the header length
+pub const MSG_START: u8 = 0b0001; // First segment of a message
+pub const MSG_END: u8 = 0b0010; // Last segment of a message
+pub const FORWARD: u8 = 0b0100; // Abandoned segment, the receiver skips over its slot
+pub const UNORDERED: u8 = 0b1000; // Record delivered as soon as it arrives
+
+// Frame flags of a SYN or SYN-ACK
+pub const TOKEN: u8 = 0b0001; // A resumption token follows the key material of a SYN, or the cookie of a SYN-ACK
+pub const RESUMED: u8 = 0b0010; // The SYN's token was taken, the connection is set up without a handshake ACK
+pub const MSS: u8 = 0b0100; // The largest datagram the end takes ends the payload, 2 bytes big-endian
+pub const RETRY: u8 = 0b1000; // A SYN-ACK with only a cookie, which the SYN sent again echoes after its key material
+
+// Frame flags of a pure ACK from the sender
+pub const KEEPALIVE: u8 = 0b0001; // Keepalive probe, its stream sequence number counts the probes
+
+// TCP header struct, total 62 bytes
 #[derive(Debug)]
 pub struct TcpHeader {
     pub source_port: u16,
     pub destination_port: u16,
-    pub sequence_number: u32,
-    pub ack_number: u32,
+    pub sequence_number: SeqNum,
+    pub ack_number: SeqNum,
     pub header_length: u8,
-    pub flags: u8, // URG, ACK, PSH, RST, SYN, FIN (each 1 bit)
+    pub frame_flags: u8, // UNORDERED, FORWARD, MSG_END, MSG_START (each 1 bit)
+    pub flags: u8, // PATH_RESPONSE, PATH_CHALLENGE, URG, ACK, PSH, RST, SYN, FIN (each 1 bit)
     pub window_size: u16,
-    pub hash_value: GenericArray<u8, U32>, // 32 bytes of hash value
+    pub connection_id: u64, // Chosen by the sender, identifies the connection across address changes
+    pub stream_id: u16, // Stream the payload belongs to
+    pub stream_seq: SeqNum, // Sequence number of the payload within its stream
+    pub hash_value: [u8; 32], // 32 bytes of hash value
 }
 
 // Implement the TCP header
 impl TcpHeader {
-    // Create a new TCP header
-    pub fn new(header_bytes: &[u8]) -> Self {
-        if header_bytes.len() < 48 {
-            panic!("Header too short");
+    // Parse a header from the start of a datagram
+    pub fn new(header_bytes: &[u8]) -> Result<Self, String> {
+        if header_bytes.len() < HEADER_SIZE {
+            return Err(format!("Header too short: {} bytes", header_bytes.len()));
         }
         // Parse the header bytes into the fields of the header
         let source_port = u16::from_be_bytes(header_bytes[0..2].try_into().unwrap());
         let destination_port = u16::from_be_bytes(header_bytes[2..4].try_into().unwrap());
-        let sequence_number = u32::from_be_bytes(header_bytes[4..8].try_into().unwrap());
-        let ack_number = u32::from_be_bytes(header_bytes[8..12].try_into().unwrap());
+        let sequence_number = SeqNum(u32::from_be_bytes(header_bytes[4..8].try_into().unwrap()));
+        let ack_number = SeqNum(u32::from_be_bytes(header_bytes[8..12].try_into().unwrap()));
         let header_length = header_bytes[12] >> 4; // get the first 4 bits
-        let flags = header_bytes[13] & 0b0011_1111; // get the last 6 bits
+        let frame_flags = header_bytes[12] & 0b0000_1111; // get the last 4 bits
+        let flags = header_bytes[13];
         let window_size = u16::from_be_bytes(header_bytes[14..16].try_into().unwrap());
-        let hash_value = GenericArray::clone_from_slice(&header_bytes[16..48]);
+        let connection_id = u64::from_be_bytes(header_bytes[16..24].try_into().unwrap());
+        let stream_id = u16::from_be_bytes(header_bytes[24..26].try_into().unwrap());
+        let stream_seq = SeqNum(u32::from_be_bytes(header_bytes[26..30].try_into().unwrap()));
+        let hash_value = header_bytes[30..62].try_into().unwrap();
 
-        TcpHeader {
+        Ok(TcpHeader {
             source_port,
             destination_port,
             sequence_number,
             ack_number,
             header_length,
+            frame_flags,
             flags,
             window_size,
+            connection_id,
+            stream_id,
+            stream_seq,
             hash_value,
+        })
+    }
+
+    // The RST answering this segment: back to its ports, for its connection, with the sequence number
+    // it acknowledged, so only someone who saw the segment can make one the other end accepts
+    pub fn reset(&self) -> TcpHeader {
+        TcpHeader {
+            source_port: self.destination_port,
+            destination_port: self.source_port,
+            sequence_number: self.ack_number,
+            ack_number: self.sequence_number,
+            header_length: 4,
+            frame_flags: 0,
+            flags: RST,
+            window_size: 0,
+            connection_id: self.connection_id,
+            stream_id: 0,
+            stream_seq: SeqNum(0),
+            hash_value: [0; 32],
         }
     }
 
     // Function to calculate the hash of the header and data
-    pub fn calculate_header_data_hash(&self, data: &[u8]) -> GenericArray<u8, U32> {
+    pub fn calculate_header_data_hash(&self, data: &[u8]) -> [u8; 32] {
         let mut hasher = Sha256::new();
-        hasher.update(&self.as_bytes_without_hash());
+        hasher.update(self.as_bytes_without_hash());
         hasher.update(data);
-        let result = hasher.finalize();
-        result
+        hasher.finalize().into()
     }   
     
     // Function to calculate the hash value of the header
-    pub fn calculate_header_hash(&self) -> GenericArray<u8, U32> {
+    pub fn calculate_header_hash(&self) -> [u8; 32] {
         let mut hasher = Sha256::new();
-        hasher.update(&self.as_bytes_without_hash());
-        let result = hasher.finalize();
-        result
+        hasher.update(self.as_bytes_without_hash());
+        hasher.finalize().into()
     }
 
     // Helper method to serialize the header without the hash_value
@@ -75,21 +129,21 @@ impl TcpHeader {
         res.push(u8::from_str_radix(&dst_port_str[8..], 2).unwrap());
 
         // Get the sequence number as a byte and push it to the result
-        let seq_num_str = format!("{:032b}", self.sequence_number);
+        let seq_num_str = format!("{:032b}", self.sequence_number.0);
         res.push(u8::from_str_radix(&seq_num_str[..8], 2).unwrap());
         res.push(u8::from_str_radix(&seq_num_str[8..16], 2).unwrap());
         res.push(u8::from_str_radix(&seq_num_str[16..24], 2).unwrap());
         res.push(u8::from_str_radix(&seq_num_str[24..], 2).unwrap());
 
         // Get the ack number as a byte and push it to the result
-        let ack_num_str = format!("{:032b}", self.ack_number);
+        let ack_num_str = format!("{:032b}", self.ack_number.0);
         res.push(u8::from_str_radix(&ack_num_str[..8], 2).unwrap());
         res.push(u8::from_str_radix(&ack_num_str[8..16], 2).unwrap());
         res.push(u8::from_str_radix(&ack_num_str[16..24], 2).unwrap());
         res.push(u8::from_str_radix(&ack_num_str[24..], 2).unwrap());
         
-        // Get the header length as a byte and push it to the result
-        let head_len_str = format!("{:08b}", self.header_length << 4);
+        // Get the header length and frame flags as a byte and push it to the result
+        let head_len_str = format!("{:08b}", (self.header_length << 4) | self.frame_flags);
         res.push(u8::from_str_radix(&head_len_str[..], 2).unwrap());
         
         // Get the flags as a byte and push it to the result
@@ -101,53 +155,24 @@ impl TcpHeader {
         res.push(u8::from_str_radix(&wnd_size_str[..8], 2).unwrap());
         res.push(u8::from_str_radix(&wnd_size_str[8..], 2).unwrap());
 
+        // Get the connection ID as bytes and push it to the result
+        res.extend_from_slice(&self.connection_id.to_be_bytes());
+
+        // Get the stream ID and stream sequence number as bytes and push them to the result
+        res.extend_from_slice(&self.stream_id.to_be_bytes());
+        res.extend_from_slice(&self.stream_seq.0.to_be_bytes());
+
         res
     }
     
 
     // Convert the header to a byte array
     pub fn as_bytes(&self) -> Vec<u8> {
-        let mut res: Vec<u8> = vec![];
-        // Get the source port as a byte and push it to the result
-        let source_port_str = format!("{:016b}", self.source_port); // convert to string of 16 bits
-        res.push(u8::from_str_radix(&source_port_str[..8], 2).unwrap());
-        res.push(u8::from_str_radix(&source_port_str[8..], 2).unwrap());
-
-        // Get the destination port as a byte and push it to the result
-        let dst_port_str = format!("{:016b}", self.destination_port);
-        res.push(u8::from_str_radix(&dst_port_str[..8], 2).unwrap());
-        res.push(u8::from_str_radix(&dst_port_str[8..], 2).unwrap());
-
-        // Get the sequence number as a byte and push it to the result
-        let seq_num_str = format!("{:032b}", self.sequence_number);
-        res.push(u8::from_str_radix(&seq_num_str[..8], 2).unwrap());
-        res.push(u8::from_str_radix(&seq_num_str[8..16], 2).unwrap());
-        res.push(u8::from_str_radix(&seq_num_str[16..24], 2).unwrap());
-        res.push(u8::from_str_radix(&seq_num_str[24..], 2).unwrap());
-
-        // Get the ack number as a byte and push it to the result
-        let ack_num_str = format!("{:032b}", self.ack_number);
-        res.push(u8::from_str_radix(&ack_num_str[..8], 2).unwrap());
-        res.push(u8::from_str_radix(&ack_num_str[8..16], 2).unwrap());
-        res.push(u8::from_str_radix(&ack_num_str[16..24], 2).unwrap());
-        res.push(u8::from_str_radix(&ack_num_str[24..], 2).unwrap());
-        
-        // Get the header length as a byte and push it to the result
-        let head_len_str = format!("{:08b}", self.header_length << 4);
-        res.push(u8::from_str_radix(&head_len_str[..], 2).unwrap());
-        
-        // Get the flags as a byte and push it to the result
-        let flag_str = format!("{:08b}", self.flags);
-        res.push(u8::from_str_radix(&flag_str[..], 2).unwrap());
-
-        // Get the window size as a byte and push it to the result
-        let wnd_size_str = format!("{:016b}", self.window_size);
-        res.push(u8::from_str_radix(&wnd_size_str[..8], 2).unwrap());
-        res.push(u8::from_str_radix(&wnd_size_str[8..], 2).unwrap());
+        let mut res = self.as_bytes_without_hash();
 
         // Get the hash value as a byte and push it to the result
         res.extend_from_slice(&self.hash_value);
 
         res
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,42 @@
+use std::fmt::Debug;
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::time::Instant;
+
+// Where a connection sends and receives its datagrams, a UDP socket or a simulated link
+pub trait Transport: Debug + Send {
+    fn send_to(&self, buf: &[u8], addr: &str) -> io::Result<usize>;
+    // Must not block, returns WouldBlock if nothing arrived
+    fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)>;
+    fn local_addr(&self) -> io::Result<SocketAddr>;
+}
+
+// Where a connection reads the time, the system clock or a simulated one
+pub trait Clock: Debug + Send + Sync {
+    fn now(&self) -> Instant;
+}
+
+// Wall-clock time
+#[derive(Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+// The socket has to be switched to non-blocking mode
+impl Transport for UdpSocket {
+    fn send_to(&self, buf: &[u8], addr: &str) -> io::Result<usize> {
+        UdpSocket::send_to(self, buf, addr)
+    }
+
+    fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        UdpSocket::recv_from(self, buf)
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        UdpSocket::local_addr(self)
+    }
+}
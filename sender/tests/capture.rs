@@ -0,0 +1,63 @@
+use std::net::UdpSocket;
+use std::sync::Arc;
+use std::time::Duration;
+
+use sender::util::capture::{INBOUND, OUTBOUND};
+use sender::{Capture, SystemClock, Transport};
+
+// Blocks of a pcapng file as (type, body)
+fn blocks(file: &[u8]) -> Vec<(u32, &[u8])> {
+    let mut res = Vec::new();
+    let mut rest = file;
+    while !rest.is_empty() {
+        let block_type = u32::from_le_bytes(rest[..4].try_into().unwrap());
+        let len = u32::from_le_bytes(rest[4..8].try_into().unwrap()) as usize;
+        assert_eq!(&rest[4..8], &rest[len - 4..len], "trailing length");
+        res.push((block_type, &rest[8..len - 4]));
+        rest = &rest[len..];
+    }
+    res
+}
+
+#[test]
+fn datagrams_are_written_with_their_direction() {
+    let path = std::env::temp_dir().join(format!("sender-capture-{}.pcapng", std::process::id()));
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let local = socket.local_addr().unwrap();
+    let peer = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let peer_addr = peer.local_addr().unwrap();
+
+    let capture = Capture::new(Box::new(socket), &path, Arc::new(SystemClock)).unwrap();
+    capture.send_to(b"hello", &peer_addr.to_string()).unwrap();
+    let mut buf = [0; 16];
+    let (len, _) = peer.recv_from(&mut buf).unwrap();
+    peer.send_to(&buf[..len], local).unwrap();
+    assert_eq!(capture.recv_from(&mut buf).unwrap(), (5, peer_addr));
+    drop(capture);
+
+    let file = std::fs::read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let blocks = blocks(&file);
+    assert_eq!(blocks.len(), 4);
+    assert_eq!(blocks[0].0, 0x0A0D_0D0A);
+    assert_eq!(blocks[1], (1, &[101, 0, 0, 0, 0, 0, 0, 0][..]));
+
+    let expected = [(local, peer_addr, OUTBOUND), (peer_addr, local, INBOUND)];
+    for ((block_type, body), (src, dst, direction)) in blocks[2..].iter().zip(expected) {
+        assert_eq!(*block_type, 6);
+        let len = u32::from_le_bytes(body[12..16].try_into().unwrap()) as usize;
+        assert_eq!(len, 20 + 8 + 5);
+        let packet = &body[20..20 + len];
+        // IPv4 header, UDP header and the datagram itself
+        assert_eq!(packet[0], 0x45);
+        assert_eq!(packet[9], 17);
+        assert_eq!(&packet[20..22], &src.port().to_be_bytes());
+        assert_eq!(&packet[22..24], &dst.port().to_be_bytes());
+        assert_eq!(&packet[28..], b"hello");
+        // The epb_flags option follows the padded packet
+        let options = &body[20 + ((len + 3) & !3)..];
+        assert_eq!(&options[..4], &[2, 0, 4, 0]);
+        assert_eq!(u32::from_le_bytes(options[4..8].try_into().unwrap()), direction);
+    }
+}
@@ -0,0 +1,174 @@
+// Helpers shared by the tests, each test file uses some of them
+#![allow(dead_code)]
+
+use std::collections::VecDeque;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use receiver::Receiver;
+use sender::util::seq::SeqNum;
+use sender::util::tcp_header::TcpHeader;
+use sender::{Clock, Sender};
+
+// Cookie the tests' receiver puts in its SYN-ACK
+pub const COOKIE: [u8; 16] = [9; 16];
+
+// Time only moves when the test says so
+#[derive(Debug)]
+pub struct ManualClock(Mutex<Instant>);
+
+impl ManualClock {
+    pub fn new() -> Arc<Self> {
+        Arc::new(ManualClock(Mutex::new(Instant::now())))
+    }
+
+    pub fn advance(&self, by: Duration) {
+        *self.0.lock().unwrap() += by;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        *self.0.lock().unwrap()
+    }
+}
+
+pub fn header(ports: (u16, u16), seq: SeqNum, ack: SeqNum, flags: u8, connection_id: u64) -> TcpHeader {
+    TcpHeader {
+        source_port: ports.0,
+        destination_port: ports.1,
+        sequence_number: seq,
+        ack_number: ack,
+        header_length: 4,
+        frame_flags: 0,
+        flags,
+        window_size: 65340,
+        connection_id,
+        stream_id: 0,
+        stream_seq: SeqNum(0),
+        hash_value: [0; 32],
+    }
+}
+
+// A sender on the given clock and the socket of the receiver it sends to, which the test plays,
+// with the address to answer the sender at
+pub fn sender(clock: Arc<dyn Clock>) -> (Sender, UdpSocket, SocketAddr) {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket.set_nonblocking(true).unwrap();
+    let to = socket.local_addr().unwrap();
+    let peer = UdpSocket::bind("127.0.0.1:0").unwrap();
+    peer.set_read_timeout(Some(Duration::from_millis(10))).unwrap();
+    let port = peer.local_addr().unwrap().port();
+    let sender = Sender::with_transport("127.0.0.1".to_string(), port, Box::new(socket), clock, 1, 65340, 4).unwrap();
+    (sender, peer, to)
+}
+
+// Poll the sender until the peer gets a datagram with these flags
+pub fn next(sender: &mut Sender, peer: &UdpSocket, flags: u8) -> TcpHeader {
+    let mut buf = [0; 1500];
+    for _ in 0..500 {
+        sender.poll().unwrap();
+        if let Ok(len) = peer.recv(&mut buf) {
+            let header = TcpHeader::new(&buf[..len]).unwrap();
+            if header.flags == flags {
+                return header;
+            }
+        }
+    }
+    panic!("The sender sent no packet with flags {flags:#010b}");
+}
+
+// One-way delay of the relay, long enough that the timeouts don't fire on how long the test takes to loop
+const DELAY: Duration = Duration::from_millis(25);
+
+// Decides whether a datagram of the sender gets through
+type Filter = Box<dyn FnMut(&[u8]) -> bool>;
+
+// A sender and a receiver on loopback, talking through a relay that delays every datagram and shows those of
+// the sender to a filter, dropping them if it returns false
+pub struct Link {
+    pub sender: Sender,
+    pub receiver: Receiver,
+    relay: UdpSocket,
+    to_receiver: SocketAddr,
+    to_sender: Option<SocketAddr>,
+    filter: Filter,
+    queue: VecDeque<(Instant, SocketAddr, Vec<u8>)>, // Datagrams waiting out the delay, with their destination
+}
+
+impl Link {
+    pub fn new(filter: impl FnMut(&[u8]) -> bool + 'static) -> Self {
+        let receiver = Receiver::new("127.0.0.1".to_string(), None).unwrap();
+        let relay = UdpSocket::bind("127.0.0.1:0").unwrap();
+        relay.set_nonblocking(true).unwrap();
+        let port = relay.local_addr().unwrap().port();
+        let sender = Sender::new("127.0.0.1".to_string(), port, "127.0.0.1".to_string(), 65340, 4, None).unwrap();
+        Link {
+            sender,
+            to_receiver: SocketAddr::from(([127, 0, 0, 1], receiver.local_port())),
+            receiver,
+            relay,
+            to_sender: None,
+            filter: Box::new(filter),
+            queue: VecDeque::new(),
+        }
+    }
+
+    // Nothing lost on the way
+    pub fn clean() -> Self {
+        Self::new(|_| true)
+    }
+
+    // Run until the sender got everything it has queued acknowledged or the time is up, with the given function
+    // reading from the receiver after every poll. Returns whether the sender finished.
+    pub fn run(&mut self, timeout: Duration, mut read: impl FnMut(&mut Receiver)) -> bool {
+        let deadline = Instant::now() + timeout;
+        // Each poll takes one datagram, every end is polled at least once for each passed on to it
+        let mut to_sender = 1;
+        while Instant::now() < deadline {
+            for _ in 0..to_sender.max(1) {
+                if self.sender.poll().unwrap() {
+                    return true;
+                }
+            }
+            let (to_receiver, _) = self.forward();
+            for _ in 0..to_receiver.max(1) {
+                self.receiver.poll().unwrap();
+                read(&mut self.receiver);
+            }
+            to_sender = self.forward().1;
+        }
+        false
+    }
+
+    // Take in what waits at the relay, and pass on what waited out the delay: the receiver answers the relay and
+    // the relay the sender. Returns how many datagrams went to the receiver and to the sender.
+    fn forward(&mut self) -> (usize, usize) {
+        let now = Instant::now();
+        let mut buf = [0; 65536];
+        while let Ok((len, from)) = self.relay.recv_from(&mut buf) {
+            if from == self.to_receiver {
+                if let Some(to) = self.to_sender {
+                    self.queue.push_back((now + DELAY, to, buf[..len].to_vec()));
+                }
+            } else {
+                self.to_sender = Some(from);
+                if (self.filter)(&buf[..len]) {
+                    self.queue.push_back((now + DELAY, self.to_receiver, buf[..len].to_vec()));
+                }
+            }
+        }
+
+        let mut passed = (0, 0);
+        while self.queue.front().is_some_and(|(at, _, _)| *at <= now) {
+            let (_, to, data) = self.queue.pop_front().unwrap();
+            let _ = self.relay.send_to(&data, to);
+            match to == self.to_receiver {
+                true => passed.0 += 1,
+                false => passed.1 += 1,
+            }
+        }
+        passed
+    }
+}
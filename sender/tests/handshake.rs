@@ -0,0 +1,67 @@
+use std::net::UdpSocket;
+use std::time::Duration;
+
+use sender::util::crypto::Protection;
+use sender::util::seq::SeqNum;
+use sender::util::tcp_header::TcpHeader;
+use sender::Sender;
+
+mod common;
+use common::{header, sender, ManualClock, COOKIE};
+
+// Datagrams the sender sent with these flags, after polling it a few times
+fn sent(sender: &mut Sender, peer: &UdpSocket, flags: u8) -> usize {
+    let mut buf = [0; 1500];
+    let mut count = 0;
+    for _ in 0..10 {
+        sender.poll().unwrap();
+        while let Ok(len) = peer.recv(&mut buf) {
+            if TcpHeader::new(&buf[..len]).unwrap().flags == flags {
+                count += 1;
+            }
+        }
+    }
+    count
+}
+
+#[test]
+fn handshake_packets_back_off_and_a_repeated_syn_ack_gets_the_ack_again() {
+    let clock = ManualClock::new();
+    let (mut sender, peer, to) = sender(clock.clone());
+    sender.send(0, &[7; 100]).unwrap();
+
+    // The SYN waits twice as long every time it is resent, up to two seconds
+    let mut buf = [0; 1500];
+    sender.poll().unwrap();
+    let len = peer.recv(&mut buf).unwrap();
+    let syn = TcpHeader::new(&buf[..len]).unwrap();
+    for wait in [800, 1600, 2000, 2000] {
+        clock.advance(Duration::from_millis(wait - 1));
+        assert_eq!(sent(&mut sender, &peer, 0b0000_0010), 0);
+        clock.advance(Duration::from_millis(1));
+        assert_eq!(sent(&mut sender, &peer, 0b0000_0010), 1);
+    }
+
+    let ports = (syn.destination_port, syn.source_port);
+    let (id, seq) = (syn.connection_id, SeqNum(5000));
+    let unkeyed = Protection::default();
+    let mut syn_ack = header(ports, seq, syn.sequence_number + 1, 0b0001_0010, id);
+    let syn_ack = unkeyed.seal(&mut syn_ack, &COOKIE);
+    peer.send_to(&syn_ack, to).unwrap();
+    assert_eq!(sent(&mut sender, &peer, 0b0001_0000), 1);
+
+    // Another answer to the SYN, right away, is one resent before the first arrived
+    peer.send_to(&syn_ack, to).unwrap();
+    assert_eq!(sent(&mut sender, &peer, 0b0001_0000), 0);
+
+    // A round trip later, the receiver is still waiting for our ACK
+    clock.advance(Duration::from_millis(400));
+    peer.send_to(&syn_ack, to).unwrap();
+    assert_eq!(sent(&mut sender, &peer, 0b0001_0000), 1);
+
+    // Someone else's SYN-ACK
+    clock.advance(Duration::from_millis(400));
+    let mut forged = header(ports, seq + 7, syn.sequence_number + 1, 0b0001_0010, id);
+    peer.send_to(&unkeyed.seal(&mut forged, &COOKIE), to).unwrap();
+    assert_eq!(sent(&mut sender, &peer, 0b0001_0000), 0);
+}
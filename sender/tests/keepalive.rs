@@ -0,0 +1,53 @@
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+
+use sender::util::crypto::{session_key, Protection};
+use sender::util::seq::SeqNum;
+use sender::util::tcp_header::KEEPALIVE;
+use sender::SystemClock;
+
+mod common;
+use common::{header, next, sender, COOKIE};
+
+#[test]
+fn an_idle_sender_probes_the_receiver_and_times_out_once_it_is_gone() {
+    let (mut sender, peer, to) = sender(Arc::new(SystemClock));
+    sender.set_keepalive(Some(Duration::from_millis(50)));
+    sender.set_idle_timeout(Some(Duration::from_millis(300)));
+    sender.send(0, &[7; 100]).unwrap();
+
+    let syn = next(&mut sender, &peer, 0b0000_0010);
+    let ports = (syn.destination_port, syn.source_port);
+    let (id, seq) = (syn.connection_id, SeqNum(5000));
+    let mut syn_ack = header(ports, seq, syn.sequence_number + 1, 0b0001_0010, id);
+    peer.send_to(&Protection::default().seal(&mut syn_ack, &COOKIE), to).unwrap();
+    next(&mut sender, &peer, 0b0001_0000);
+    let data = next(&mut sender, &peer, 0b0001_1000);
+
+    // Everything acknowledged, the connection idles
+    let protection = Protection {
+        session: Some(session_key(&COOKIE, id)),
+        ..Protection::default()
+    };
+    let mut ack = header(ports, seq + 1, data.sequence_number + 100, 0b0001_0000, id);
+    peer.send_to(&protection.seal(&mut ack, &[]), to).unwrap();
+
+    // Probes have nonces of their own, answering them keeps the connection open past the timeout
+    for probe in 1..=8 {
+        let keepalive = next(&mut sender, &peer, 0b0001_0000);
+        assert_eq!(keepalive.frame_flags, KEEPALIVE);
+        assert_eq!(keepalive.stream_seq, SeqNum(probe));
+        let mut ack = header(ports, seq + 1 + probe, data.sequence_number + 100, 0b0001_0000, id);
+        peer.send_to(&protection.seal(&mut ack, &[]), to).unwrap();
+    }
+
+    // Probes left unanswered
+    let err = loop {
+        if let Err(e) = sender.poll() {
+            break e;
+        }
+    };
+    assert!(err.contains("timed out"), "{err}");
+    assert_eq!(sender.error(), Some(io::ErrorKind::TimedOut));
+}
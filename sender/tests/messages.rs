@@ -0,0 +1,73 @@
+use std::time::Duration;
+
+use sender::util::tcp_header::TcpHeader;
+
+mod common;
+use common::Link;
+
+// Loses every nth data segment, so fragments arrive out of order and go again
+fn lossy(nth: u32) -> Link {
+    let mut count = 0;
+    Link::new(move |buf| {
+        if TcpHeader::new(buf).unwrap().flags != 0b0001_1000 {
+            return true;
+        }
+        count += 1;
+        count % nth != 0
+    })
+}
+
+#[test]
+fn messages_come_out_whole_whatever_their_size() {
+    // Empty, one byte, around a segment, and many segments
+    let sizes = [0, 1, 1137, 1138, 1139, 2276, 2277, 5000, 100_000, 3, 0, 60_000];
+    let messages: Vec<Vec<u8>> = sizes
+        .iter()
+        .enumerate()
+        .map(|(i, &size)| (0..size).map(|j| (j % 251) as u8 ^ i as u8).collect())
+        .collect();
+
+    for nth in [7, 11, 20] {
+        let mut link = lossy(nth);
+        for msg in &messages {
+            link.sender.send_message(0, msg).unwrap();
+        }
+
+        let mut received = Vec::new();
+        let mut bytes = Vec::new();
+        let finished = link.run(Duration::from_secs(30), |receiver| {
+            received.extend(std::iter::from_fn(|| receiver.recv_message(0)));
+            bytes.extend(receiver.read(0));
+        });
+
+        assert!(finished, "{nth}");
+        assert_eq!(received, messages, "{nth}");
+        // Nothing leaks into the byte stream
+        assert!(bytes.is_empty(), "{nth}");
+    }
+}
+
+#[test]
+fn messages_and_bytes_on_separate_streams_keep_to_their_own() {
+    let mut link = lossy(20);
+    let other = link.sender.open_stream();
+    let data: Vec<u8> = (0..50_000u32).map(|j| (j % 251) as u8).collect();
+    let messages: Vec<Vec<u8>> = (1..=20).map(|i| vec![i as u8; i * 700]).collect();
+    link.sender.send(0, &data).unwrap();
+    for msg in &messages {
+        link.sender.send_message(other, msg).unwrap();
+    }
+
+    let mut received = Vec::new();
+    let mut bytes = Vec::new();
+    let finished = link.run(Duration::from_secs(30), |receiver| {
+        received.extend(std::iter::from_fn(|| receiver.recv_message(other)));
+        bytes.extend(receiver.read(0));
+        assert_eq!(receiver.recv_message(0), None);
+        assert!(receiver.read(other).is_empty());
+    });
+
+    assert!(finished);
+    assert_eq!(received, messages);
+    assert_eq!(bytes, data);
+}
@@ -0,0 +1,91 @@
+use std::cell::Cell;
+use std::collections::HashSet;
+use std::rc::Rc;
+use std::time::Duration;
+
+use sender::util::tcp_header::{TcpHeader, FORWARD, HEADER_SIZE};
+use sender::Reliability;
+
+mod common;
+use common::Link;
+
+// Send every other message with the given reliability and the rest reliably. Every 10th data segment is lost,
+// every copy of it if it belongs to an unreliable message, whose bytes are odd. Returns whether the sender
+// finished, the messages received and the skip markers sent.
+fn transfer(reliability: Reliability, messages: &[Vec<u8>]) -> (bool, Vec<Vec<u8>>, usize) {
+    let forwards = Rc::new(Cell::new(0));
+    let counter = forwards.clone();
+    let mut count = 0;
+    let mut doomed = HashSet::new();
+    let mut link = Link::new(move |buf| {
+        let header = TcpHeader::new(buf).unwrap();
+        if header.flags != 0b0001_1000 {
+            return true;
+        }
+        if header.frame_flags & FORWARD != 0 {
+            counter.set(counter.get() + 1);
+            return true;
+        }
+        if doomed.contains(&header.sequence_number) {
+            return false;
+        }
+        count += 1;
+        if count % 10 != 0 {
+            return true;
+        }
+        if buf[HEADER_SIZE] % 2 == 1 {
+            doomed.insert(header.sequence_number);
+        }
+        false
+    });
+    for (i, msg) in messages.iter().enumerate() {
+        match i % 2 {
+            0 => link.sender.send_message(0, msg).unwrap(),
+            _ => link.sender.send_message_with(0, msg, reliability).unwrap(),
+        }
+    }
+
+    let mut received = Vec::new();
+    let finished = link.run(Duration::from_secs(30), |receiver| {
+        received.extend(std::iter::from_fn(|| receiver.recv_message(0)));
+    });
+    (finished, received, forwards.get())
+}
+
+// Messages received are whole and in order, every reliable one among them, and some unreliable ones are missing
+fn check(messages: &[Vec<u8>], received: &[Vec<u8>]) {
+    let mut rest = received.iter().peekable();
+    let mut skipped = 0;
+    for (i, msg) in messages.iter().enumerate() {
+        if rest.peek() == Some(&msg) {
+            rest.next();
+        } else {
+            assert!(i % 2 == 1, "reliable message {i} missing");
+            skipped += 1;
+        }
+    }
+    assert_eq!(rest.next(), None, "received a message that wasn't sent, or out of order");
+    assert!(skipped > 0);
+}
+
+fn messages() -> Vec<Vec<u8>> {
+    (0..200).map(|i| vec![i as u8; 1000 + i * 13]).collect()
+}
+
+#[test]
+fn messages_out_of_retransmits_are_skipped() {
+    let messages = messages();
+    let (finished, received, forwards) = transfer(Reliability::MaxRetransmits(0), &messages);
+    assert!(finished);
+    assert!(forwards > 0);
+    check(&messages, &received);
+}
+
+#[test]
+fn messages_past_their_lifetime_are_skipped() {
+    let messages = messages();
+    let (finished, received, forwards) = transfer(Reliability::Lifetime(Duration::from_millis(300)), &messages);
+    assert!(finished);
+    assert!(forwards > 0);
+    check(&messages, &received);
+}
@@ -0,0 +1,67 @@
+use std::io;
+use std::time::Duration;
+
+use sender::util::crypto::{session_key, Protection};
+use sender::util::seq::SeqNum;
+use sender::Sender;
+
+mod common;
+use common::{header, next, sender, ManualClock, COOKIE};
+
+// Poll the sender until it stops with an error, or give up
+fn run(sender: &mut Sender) -> Option<String> {
+    (0..1000).find_map(|_| sender.poll().err())
+}
+
+#[test]
+fn a_reset_from_the_receiver_ends_the_connection_once_it_is_set_up() {
+    // Nothing times out unless the test moves the clock
+    let clock = ManualClock::new();
+    let (mut sender, peer, to) = sender(clock.clone());
+    sender.send(0, &[7; 5000]).unwrap();
+
+    let syn = next(&mut sender, &peer, 0b0000_0010);
+    let ports = (syn.destination_port, syn.source_port);
+    let (id, seq) = (syn.connection_id, SeqNum(5000));
+    let unkeyed = Protection::default();
+    let mut syn_ack = header(ports, seq, syn.sequence_number + 1, 0b0001_0010, id);
+    peer.send_to(&unkeyed.seal(&mut syn_ack, &COOKIE), to).unwrap();
+    let handshake_ack = next(&mut sender, &peer, 0b0001_0000);
+    let data = next(&mut sender, &peer, 0b0001_1000);
+
+    // Not from the receiver, which answers nothing but the handshake ACK before it acknowledges that
+    peer.send_to(&unkeyed.seal(&mut data.reset(), &[]), to).unwrap();
+    assert_eq!(run(&mut sender), None);
+
+    // Then the receiver acknowledges the handshake ACK, a moment later
+    clock.advance(Duration::from_millis(1));
+    let protection = Protection {
+        session: Some(session_key(&COOKIE, id)),
+        ..Protection::default()
+    };
+    let mut ack = header(ports, seq + 1, handshake_ack.sequence_number + 1, 0b0001_0000, id);
+    peer.send_to(&protection.seal(&mut ack, &[]), to).unwrap();
+    assert_eq!(run(&mut sender), None);
+    // A segment first sent once the connection is set up, not a copy of one sent before
+    sender.send(0, &[8; 100]).unwrap();
+    let data = loop {
+        let data = next(&mut sender, &peer, 0b0001_1000);
+        if data.stream_seq == SeqNum(5000) {
+            break data;
+        }
+    };
+
+    // Outside the window, or answering a segment that was acknowledged
+    let mut acked = data.reset();
+    acked.ack_number = handshake_ack.sequence_number;
+    peer.send_to(&unkeyed.seal(&mut acked, &[]), to).unwrap();
+    let mut reset = data.reset();
+    reset.sequence_number += 100_000;
+    peer.send_to(&unkeyed.seal(&mut reset, &[]), to).unwrap();
+    assert_eq!(run(&mut sender), None);
+
+    peer.send_to(&unkeyed.seal(&mut data.reset(), &[]), to).unwrap();
+    let err = run(&mut sender).unwrap();
+    assert!(err.contains("reset"), "{err}");
+    assert_eq!(sender.error(), Some(io::ErrorKind::ConnectionReset));
+}
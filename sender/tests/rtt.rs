@@ -0,0 +1,68 @@
+use std::time::Duration;
+
+use sender::util::crypto::{session_key, Protection};
+use sender::util::seq::SeqNum;
+use sender::Clock;
+
+mod common;
+use common::{header, next, sender, ManualClock, COOKIE};
+
+#[test]
+fn the_ack_of_resent_data_gives_no_rtt_sample() {
+    let clock = ManualClock::new();
+    let (mut sender, peer, to) = sender(clock.clone());
+    sender.send(0, &[7; 100]).unwrap();
+
+    let syn = next(&mut sender, &peer, 0b0000_0010);
+    let ports = (syn.destination_port, syn.source_port);
+    let (id, seq) = (syn.connection_id, SeqNum(5000));
+    clock.advance(Duration::from_millis(100));
+    let mut syn_ack = header(ports, seq, syn.sequence_number + 1, 0b0001_0010, id);
+    peer.send_to(&Protection::default().seal(&mut syn_ack, &COOKIE), to).unwrap();
+    let data = next(&mut sender, &peer, 0b0001_1000);
+    // The handshake timed the path at 100ms, so the RTO is 180ms, and the handshake ACK waits two thirds of it
+    assert_eq!(sender.next_timeout(), Some(clock.now() + Duration::from_millis(180) * 2 / 3));
+
+    // The data times out and goes again, its ACK may answer either copy
+    clock.advance(Duration::from_secs(2));
+    let resent = next(&mut sender, &peer, 0b0001_1000);
+    assert_eq!(resent.sequence_number, data.sequence_number);
+    clock.advance(Duration::from_millis(10));
+    let protection = Protection {
+        session: Some(session_key(&COOKIE, id)),
+        ..Protection::default()
+    };
+    let mut ack = header(ports, seq + 1, data.sequence_number + 100, 0b0001_0000, id);
+    peer.send_to(&protection.seal(&mut ack, &[]), to).unwrap();
+    assert!((0..100).any(|_| sender.poll().unwrap()));
+
+    // A 10ms sample would have cut the RTO
+    sender.send(0, &[7; 100]).unwrap();
+    next(&mut sender, &peer, 0b0001_1000);
+    assert_eq!(sender.next_timeout(), Some(clock.now() + Duration::from_millis(180)));
+}
+
+#[test]
+fn the_syn_ack_of_a_resent_syn_gives_no_rtt_sample() {
+    for resend in [false, true] {
+        let clock = ManualClock::new();
+        let (mut sender, peer, to) = sender(clock.clone());
+        sender.send(0, &[7; 100]).unwrap();
+
+        let syn = next(&mut sender, &peer, 0b0000_0010);
+        if resend {
+            clock.advance(Duration::from_millis(800));
+            next(&mut sender, &peer, 0b0000_0010);
+        }
+
+        // The SYN-ACK may answer either SYN, it only times the path if there was one
+        clock.advance(Duration::from_millis(100));
+        let ports = (syn.destination_port, syn.source_port);
+        let mut syn_ack = header(ports, SeqNum(5000), syn.sequence_number + 1, 0b0001_0010, syn.connection_id);
+        peer.send_to(&Protection::default().seal(&mut syn_ack, &COOKIE), to).unwrap();
+        next(&mut sender, &peer, 0b0001_1000);
+        let rto = if resend { 800 } else { 180 };
+        let timeout = Duration::from_millis(rto) * 2 / 3;
+        assert_eq!(sender.next_timeout(), Some(clock.now() + timeout), "{resend}");
+    }
+}
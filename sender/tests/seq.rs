@@ -0,0 +1,62 @@
+use proptest::prelude::*;
+use std::cmp::Ordering;
+
+use sender::util::seq::SeqNum;
+use sender::util::tcp_header::TcpHeader;
+
+// Numbers close to the wrap, where most of the mistakes happen
+fn near_wrap() -> impl Strategy<Value = u32> {
+    prop_oneof![u32::MAX - 100_000..=u32::MAX, 0..100_000u32, any::<u32>()]
+}
+
+proptest! {
+    #[test]
+    fn add_then_sub_gives_the_distance(a in near_wrap(), n in any::<u32>()) {
+        prop_assert_eq!((SeqNum(a) + n) - SeqNum(a), n);
+        prop_assert_eq!(SeqNum(a) + n, SeqNum(a.wrapping_add(n)));
+    }
+
+    #[test]
+    fn less_than_half_the_space_ahead_is_later(a in near_wrap(), n in 1..1u32 << 31) {
+        let (a, b) = (SeqNum(a), SeqNum(a) + n);
+        prop_assert!(a < b);
+        prop_assert!(b > a);
+        prop_assert!(a != b);
+        prop_assert_eq!(b.partial_cmp(&a), Some(Ordering::Greater));
+    }
+
+    #[test]
+    fn half_the_space_apart_is_not_ordered(a in near_wrap()) {
+        let (a, b) = (SeqNum(a), SeqNum(a) + (1 << 31));
+        prop_assert_eq!(a.partial_cmp(&b), None);
+        prop_assert_eq!(b.partial_cmp(&a), None);
+    }
+
+    #[test]
+    fn in_range_across_the_wrap(start in near_wrap(), len in 0..1u32 << 20, k in 0..1u32 << 21) {
+        let start = SeqNum(start);
+        prop_assert_eq!((start + k).in_range(start, start + len), k < len);
+    }
+
+    #[test]
+    fn header_keeps_sequence_numbers(seq in near_wrap(), ack in near_wrap(), stream_seq in near_wrap()) {
+        let header = TcpHeader {
+            source_port: 1,
+            destination_port: 2,
+            sequence_number: SeqNum(seq),
+            ack_number: SeqNum(ack),
+            header_length: 4,
+            frame_flags: 0,
+            flags: 16,
+            window_size: 0,
+            connection_id: 0,
+            stream_id: 0,
+            stream_seq: SeqNum(stream_seq),
+            hash_value: [0; 32],
+        };
+        let parsed = TcpHeader::new(&header.as_bytes()).unwrap();
+        prop_assert_eq!(parsed.sequence_number, SeqNum(seq));
+        prop_assert_eq!(parsed.ack_number, SeqNum(ack));
+        prop_assert_eq!(parsed.stream_seq, SeqNum(stream_seq));
+    }
+}
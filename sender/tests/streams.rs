@@ -0,0 +1,80 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use sender::util::tcp_header::TcpHeader;
+
+mod common;
+use common::Link;
+
+// Loses every 50th data segment
+fn lossy() -> Link {
+    let mut count = 0;
+    Link::new(move |buf| {
+        if TcpHeader::new(buf).unwrap().flags != 0b0001_1000 {
+            return true;
+        }
+        count += 1;
+        count % 50 != 0
+    })
+}
+
+#[test]
+fn streams_are_delivered_whole_and_take_turns() {
+    let mut link = lossy();
+    let mut sent = BTreeMap::new();
+    for i in 0..3u8 {
+        let stream_id = if i == 0 { 0 } else { link.sender.open_stream() };
+        let data: Vec<u8> = (0..200_000u32).map(|j| (j % 251) as u8 ^ i).collect();
+        link.sender.send(stream_id, &data).unwrap();
+        sent.insert(stream_id, data);
+    }
+
+    // What each stream delivered, and how much every stream had when the first one was complete
+    let mut received: BTreeMap<u16, Vec<u8>> = BTreeMap::new();
+    let mut first_done = None;
+    let finished = link.run(Duration::from_secs(30), |receiver| {
+        for stream_id in receiver.readable() {
+            received.entry(stream_id).or_default().extend(receiver.read(stream_id));
+        }
+        if first_done.is_none() && received.iter().any(|(id, data)| data.len() == sent[id].len()) {
+            first_done = Some(received.values().map(Vec::len).collect::<Vec<_>>());
+        }
+    });
+
+    assert!(finished);
+    assert_eq!(received, sent);
+    // Streams were sent side by side, not one after the other
+    let first_done = first_done.unwrap();
+    assert!(first_done.iter().all(|&len| len > 100_000), "{first_done:?}");
+}
+
+#[test]
+fn a_stream_is_not_held_up_by_a_loss_on_another() {
+    // Lose the first data segment of stream 0
+    let mut dropped = false;
+    let mut link = Link::new(move |buf| {
+        let header = TcpHeader::new(buf).unwrap();
+        let first = header.flags == 0b0001_1000 && header.stream_id == 0;
+        !first || std::mem::replace(&mut dropped, true)
+    });
+    let other = link.sender.open_stream();
+    let data: Vec<u8> = (0..20_000u32).map(|j| (j % 251) as u8).collect();
+    link.sender.send(0, &data).unwrap();
+    link.sender.send(other, &data).unwrap();
+
+    // How much the other stream delivered before stream 0 delivered anything
+    let mut before = None;
+    let mut received = [Vec::new(), Vec::new()];
+    let finished = link.run(Duration::from_secs(10), |receiver| {
+        received[1].extend(receiver.read(other));
+        let data = receiver.read(0);
+        if before.is_none() && !data.is_empty() {
+            before = Some(received[1].len());
+        }
+        received[0].extend(data);
+    });
+
+    assert!(finished);
+    assert_eq!(received, [data.clone(), data]);
+    assert!(before.unwrap() > 10_000, "{before:?}");
+}
@@ -0,0 +1,82 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::Duration;
+
+use sender::util::seq::SeqNum;
+use sender::util::tcp_header::TcpHeader;
+
+mod common;
+use common::Link;
+
+fn records() -> Vec<Vec<u8>> {
+    (0..500u32).map(|i| [&i.to_be_bytes()[..], &[7; 500]].concat()).collect()
+}
+
+#[test]
+fn records_come_out_as_they_arrive() {
+    for nth in [11, 20] {
+        // Lose every nth data segment
+        let mut count = 0;
+        let mut link = Link::new(move |buf| {
+            if TcpHeader::new(buf).unwrap().flags != 0b0001_1000 {
+                return true;
+            }
+            count += 1;
+            count % nth != 0
+        });
+        let records = records();
+        for record in &records {
+            link.sender.send_unordered(0, record).unwrap();
+        }
+
+        let mut received = Vec::new();
+        let finished = link.run(Duration::from_secs(30), |receiver| {
+            received.extend(std::iter::from_fn(|| receiver.recv_message(0)));
+        });
+
+        assert!(finished, "{nth}");
+        // Records behind a lost one didn't wait for it
+        assert_ne!(received, records, "{nth}");
+        received.sort();
+        assert_eq!(received, records, "{nth}");
+    }
+}
+
+#[test]
+fn only_the_lost_record_goes_again() {
+    // Lose the third data segment once, and count how many times each is sent
+    let sent = Rc::new(RefCell::new(HashMap::<SeqNum, u32>::new()));
+    let counter = sent.clone();
+    let mut link = Link::new(move |buf| {
+        let header = TcpHeader::new(buf).unwrap();
+        if header.flags != 0b0001_1000 {
+            return true;
+        }
+        let mut sent = counter.borrow_mut();
+        *sent.entry(header.sequence_number).or_default() += 1;
+        !(sent.len() == 3 && sent[&header.sequence_number] == 1)
+    });
+    // Few enough that the window never fills
+    for record in &records()[..30] {
+        link.sender.send_unordered(0, record).unwrap();
+    }
+
+    let mut received = Vec::new();
+    let finished = link.run(Duration::from_secs(30), |receiver| {
+        received.extend(std::iter::from_fn(|| receiver.recv_message(0)));
+    });
+
+    assert!(finished);
+    assert_eq!(received.len(), 30);
+    // The receiver reported what it held behind the hole, so nothing else went again
+    let resent = sent.borrow().values().filter(|&&times| times > 1).count();
+    assert_eq!(resent, 1);
+}
+
+#[test]
+fn a_record_larger_than_a_segment_is_refused() {
+    let mut link = Link::clean();
+    let err = link.sender.send_unordered(0, &[7; 5000]).unwrap_err();
+    assert!(err.contains("doesn't fit"), "{err}");
+}
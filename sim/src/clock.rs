@@ -0,0 +1,61 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+// Virtual time shared by both ends of a simulation, it only moves when the simulation advances it
+#[derive(Debug)]
+pub struct SimClock {
+    base: Instant,
+    nanos: AtomicU64, // Time since the simulation started
+}
+
+impl SimClock {
+    pub fn new() -> Self {
+        SimClock {
+            base: Instant::now(),
+            nanos: AtomicU64::new(0),
+        }
+    }
+
+    // Simulated time since the start
+    pub fn elapsed(&self) -> Duration {
+        Duration::from_nanos(self.nanos.load(Ordering::SeqCst))
+    }
+
+    // Simulated seconds since the start, the emulator's unit
+    pub fn secs(&self) -> f64 {
+        self.elapsed().as_secs_f64()
+    }
+
+    // Simulated time of an instant handed out by this clock
+    pub fn since_start(&self, instant: Instant) -> Duration {
+        instant.saturating_duration_since(self.base)
+    }
+
+    // Move the clock forward, it never goes back
+    pub fn advance_to(&self, elapsed: Duration) {
+        self.nanos
+            .fetch_max(elapsed.as_nanos() as u64, Ordering::SeqCst);
+    }
+
+    fn now(&self) -> Instant {
+        self.base + self.elapsed()
+    }
+}
+
+impl Default for SimClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl sender::Clock for SimClock {
+    fn now(&self) -> Instant {
+        SimClock::now(self)
+    }
+}
+
+impl receiver::Clock for SimClock {
+    fn now(&self) -> Instant {
+        SimClock::now(self)
+    }
+}
@@ -0,0 +1,7 @@
+pub mod clock;
+pub mod link;
+pub mod simulation;
+
+pub use clock::SimClock;
+pub use link::{Link, SimSocket};
+pub use simulation::{Outcome, Simulation};
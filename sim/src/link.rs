@@ -0,0 +1,150 @@
+use std::collections::VecDeque;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use impair::{Direction, Emulator, Stats};
+
+use crate::clock::SimClock;
+
+// Emulated network between the two ends, packets arrive in an inbox instead of a socket
+#[derive(Debug)]
+pub struct Link {
+    emulator: Emulator,
+    sender: SocketAddr,
+    receiver: SocketAddr,
+    seen_as: SocketAddr, // The sender's address as the receiver sees it, a NAT in front of the sender may move it
+    to_sender: VecDeque<Vec<u8>>, // Delivered, waiting for the sender to read
+    to_receiver: VecDeque<Vec<u8>>, // Delivered, waiting for the receiver to read
+}
+
+impl Link {
+    pub fn new(emulator: Emulator, sender: SocketAddr, receiver: SocketAddr) -> Self {
+        Link {
+            emulator,
+            sender,
+            receiver,
+            seen_as: sender,
+            to_sender: VecDeque::new(),
+            to_receiver: VecDeque::new(),
+        }
+    }
+
+    // Move the packets that are due into the inboxes
+    pub fn deliver(&mut self, now: f64) {
+        let to_sender = self
+            .emulator
+            .ready_to_deliver(Direction::ReceiverToSender, now);
+        self.to_sender.extend(to_sender);
+        let to_receiver = self
+            .emulator
+            .ready_to_deliver(Direction::SenderToReceiver, now);
+        self.to_receiver.extend(to_receiver);
+    }
+
+    // Whether something is waiting to be read or due right now
+    pub fn busy(&self, now: f64) -> bool {
+        !self.to_sender.is_empty()
+            || !self.to_receiver.is_empty()
+            || self.emulator.sleep_time(now) == 0.0
+    }
+
+    // Seconds until a packet may be due
+    pub fn sleep_time(&self, now: f64) -> f64 {
+        self.emulator.sleep_time(now)
+    }
+
+    // Give the sender a new address from now on, what the receiver sends to the old one is lost.
+    // Packets already on the link arrive from the new address too.
+    pub fn rebind_sender(&mut self, addr: SocketAddr) {
+        self.seen_as = addr;
+    }
+
+    // Counters of the given direction
+    pub fn stats(&self, direction: Direction) -> Stats {
+        self.emulator.stats(direction)
+    }
+
+    fn send(&mut self, from: SocketAddr, to: &str, data: &[u8], now: f64) -> io::Result<usize> {
+        let to: SocketAddr = to
+            .parse()
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Invalid address"))?;
+        let direction = if from == self.sender && to == self.receiver {
+            Direction::SenderToReceiver
+        } else if from == self.receiver && to == self.seen_as {
+            Direction::ReceiverToSender
+        } else {
+            // Nothing else is on the link
+            return Ok(data.len());
+        };
+        self.emulator.packet_received(direction, data, now);
+        Ok(data.len())
+    }
+
+    fn recv(&mut self, at: SocketAddr, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        let (inbox, from) = if at == self.sender {
+            (&mut self.to_sender, self.receiver)
+        } else {
+            (&mut self.to_receiver, self.seen_as)
+        };
+        match inbox.pop_front() {
+            Some(data) => {
+                let len = data.len().min(buf.len());
+                buf[..len].copy_from_slice(&data[..len]);
+                Ok((len, from))
+            }
+            None => Err(io::ErrorKind::WouldBlock.into()),
+        }
+    }
+}
+
+// One end's view of the link
+#[derive(Debug, Clone)]
+pub struct SimSocket {
+    addr: SocketAddr,
+    link: Arc<Mutex<Link>>,
+    clock: Arc<SimClock>,
+}
+
+impl SimSocket {
+    pub fn new(addr: SocketAddr, link: Arc<Mutex<Link>>, clock: Arc<SimClock>) -> Self {
+        SimSocket { addr, link, clock }
+    }
+
+    fn send(&self, buf: &[u8], addr: &str) -> io::Result<usize> {
+        let now = self.clock.secs();
+        self.link.lock().unwrap().send(self.addr, addr, buf, now)
+    }
+
+    fn recv(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        self.link.lock().unwrap().recv(self.addr, buf)
+    }
+}
+
+impl sender::Transport for SimSocket {
+    fn send_to(&self, buf: &[u8], addr: &str) -> io::Result<usize> {
+        self.send(buf, addr)
+    }
+
+    fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        self.recv(buf)
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.addr)
+    }
+}
+
+impl receiver::Transport for SimSocket {
+    fn send_to(&self, buf: &[u8], addr: &str) -> io::Result<usize> {
+        self.send(buf, addr)
+    }
+
+    fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        self.recv(buf)
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.addr)
+    }
+}
@@ -0,0 +1,223 @@
+use rand::prelude::*;
+use rand::rngs::StdRng;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use impair::{Direction, Emulator, Network, Stats};
+use receiver::Receiver;
+use sender::Sender;
+
+use crate::clock::SimClock;
+use crate::link::{Link, SimSocket};
+
+// Smallest step of the clock, so a busy end can't stall the simulation
+const MIN_STEP: Duration = Duration::from_micros(1);
+
+// Result of a run
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Outcome {
+    pub finished: bool, // The sender got everything acknowledged within the lifetime
+    pub received: Vec<u8>, // Data the receiver delivered on stream 0, empty if the test did the reading
+    pub elapsed: Duration, // Simulated time of the run
+    pub s_to_r: Stats,
+    pub r_to_s: Stats,
+    pub sender: sender::Stats,     // Counters kept by each end
+    pub receiver: receiver::Stats,
+}
+
+// Sender and receiver talking through the emulator on a virtual clock.
+// Everything random is derived from one seed, so a run is replayed exactly.
+#[derive(Debug)]
+pub struct Simulation {
+    clock: Arc<SimClock>,
+    link: Arc<Mutex<Link>>,
+    sender: Sender,
+    receiver: Receiver,
+}
+
+impl Simulation {
+    pub fn new(network: Network, seed: u64) -> Result<Self, String> {
+        Self::with_sender_transport(network, seed, |socket| Box::new(socket))
+    }
+
+    // Same as new, with the sender's socket wrapped by the given function, to watch or tamper with what it sends
+    pub fn with_sender_transport(
+        network: Network,
+        seed: u64,
+        wrap: impl FnOnce(SimSocket) -> Box<dyn sender::Transport>,
+    ) -> Result<Self, String> {
+        Self::with_transports(network, seed, wrap, |socket| Box::new(socket))
+    }
+
+    // Same as new, with the socket of each end wrapped by the given functions
+    pub fn with_transports(
+        network: Network,
+        seed: u64,
+        wrap_sender: impl FnOnce(SimSocket) -> Box<dyn sender::Transport>,
+        wrap_receiver: impl FnOnce(SimSocket) -> Box<dyn receiver::Transport>,
+    ) -> Result<Self, String> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let sender_addr: SocketAddr = "10.0.0.1:1000".parse().unwrap();
+        let receiver_addr: SocketAddr = "10.0.0.2:2000".parse().unwrap();
+
+        let clock = Arc::new(SimClock::new());
+        // Same impairments as impair with this seed
+        let emulator = Emulator::new(network, Some(seed), 0.0);
+        let link = Arc::new(Mutex::new(Link::new(emulator, sender_addr, receiver_addr)));
+
+        let receiver = Receiver::with_transport(
+            wrap_receiver(SimSocket::new(receiver_addr, link.clone(), clock.clone())),
+            clock.clone(),
+            rng.gen(),
+        )?;
+        let sender = Sender::with_transport(
+            receiver_addr.ip().to_string(),
+            receiver_addr.port(),
+            wrap_sender(SimSocket::new(sender_addr, link.clone(), clock.clone())),
+            clock.clone(),
+            rng.gen(),
+            65340,
+            4,
+        )?;
+
+        Ok(Simulation {
+            clock,
+            link,
+            sender,
+            receiver,
+        })
+    }
+
+    // The sender, to set it up beyond what the setters below cover or queue more than stream 0
+    pub fn sender(&mut self) -> &mut Sender {
+        &mut self.sender
+    }
+
+    // The receiver, to set it up beyond what the setters below cover
+    pub fn receiver(&mut self) -> &mut Receiver {
+        &mut self.receiver
+    }
+
+    // Start both ends at the given sequence numbers instead of random ones
+    pub fn set_initial_seqs(&mut self, sender: u32, receiver: u32) -> Result<(), String> {
+        self.sender.set_initial_seq(sender)?;
+        self.receiver.set_initial_seq(receiver)
+    }
+
+    // Encrypt the connection with the given pre-shared key on both ends
+    pub fn set_psk(&mut self, psk: &[u8]) -> Result<(), String> {
+        self.sender.set_psk(sender::Psk::new(psk)?)?;
+        self.receiver.set_psk(receiver::Psk::new(psk)?)
+    }
+
+    // Encrypt the connection with keys from a Noise handshake between the given static keys,
+    // the sender pinning the receiver's and the receiver allowing the given senders if asked
+    pub fn set_identities(
+        &mut self,
+        sender: &[u8],
+        receiver: &[u8],
+        pinned: Option<[u8; 32]>,
+        allowed: Option<Vec<[u8; 32]>>,
+    ) -> Result<(), String> {
+        self.sender.set_identity(sender::Identity::new(sender)?, pinned)?;
+        self.receiver.set_identity(receiver::Identity::new(receiver)?, allowed)
+    }
+
+    // Have the receiver hand out resumption tokens under this key, and take them back
+    pub fn set_resumption_key(&mut self, key: &[u8]) -> Result<(), String> {
+        self.receiver.set_resumption_key(receiver::ResumptionKey::new(key)?)
+    }
+
+    // Have the sender bring back a token from an earlier run
+    pub fn set_resumption_token(&mut self, token: &[u8]) -> Result<(), String> {
+        self.sender.set_resumption_token(token)
+    }
+
+    // Token the receiver handed out during the run, for the next one
+    pub fn resumption_token(&self) -> Option<Vec<u8>> {
+        self.sender.resumption_token()
+    }
+
+    // Give up on a quiet peer after this long, on both ends
+    pub fn set_idle_timeout(&mut self, timeout: Duration) {
+        self.sender.set_idle_timeout(Some(timeout));
+        self.receiver.set_idle_timeout(Some(timeout));
+    }
+
+    // Give up instead of resending a packet more than this many times
+    pub fn set_max_retransmits(&mut self, max: u32) {
+        self.sender.set_max_retransmits(Some(max));
+    }
+
+    // Move the sender to a new address, as a NAT rebinding would
+    pub fn rebind_sender(&mut self, addr: SocketAddr) {
+        self.link.lock().unwrap().rebind_sender(addr);
+    }
+
+    // Write the event log of each end to the given files
+    pub fn log_events(&mut self, sender: &Path, receiver: &Path) -> Result<(), String> {
+        self.sender.log_events(sender)?;
+        self.receiver.log_events(receiver)
+    }
+
+    // Transfer the data on stream 0, until the sender is done or the lifetime is over
+    pub fn run(&mut self, data: &[u8], lifetime: Duration) -> Result<Outcome, String> {
+        self.sender.send(0, data)?;
+        let mut received = Vec::new();
+        let outcome = self.run_with(lifetime, |receiver| {
+            for stream_id in receiver.readable() {
+                let data = receiver.read(stream_id);
+                if stream_id == 0 {
+                    received.extend(data);
+                }
+            }
+        })?;
+        Ok(Outcome { received, ..outcome })
+    }
+
+    // Run until the sender got everything it has queued acknowledged or the lifetime is over, with the given
+    // function reading from the receiver after every poll in place of the application. Nothing is received.
+    pub fn run_with(&mut self, lifetime: Duration, mut read: impl FnMut(&mut Receiver)) -> Result<Outcome, String> {
+        let finished = loop {
+            let now = self.clock.elapsed();
+            if now > lifetime {
+                break false;
+            }
+
+            // Let both ends react to everything that happens at this instant
+            let finished = loop {
+                self.link.lock().unwrap().deliver(self.clock.secs());
+                let finished = self.sender.poll()?;
+                self.receiver.poll()?;
+                read(&mut self.receiver);
+                if finished || !self.link.lock().unwrap().busy(self.clock.secs()) {
+                    break finished;
+                }
+            };
+            if finished {
+                break true;
+            }
+
+            // Jump to the next packet arrival or retransmission timeout
+            let sleep = self.link.lock().unwrap().sleep_time(self.clock.secs());
+            let mut next = now + Duration::from_secs_f64(sleep);
+            if let Some(timeout) = self.sender.next_timeout() {
+                next = next.min(self.clock.since_start(timeout));
+            }
+            self.clock.advance_to(next.max(now + MIN_STEP));
+        };
+
+        let link = self.link.lock().unwrap();
+        Ok(Outcome {
+            finished,
+            received: Vec::new(),
+            elapsed: self.clock.elapsed(),
+            s_to_r: link.stats(Direction::SenderToReceiver),
+            r_to_s: link.stats(Direction::ReceiverToSender),
+            sender: self.sender.stats(),
+            receiver: self.receiver.stats(),
+        })
+    }
+}
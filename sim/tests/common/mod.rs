@@ -0,0 +1,90 @@
+// Helpers shared by the tests, each test file uses some of them
+#![allow(dead_code)]
+
+use std::fmt;
+use std::io;
+use std::net::SocketAddr;
+
+use impair::Network;
+use sim::SimSocket;
+
+// A short path with room for everything, nothing lost
+pub fn clean() -> Network {
+    Network {
+        delay: 0.01,
+        bandwidth: 1_000_000.0,
+        buffer: 64_000,
+        drop: None,
+        duplicate: None,
+        mangle: None,
+        jitter: None,
+    }
+}
+
+// Sender socket that shows every datagram to the given function before sending it, and drops it if that
+// returns false. Tests watch or tamper with what the sender sends through it.
+pub fn tap(inner: SimSocket, filter: impl Fn(&[u8]) -> bool + Send + 'static) -> Box<dyn sender::Transport> {
+    Box::new(Tap { inner, filter })
+}
+
+struct Tap<F> {
+    inner: SimSocket,
+    filter: F,
+}
+
+impl<F> fmt::Debug for Tap<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Tap").field("inner", &self.inner).finish_non_exhaustive()
+    }
+}
+
+impl<F: Fn(&[u8]) -> bool + Send> sender::Transport for Tap<F> {
+    fn send_to(&self, buf: &[u8], addr: &str) -> io::Result<usize> {
+        if !(self.filter)(buf) {
+            return Ok(buf.len());
+        }
+        sender::Transport::send_to(&self.inner, buf, addr)
+    }
+
+    fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        sender::Transport::recv_from(&self.inner, buf)
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        sender::Transport::local_addr(&self.inner)
+    }
+}
+
+// Receiver socket that shows every datagram to the given function: those it sends with the address they go to,
+// those it receives with none. Tests watch where the receiver sends what through it.
+pub fn watch(inner: SimSocket, f: impl Fn(&[u8], Option<&str>) + Send + 'static) -> Box<dyn receiver::Transport> {
+    Box::new(Watch { inner, f })
+}
+
+struct Watch<F> {
+    inner: SimSocket,
+    f: F,
+}
+
+impl<F> fmt::Debug for Watch<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Watch").field("inner", &self.inner).finish_non_exhaustive()
+    }
+}
+
+impl<F: Fn(&[u8], Option<&str>) + Send> receiver::Transport for Watch<F> {
+    fn send_to(&self, buf: &[u8], addr: &str) -> io::Result<usize> {
+        (self.f)(buf, Some(addr));
+        receiver::Transport::send_to(&self.inner, buf, addr)
+    }
+
+    fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        let (len, from) = receiver::Transport::recv_from(&self.inner, buf)?;
+        (self.f)(&buf[..len], None);
+        Ok((len, from))
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        receiver::Transport::local_addr(&self.inner)
+    }
+}
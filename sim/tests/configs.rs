@@ -0,0 +1,77 @@
+use rand::prelude::*;
+use rand::rngs::StdRng;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use impair::Config;
+use sim::Simulation;
+
+// Bytes on the link in both directions, as a multiple of the data size
+const MAX_OVERHEAD: f64 = 3.0;
+// The queue of the slowest link holds more than 3 seconds, so timeouts fire while packets still wait in it
+const MAX_OVERHEAD_LOW_BANDWIDTH: f64 = 6.0;
+
+fn configs() -> Vec<PathBuf> {
+    let dir = format!(
+        "{}/../transport-starter-code-main/configs",
+        env!("CARGO_MANIFEST_DIR")
+    );
+    let mut paths: Vec<_> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "conf"))
+        .collect();
+    paths.sort();
+    paths
+}
+
+// Run one config the way the test script does, returns what went wrong
+fn check(path: &Path) -> Result<(), String> {
+    let name = path.file_stem().unwrap().to_string_lossy().to_string();
+    let config = Config::from_file(path.to_str().unwrap())?;
+    let seed = config.seed.unwrap_or(0);
+    let lifetime = Duration::from_secs_f64(config.lifetime.unwrap_or(f64::MAX));
+
+    let mut data = vec![0; config.data.unwrap_or(0)];
+    StdRng::seed_from_u64(seed).fill_bytes(&mut data);
+
+    let outcome = Simulation::new(config.network, seed)?.run(&data, lifetime)?;
+    if !outcome.finished {
+        return Err(format!(
+            "{name}: sender did not finish within {:?}",
+            lifetime
+        ));
+    }
+    if outcome.received != data {
+        return Err(format!(
+            "{name}: data was not transmitted correctly, sent {} bytes, received {} bytes",
+            data.len(),
+            outcome.received.len()
+        ));
+    }
+
+    let max = if name == "7-1-low-bandwidth" {
+        MAX_OVERHEAD_LOW_BANDWIDTH
+    } else {
+        MAX_OVERHEAD
+    };
+    let sent = outcome.s_to_r.bytes + outcome.r_to_s.bytes;
+    let overhead = sent as f64 / data.len().max(1) as f64;
+    if overhead > max {
+        return Err(format!(
+            "{name}: {sent} bytes sent for {} bytes of data ({overhead:.2}x, at most {max}x)",
+            data.len()
+        ));
+    }
+    Ok(())
+}
+
+#[test]
+fn every_config_is_delivered_within_its_lifetime() {
+    let paths = configs();
+    assert!(!paths.is_empty(), "no configs found");
+
+    let failures: Vec<String> = paths.iter().filter_map(|path| check(path).err()).collect();
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
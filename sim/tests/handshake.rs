@@ -0,0 +1,30 @@
+use std::time::Duration;
+
+use impair::Network;
+use sim::Simulation;
+
+// Half of the packets in each direction are lost, the handshake too
+fn network() -> Network {
+    Network {
+        delay: 0.05,
+        bandwidth: 1_000_000.0,
+        buffer: 64_000,
+        drop: Some(0.5),
+        duplicate: None,
+        mangle: None,
+        jitter: Some(0.02),
+    }
+}
+
+#[test]
+fn the_connection_is_set_up_whichever_handshake_packets_are_lost() {
+    let data = b"handshake".repeat(100);
+    for seed in 0..40 {
+        let outcome = Simulation::new(network(), seed)
+            .unwrap()
+            .run(&data, Duration::from_secs(120))
+            .unwrap();
+        assert!(outcome.finished, "seed {seed}: not finished after {:?}", outcome.elapsed);
+        assert_eq!(outcome.received, data, "seed {seed}");
+    }
+}
@@ -0,0 +1,54 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use receiver::util::tcp_header::{TcpHeader, PATH_CHALLENGE, PATH_RESPONSE};
+use receiver::Receiver;
+use sim::Simulation;
+
+mod common;
+use common::{clean, watch};
+
+const MOVED: &str = "10.0.0.3:3000";
+
+#[test]
+fn the_receiver_follows_a_sender_that_moves_once_it_answers_from_there() {
+    // Flags of what the receiver sends, with the address, and of what it receives
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let log = events.clone();
+    let mut sim = Simulation::with_transports(
+        clean(),
+        1,
+        |socket| Box::new(socket),
+        |socket| {
+            watch(socket, move |datagram, to| {
+                let flags = TcpHeader::new(datagram).unwrap().flags;
+                log.lock().unwrap().push((flags, to.map(str::to_string)));
+            })
+        },
+    )
+    .unwrap();
+    let data: Vec<u8> = (0..200_000).map(|i| (i % 251) as u8).collect();
+    sim.sender().send(0, &data).unwrap();
+
+    let mut received = Vec::new();
+    let mut read = |receiver: &mut Receiver| received.extend(receiver.read(0));
+    let outcome = sim.run_with(Duration::from_millis(100), &mut read).unwrap();
+    assert!(!outcome.finished);
+    sim.rebind_sender(MOVED.parse().unwrap());
+    let outcome = sim.run_with(Duration::from_secs(10), &mut read).unwrap();
+    assert!(outcome.finished);
+    assert_eq!(received, data);
+
+    // Nothing but the challenge goes to the new address until the answer comes back from there
+    let events = events.lock().unwrap();
+    let to = |i: usize| events[i].1.as_deref();
+    let challenged = (0..events.len()).find(|&i| to(i) == Some(MOVED)).unwrap();
+    assert_eq!(events[challenged].0, PATH_CHALLENGE);
+    let answered = (0..events.len()).find(|&i| events[i] == (PATH_RESPONSE, None)).unwrap();
+    assert!(challenged < answered);
+    assert!((challenged..answered).all(|i| to(i) != Some(MOVED) || events[i].0 == PATH_CHALLENGE));
+    // Then the ACKs follow the sender
+    let sent: Vec<_> = (answered..events.len()).filter_map(to).collect();
+    assert!(!sent.is_empty());
+    assert!(sent.iter().all(|&addr| addr == MOVED));
+}
@@ -0,0 +1,90 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use sim::Simulation;
+
+mod common;
+use common::{clean, tap};
+
+// Transfer the data over a clean link through a path of the given MTU, which changes to `then` once half the
+// data is delivered, to a receiver taking datagrams of up to `max_datagram` bytes. Returns whether the sender
+// finished, the data delivered and the sizes of the datagrams that got through, before and after the change.
+fn transfer(mtu: usize, then: usize, max_datagram: usize, data: &[u8]) -> (bool, Vec<u8>, Vec<usize>, Vec<usize>) {
+    let path_mtu = Arc::new(Mutex::new(mtu));
+    let passed = Arc::new(Mutex::new(Vec::new()));
+    // The path drops datagrams above its MTU, the sizes of those it lets through are kept
+    let (tap_mtu, tap_passed) = (path_mtu.clone(), passed.clone());
+    let mut sim = Simulation::with_sender_transport(clean(), 1, |inner| {
+        tap(inner, move |buf| {
+            let fits = buf.len() <= *tap_mtu.lock().unwrap();
+            if fits {
+                tap_passed.lock().unwrap().push(buf.len());
+            }
+            fits
+        })
+    })
+    .unwrap();
+    sim.receiver().set_max_datagram_size(max_datagram).unwrap();
+    sim.sender().send(0, data).unwrap();
+
+    let mut received = Vec::new();
+    let mut before = None;
+    let outcome = sim
+        .run_with(Duration::from_secs(30), |receiver| {
+            received.extend(receiver.read(0));
+            if before.is_none() && received.len() >= data.len() / 2 {
+                *path_mtu.lock().unwrap() = then;
+                before = Some(passed.lock().unwrap().len());
+            }
+        })
+        .unwrap();
+
+    let mut passed = passed.lock().unwrap().clone();
+    let after = passed.split_off(before.unwrap_or(passed.len()));
+    (outcome.finished, received, passed, after)
+}
+
+#[test]
+fn segments_grow_to_what_the_path_carries() {
+    let data: Vec<u8> = (0..1_000_000).map(|i| (i % 251) as u8).collect();
+    for mtu in [1500, 1300] {
+        let (finished, received, passed, _) = transfer(mtu, mtu, 1500, &data);
+        assert!(finished, "{mtu}");
+        assert_eq!(received, data, "{mtu}");
+        // The search ends within 16 bytes of the path MTU
+        let largest = passed.iter().copied().max().unwrap();
+        assert!(largest > mtu - 16, "{mtu}: {largest}");
+        assert!(passed.iter().filter(|&&size| size == largest).count() > 50, "{mtu}");
+    }
+}
+
+#[test]
+fn a_path_that_narrows_falls_back_to_the_base_size() {
+    let data: Vec<u8> = (0..1_000_000).map(|i| (i % 251) as u8).collect();
+    let (finished, received, before, after) = transfer(1500, 1250, 1500, &data);
+    assert!(finished);
+    assert_eq!(received, data);
+    assert_eq!(before.iter().copied().max(), Some(1500));
+    // The data in flight when the path changed went again in pieces, then the search found the new size
+    assert!(after.iter().any(|&size| size > 1250 - 16 && size <= 1250), "{after:?}");
+}
+
+#[test]
+fn segments_stay_within_what_the_receiver_takes() {
+    let data: Vec<u8> = (0..1_000_000).map(|i| (i % 251) as u8).collect();
+    let (finished, received, passed, _) = transfer(1500, 1500, 1300, &data);
+    assert!(finished);
+    assert_eq!(received, data);
+    // The receiver announced 1300 bytes in its SYN-ACK, the search starts there instead of probing the path
+    assert_eq!(passed.iter().copied().max(), Some(1300));
+}
+
+#[test]
+fn a_receiver_that_takes_less_than_the_base_size_gets_what_it_takes() {
+    let data: Vec<u8> = (0..200_000).map(|i| (i % 251) as u8).collect();
+    let (finished, received, passed, _) = transfer(1500, 1500, 600, &data);
+    assert!(finished);
+    assert_eq!(received, data);
+    // Nothing above what the receiver announced, not even the first segments cut to the base size
+    assert_eq!(passed.iter().copied().max(), Some(600));
+}
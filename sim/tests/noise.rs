@@ -0,0 +1,90 @@
+use rand::prelude::*;
+use rand::rngs::StdRng;
+use std::fs;
+use std::io;
+use std::time::Duration;
+
+use impair::Config;
+use sender::util::tcp_header::TcpHeader;
+use sim::Simulation;
+
+mod common;
+use common::{clean, tap};
+
+const SENDER_KEY: [u8; 32] = [1; 32];
+const RECEIVER_KEY: [u8; 32] = [2; 32];
+
+fn public(private: &[u8]) -> [u8; 32] {
+    sender::Identity::new(private).unwrap().public()
+}
+
+#[test]
+fn every_config_transfers_after_a_noise_handshake() {
+    let dir = format!("{}/../transport-starter-code-main/configs", env!("CARGO_MANIFEST_DIR"));
+    let mut paths: Vec<_> = fs::read_dir(dir).unwrap().map(|entry| entry.unwrap().path()).collect();
+    paths.sort();
+
+    for path in paths.iter().filter(|path| path.extension().is_some_and(|ext| ext == "conf")) {
+        let config = Config::from_file(path.to_str().unwrap()).unwrap();
+        let seed = config.seed.unwrap_or(0);
+        let mut data = vec![0; config.data.unwrap_or(0)];
+        StdRng::seed_from_u64(seed).fill_bytes(&mut data);
+
+        // The cookie retry takes the Noise handshake one round trip more, as slow as the jitter makes it
+        let round_trip = 2.0 * (config.network.delay + config.network.jitter.unwrap_or(0.0));
+        let lifetime = config.lifetime.unwrap_or(f64::MAX) + round_trip;
+        let mut sim = Simulation::new(config.network, seed).unwrap();
+        let allowed = vec![public(&SENDER_KEY)];
+        sim.set_identities(&SENDER_KEY, &RECEIVER_KEY, Some(public(&RECEIVER_KEY)), Some(allowed))
+            .unwrap();
+        let outcome = sim
+            .run(&data, Duration::from_secs_f64(lifetime))
+            .unwrap();
+        assert!(outcome.finished, "{} did not finish", path.display());
+        assert_eq!(outcome.received, data, "{}", path.display());
+    }
+}
+
+#[test]
+fn the_receiver_never_answers_a_noise_syn_with_more_than_it_got() {
+    // Only SYNs get through: the receiver answers the first with its cookie and the one echoing it with its
+    // Noise message
+    let mut sim = Simulation::with_sender_transport(clean(), 1, |inner| {
+        tap(inner, |buf| TcpHeader::new(buf).is_ok_and(|header| header.flags == 0b0000_0010))
+    })
+    .unwrap();
+    sim.set_identities(&SENDER_KEY, &RECEIVER_KEY, Some(public(&RECEIVER_KEY)), None)
+        .unwrap();
+    let outcome = sim.run(&[7; 1000], Duration::from_secs(1)).unwrap();
+    assert!(!outcome.finished);
+    assert_eq!(outcome.s_to_r.packets, 2);
+    assert_eq!(outcome.r_to_s.packets, 2);
+    assert!(outcome.r_to_s.bytes <= outcome.s_to_r.bytes, "{:?} {:?}", outcome.r_to_s, outcome.s_to_r);
+}
+
+#[test]
+fn a_receiver_with_another_key_than_the_pinned_one_is_an_error() {
+    let mut sim = Simulation::new(clean(), 1).unwrap();
+    sim.set_identities(&SENDER_KEY, &RECEIVER_KEY, Some(public(&[3; 32])), None)
+        .unwrap();
+    let err = sim.run(&[7; 10_000], Duration::from_secs(10)).unwrap_err();
+    assert!(err.contains("not the pinned one"), "{err}");
+}
+
+#[test]
+fn a_sender_that_is_not_allowed_is_turned_away() {
+    let mut sim = Simulation::new(clean(), 1).unwrap();
+    sim.set_identities(&SENDER_KEY, &RECEIVER_KEY, None, Some(vec![public(&[3; 32])]))
+        .unwrap();
+    let err = sim.run(&[7; 10_000], Duration::from_secs(10)).unwrap_err();
+    assert!(err.contains("reset"), "{err}");
+    assert_eq!(sim.sender().error(), Some(io::ErrorKind::ConnectionReset));
+    assert_eq!(sim.receiver().read(0), Vec::<u8>::new());
+}
+
+#[test]
+fn noise_and_a_pre_shared_key_do_not_mix() {
+    let mut sim = Simulation::new(clean(), 1).unwrap();
+    sim.set_psk(b"correct horse battery staple").unwrap();
+    assert!(sim.set_identities(&SENDER_KEY, &RECEIVER_KEY, None, None).is_err());
+}
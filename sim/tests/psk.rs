@@ -0,0 +1,76 @@
+use rand::prelude::*;
+use rand::rngs::StdRng;
+use std::fs;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use impair::Config;
+use sim::{Outcome, Simulation};
+
+mod common;
+use common::{clean, tap};
+
+const KEY: &[u8] = b"correct horse battery staple";
+
+// Both ends over a clean link with the given keys, returns the outcome and the datagrams the sender sent
+fn transfer(sender_key: &[u8], receiver_key: &[u8], data: &[u8], lifetime: Duration) -> (Outcome, Vec<Vec<u8>>) {
+    let sent = Arc::new(Mutex::new(Vec::new()));
+    let recorder = sent.clone();
+    let mut sim = Simulation::with_sender_transport(clean(), 1, |inner| {
+        tap(inner, move |buf| {
+            recorder.lock().unwrap().push(buf.to_vec());
+            true
+        })
+    })
+    .unwrap();
+    sim.sender().set_psk(sender::Psk::new(sender_key).unwrap()).unwrap();
+    sim.receiver().set_psk(receiver::Psk::new(receiver_key).unwrap()).unwrap();
+    let outcome = sim.run(data, lifetime).unwrap();
+
+    let sent = sent.lock().unwrap().clone();
+    (outcome, sent)
+}
+
+#[test]
+fn payloads_are_encrypted_on_the_wire() {
+    let data = b"attack at dawn, ".repeat(2000);
+    let (outcome, sent) = transfer(KEY, KEY, &data, Duration::from_secs(30));
+    assert!(outcome.finished);
+    assert_eq!(outcome.received, data);
+
+    // Nothing the sender sent gives the data away
+    assert!(sent.iter().all(|datagram| !datagram.windows(16).any(|w| w == b"attack at dawn, ")));
+}
+
+#[test]
+fn a_different_key_is_rejected() {
+    let data = vec![7; 10_000];
+    let (outcome, _) = transfer(KEY, b"not the key the sender has", &data, Duration::from_secs(10));
+    assert!(!outcome.finished);
+    assert!(outcome.received.is_empty());
+    // Every SYN fails its MAC
+    assert!(outcome.receiver.corrupted > 0);
+}
+
+#[test]
+fn every_config_transfers_sealed() {
+    let dir = format!("{}/../transport-starter-code-main/configs", env!("CARGO_MANIFEST_DIR"));
+    let mut paths: Vec<_> = fs::read_dir(dir).unwrap().map(|entry| entry.unwrap().path()).collect();
+    paths.sort();
+
+    for path in paths.iter().filter(|path| path.extension().is_some_and(|ext| ext == "conf")) {
+        let config = Config::from_file(path.to_str().unwrap()).unwrap();
+        let seed = config.seed.unwrap_or(0);
+        let mut data = vec![0; config.data.unwrap_or(0)];
+        StdRng::seed_from_u64(seed).fill_bytes(&mut data);
+
+        let mut sim = Simulation::new(config.network, seed).unwrap();
+        sim.set_psk(KEY).unwrap();
+        let outcome = sim
+            .run(&data, Duration::from_secs_f64(config.lifetime.unwrap_or(f64::MAX)))
+            .unwrap();
+        assert!(outcome.finished, "{} did not finish", path.display());
+        assert_eq!(outcome.received, data, "{}", path.display());
+    }
+}
+
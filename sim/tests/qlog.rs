@@ -0,0 +1,88 @@
+use rand::prelude::*;
+use rand::rngs::StdRng;
+use serde_json::Value;
+use std::fs;
+use std::time::Duration;
+
+use impair::Config;
+use plot::Trace;
+use sim::Simulation;
+
+fn events(path: &std::path::Path) -> (Value, Vec<Value>) {
+    let text = fs::read_to_string(path).unwrap();
+    let mut lines = text.lines().map(|line| serde_json::from_str::<Value>(line).unwrap());
+    (lines.next().unwrap(), lines.collect())
+}
+
+fn names<'a>(events: &'a [Value], name: &str) -> Vec<&'a Value> {
+    events.iter().filter(|event| event["name"] == name).collect()
+}
+
+#[test]
+fn event_logs_trace_both_ends() {
+    let path = format!(
+        "{}/../transport-starter-code-main/configs/4-1-drops.conf",
+        env!("CARGO_MANIFEST_DIR")
+    );
+    let config = Config::from_file(&path).unwrap();
+    let mut data = vec![0; config.data.unwrap()];
+    StdRng::seed_from_u64(3).fill_bytes(&mut data);
+
+    let dir = std::env::temp_dir().join(format!("sim-qlog-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let (sender_log, receiver_log) = (dir.join("sender.qlog"), dir.join("receiver.qlog"));
+
+    let mut sim = Simulation::new(config.network, 3).unwrap();
+    sim.log_events(&sender_log, &receiver_log).unwrap();
+    let outcome = sim
+        .run(&data, Duration::from_secs_f64(config.lifetime.unwrap()))
+        .unwrap();
+    assert!(outcome.finished);
+
+    let (header, sender) = events(&sender_log);
+    let (_, receiver) = events(&receiver_log);
+    let trace = Trace::from_file(&sender_log).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(header["vantage_point"]["type"], "client");
+    // Times only go forward and every event names the connection
+    let connection_id = &sender[0]["connection_id"];
+    for pair in sender.windows(2) {
+        assert!(pair[0]["time"].as_f64().unwrap() <= pair[1]["time"].as_f64().unwrap());
+    }
+    assert!(sender.iter().all(|event| &event["connection_id"] == connection_id));
+
+    let states: Vec<_> = names(&sender, "connectivity:connection_state_updated")
+        .iter()
+        .map(|event| event["data"]["new"].as_str().unwrap())
+        .collect();
+    assert_eq!(states, ["Handshake", "Sending", "Finished"]);
+    let states: Vec<_> = names(&receiver, "connectivity:connection_state_updated")
+        .iter()
+        .map(|event| event["data"]["new"].as_str().unwrap())
+        .collect();
+    assert_eq!(states, ["Sending"]);
+
+    // Every packet lost is sent again, for the reason it was declared lost
+    let lost = names(&sender, "recovery:packet_lost");
+    assert!(!lost.is_empty());
+    let resent: Vec<_> = names(&sender, "transport:packet_sent")
+        .into_iter()
+        .filter(|event| event["data"]["trigger"].is_string())
+        .collect();
+    assert_eq!(lost.len(), resent.len());
+    for (lost, resent) in lost.iter().zip(resent) {
+        assert_eq!(lost["data"]["trigger"], resent["data"]["trigger"]);
+        assert_eq!(lost["data"]["header"], resent["data"]["header"]);
+    }
+
+    // The receiver's ACKs show up on both sides
+    let acks_sent = names(&receiver, "transport:packet_sent").len();
+    assert!(acks_sent > 0);
+    assert!(names(&sender, "transport:packet_received").len() <= acks_sent);
+
+    // What the plot tool draws
+    assert!(!trace.cwnd.is_empty());
+    assert!(!trace.latest_rtt.is_empty());
+    assert!(plot::render(&[trace]).contains("<polyline"));
+}
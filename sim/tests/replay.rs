@@ -0,0 +1,65 @@
+use rand::prelude::*;
+use rand::rngs::StdRng;
+use std::time::{Duration, Instant};
+
+use impair::Config;
+use sim::Simulation;
+
+fn config(name: &str) -> Config {
+    let path = format!(
+        "{}/../transport-starter-code-main/configs/{}.conf",
+        env!("CARGO_MANIFEST_DIR"),
+        name
+    );
+    Config::from_file(&path).unwrap()
+}
+
+fn data(len: usize, seed: u64) -> Vec<u8> {
+    let mut data = vec![0; len];
+    StdRng::seed_from_u64(seed).fill_bytes(&mut data);
+    data
+}
+
+#[test]
+fn advanced_config_is_delivered_within_its_lifetime() {
+    let config = config("8-3-advanced");
+    let data = data(config.data.unwrap(), 1);
+    let lifetime = Duration::from_secs_f64(config.lifetime.unwrap());
+
+    let start = Instant::now();
+    let outcome = Simulation::new(config.network, 1)
+        .unwrap()
+        .run(&data, lifetime)
+        .unwrap();
+
+    assert!(outcome.finished, "not finished after {:?}", outcome.elapsed);
+    assert!(
+        outcome.received == data,
+        "data was not transmitted correctly"
+    );
+    // Replaying doesn't wait for the simulated time to pass
+    assert!(start.elapsed() < outcome.elapsed);
+}
+
+#[test]
+fn same_seed_replays_the_same_run() {
+    let config = config("8-3-advanced");
+    let data = data(config.data.unwrap(), 2);
+    let lifetime = Duration::from_secs_f64(config.lifetime.unwrap());
+
+    let first = Simulation::new(config.network.clone(), 7)
+        .unwrap()
+        .run(&data, lifetime)
+        .unwrap();
+    let second = Simulation::new(config.network.clone(), 7)
+        .unwrap()
+        .run(&data, lifetime)
+        .unwrap();
+    assert_eq!(first, second);
+
+    let other = Simulation::new(config.network, 8)
+        .unwrap()
+        .run(&data, lifetime)
+        .unwrap();
+    assert_ne!(first.s_to_r, other.s_to_r);
+}
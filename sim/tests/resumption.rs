@@ -0,0 +1,78 @@
+use std::time::Duration;
+
+use impair::Network;
+use sim::Simulation;
+
+const KEY: &[u8] = b"correct horse battery staple";
+const PSK: &[u8] = b"a pre-shared key of some length";
+
+// The high-latency profile, without its losses so round trips can be counted
+fn network(drop: Option<f64>) -> Network {
+    Network {
+        delay: 0.4,
+        bandwidth: 30_000.0,
+        buffer: 10_000,
+        drop,
+        duplicate: None,
+        mangle: None,
+        jitter: None,
+    }
+}
+
+// Run the transfer, the receiver handing out tokens under the given key and the sender bringing one back if any.
+// Returns the time it took and the token handed out.
+fn transfer(
+    network: Network,
+    seed: u64,
+    psk: Option<&[u8]>,
+    key: &[u8],
+    token: Option<&[u8]>,
+    data: &[u8],
+) -> (Duration, Vec<u8>) {
+    let mut simulation = Simulation::new(network, seed).unwrap();
+    if let Some(psk) = psk {
+        simulation.set_psk(psk).unwrap();
+    }
+    simulation.set_resumption_key(key).unwrap();
+    if let Some(token) = token {
+        simulation.set_resumption_token(token).unwrap();
+    }
+    let outcome = simulation.run(data, Duration::from_secs(60)).unwrap();
+    assert!(outcome.finished);
+    assert_eq!(outcome.received, data);
+    (outcome.elapsed, simulation.resumption_token().unwrap())
+}
+
+#[test]
+fn a_token_saves_the_round_trip_of_the_handshake() {
+    let data = vec![7; 1000];
+    for psk in [None, Some(PSK)] {
+        let (first, token) = transfer(network(None), 1, psk, KEY, None, &data);
+        let (resumed, _) = transfer(network(None), 2, psk, KEY, Some(&token), &data);
+        assert!(first >= Duration::from_millis(1600), "{first:?}");
+        assert!(resumed < Duration::from_millis(900), "{resumed:?}");
+    }
+}
+
+#[test]
+fn a_token_that_is_not_taken_falls_back_to_the_handshake() {
+    let data = vec![7; 20_000];
+    for psk in [None, Some(PSK)] {
+        let (_, token) = transfer(network(None), 1, psk, b"the key of another receiver", None, &data);
+        let (elapsed, _) = transfer(network(None), 2, psk, KEY, Some(&token), &data);
+        let (first, _) = transfer(network(None), 2, psk, KEY, None, &data);
+        // The data sent along with the SYN crossed the link for nothing, at worst the round trip it tried to save
+        assert!(elapsed < first + Duration::from_millis(800), "{elapsed:?} {first:?}");
+    }
+}
+
+#[test]
+fn lossy_resumed_transfers_finish() {
+    let data = vec![7; 32_000];
+    for psk in [None, Some(PSK)] {
+        let (_, token) = transfer(network(None), 1, psk, KEY, None, &data);
+        for seed in 0..20 {
+            transfer(network(Some(0.3)), seed, psk, KEY, Some(&token), &data);
+        }
+    }
+}
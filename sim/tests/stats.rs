@@ -0,0 +1,84 @@
+use rand::prelude::*;
+use rand::rngs::StdRng;
+use std::time::Duration;
+
+use impair::Config;
+use sim::{Outcome, Simulation};
+
+fn run(name: &str, seed: u64) -> (Vec<u8>, Outcome) {
+    let path = format!(
+        "{}/../transport-starter-code-main/configs/{name}.conf",
+        env!("CARGO_MANIFEST_DIR")
+    );
+    let config = Config::from_file(&path).unwrap();
+    let mut data = vec![0; config.data.unwrap()];
+    StdRng::seed_from_u64(seed).fill_bytes(&mut data);
+
+    let outcome = Simulation::new(config.network, seed)
+        .unwrap()
+        .run(&data, Duration::from_secs_f64(config.lifetime.unwrap()))
+        .unwrap();
+    assert!(outcome.finished, "{name} did not finish");
+    (data, outcome)
+}
+
+// What the ends count matches what the link saw
+fn check_totals(data: &[u8], outcome: &Outcome) {
+    let (sender, receiver) = (&outcome.sender, &outcome.receiver);
+    assert_eq!(sender.packets_sent, outcome.s_to_r.packets);
+    assert_eq!(sender.bytes_sent, outcome.s_to_r.bytes);
+    assert_eq!(receiver.packets_sent, outcome.r_to_s.packets);
+    assert_eq!(receiver.bytes_sent, outcome.r_to_s.bytes);
+    assert!(receiver.packets_received <= outcome.s_to_r.packets + outcome.s_to_r.duplicated);
+    assert!(sender.packets_received <= outcome.r_to_s.packets + outcome.r_to_s.duplicated);
+
+    assert_eq!(sender.payload_bytes, data.len() as u64);
+    assert_eq!(receiver.payload_bytes, data.len() as u64);
+    assert!(sender.elapsed > Duration::ZERO && sender.elapsed <= outcome.elapsed);
+    assert!(sender.goodput() > 0.0);
+
+    assert!(sender.rtt_samples > 0);
+    assert!(sender.rtt_min <= sender.rtt_avg() && sender.rtt_avg() <= sender.rtt_max);
+    assert!(sender.peak_cwnd > 0);
+}
+
+#[test]
+fn drops_are_retransmitted() {
+    let (data, outcome) = run("4-1-drops", 3);
+    check_totals(&data, &outcome);
+    let sender = &outcome.sender;
+    assert!(sender.timeout_retransmits + sender.fast_retransmits > 0);
+    assert_eq!(sender.corrupted + outcome.receiver.corrupted, 0);
+}
+
+#[test]
+fn duplicates_are_counted() {
+    let (data, outcome) = run("2-1-duplicates", 1);
+    check_totals(&data, &outcome);
+    assert!(outcome.receiver.duplicates > 0);
+}
+
+#[test]
+fn mangled_packets_are_discarded() {
+    let (data, outcome) = run("5-1-mangle", 1);
+    check_totals(&data, &outcome);
+    let corrupted = outcome.sender.corrupted + outcome.receiver.corrupted;
+    // Data that arrives before the handshake is over fails the check too, so there's no upper bound
+    assert!(corrupted > 0);
+}
+
+#[test]
+fn reports_have_every_counter() {
+    let (_, outcome) = run("4-1-drops", 3);
+    let json = outcome.sender.to_json();
+    assert_eq!(json["packets_sent"], outcome.sender.packets_sent);
+    assert_eq!(json["retransmits"]["timeout"], outcome.sender.timeout_retransmits);
+    assert!(json["rtt_ms"]["min"].is_f64());
+    // The receiver takes no RTT samples
+    assert!(outcome.receiver.to_json()["rtt_ms"]["avg"].is_null());
+
+    let text = outcome.sender.to_string();
+    assert!(text.starts_with("Statistics after"));
+    assert!(text.contains("peak cwnd"));
+    assert!(!outcome.receiver.to_string().contains("RTT"));
+}
@@ -0,0 +1,61 @@
+use std::time::Duration;
+
+use impair::Network;
+use sim::Simulation;
+
+fn network(drop: Option<f64>) -> Network {
+    Network {
+        delay: 0.01,
+        bandwidth: 1_000_000.0,
+        buffer: 64_000,
+        drop,
+        duplicate: None,
+        mangle: None,
+        jitter: None,
+    }
+}
+
+#[test]
+fn a_sender_gives_up_after_too_many_retransmissions() {
+    let mut simulation = Simulation::new(network(Some(1.0)), 1).unwrap();
+    simulation.set_max_retransmits(5);
+    let err = simulation.run(&[7; 10_000], Duration::from_secs(3600)).unwrap_err();
+    assert!(err.contains("timed out"), "{err}");
+}
+
+#[test]
+fn a_sender_gives_up_on_a_quiet_receiver() {
+    let mut simulation = Simulation::new(network(Some(1.0)), 1).unwrap();
+    simulation.set_idle_timeout(Duration::from_secs(10));
+    let err = simulation.run(&[7; 10_000], Duration::from_secs(3600)).unwrap_err();
+    assert!(err.contains("timed out"), "{err}");
+}
+
+#[test]
+fn a_lossy_transfer_finishes_within_the_limits() {
+    let data = vec![7; 200_000];
+    let mut simulation = Simulation::new(network(Some(0.1)), 1).unwrap();
+    simulation.set_idle_timeout(Duration::from_secs(10));
+    simulation.set_max_retransmits(10);
+    let outcome = simulation.run(&data, Duration::from_secs(120)).unwrap();
+    assert!(outcome.finished);
+    assert_eq!(outcome.received, data);
+}
+
+#[test]
+fn a_queue_that_holds_longer_than_the_rto_is_not_resent_over_and_over() {
+    // Segments take 0.4 s to go through and the buffer holds eight of them
+    let network = Network {
+        delay: 0.1,
+        bandwidth: 3000.0,
+        buffer: 10_000,
+        ..network(None)
+    };
+    let data = vec![7; 32_000];
+    let outcome = Simulation::new(network, 1).unwrap().run(&data, Duration::from_secs(120)).unwrap();
+    assert!(outcome.finished);
+    assert_eq!(outcome.received, data);
+    // With the RTO stuck below the time the queue takes, the data went close to five times and took 30 s
+    assert!(outcome.elapsed < Duration::from_secs(25), "{:?}", outcome.elapsed);
+    assert!(outcome.s_to_r.bytes < 4 * data.len() as u64, "{}", outcome.s_to_r.bytes);
+}
@@ -0,0 +1,44 @@
+use std::time::Duration;
+
+use receiver::Receiver;
+use sender::MAX_UNORDERED;
+use sim::Simulation;
+
+mod common;
+use common::clean;
+
+#[test]
+fn a_record_larger_than_the_base_size_is_refused() {
+    let mut sim = Simulation::new(clean(), 1).unwrap();
+    let err = sim.sender().send_unordered(0, &[7; MAX_UNORDERED + 1]).unwrap_err();
+    assert!(err.contains("doesn't fit"), "{err}");
+    sim.sender().send_unordered(0, &[7; MAX_UNORDERED]).unwrap();
+
+    let mut records = Vec::new();
+    let outcome = sim
+        .run_with(Duration::from_secs(10), |receiver| records.extend(receiver.recv_message(0)))
+        .unwrap();
+    assert!(outcome.finished);
+    assert_eq!(records, vec![vec![7; MAX_UNORDERED]]);
+}
+
+#[test]
+fn a_record_larger_than_the_receiver_takes_is_dropped_at_the_handshake() {
+    let mut sim = Simulation::new(clean(), 1).unwrap();
+    sim.receiver().set_max_datagram_size(600).unwrap();
+    // Fits the base size, the sender can't know better before the SYN-ACK
+    sim.sender().send_unordered(0, &[7; 1000]).unwrap();
+    sim.sender().send_unordered(0, &[8; 500]).unwrap();
+
+    let mut records = Vec::new();
+    let mut read = |receiver: &mut Receiver| records.extend(receiver.recv_message(0));
+    let err = sim.run_with(Duration::from_secs(10), &mut read).unwrap_err();
+    assert!(err.contains("doesn't fit in a segment of 538 bytes"), "{err}");
+    // The connection goes on without it, the record that fits still arrives
+    let outcome = sim.run_with(Duration::from_secs(10), &mut read).unwrap();
+    assert!(outcome.finished);
+    assert_eq!(records, vec![vec![8; 500]]);
+    // Once it knows, a record that large is refused right away
+    assert!(sim.sender().send_unordered(0, &[7; 539]).is_err());
+    sim.sender().send_unordered(0, &[7; 538]).unwrap();
+}
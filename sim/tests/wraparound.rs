@@ -0,0 +1,66 @@
+use proptest::prelude::*;
+use std::time::Duration;
+
+use impair::Network;
+use sim::Simulation;
+
+// Simulated time a transfer gets, far more than any of them needs
+const LIFETIME: Duration = Duration::from_secs(120);
+
+fn network() -> impl Strategy<Value = Network> {
+    (
+        0.01..0.2f64,
+        20_000.0..1_000_000.0f64,
+        0.0..0.1f64,
+        0.0..0.1f64,
+        0.0..0.05f64,
+        0.0..0.05f64,
+    )
+        .prop_map(
+            |(delay, bandwidth, drop, duplicate, mangle, jitter)| Network {
+                delay,
+                bandwidth,
+                buffer: 64_000,
+                drop: Some(drop),
+                duplicate: Some(duplicate),
+                mangle: Some(mangle),
+                jitter: Some(jitter),
+            },
+        )
+}
+
+// Data of the given size, and how far below the wrap the sender starts so it crosses it during the transfer
+fn transfer() -> impl Strategy<Value = (Vec<u8>, u32)> {
+    (1..40_000usize).prop_flat_map(|len| (prop::collection::vec(any::<u8>(), len), 0..=len as u32))
+}
+
+fn run(network: Network, seed: u64, data: &[u8], sender_seq: u32, receiver_seq: u32) {
+    let mut sim = Simulation::new(network, seed).unwrap();
+    sim.set_initial_seqs(sender_seq, receiver_seq).unwrap();
+    let outcome = sim.run(data, LIFETIME).unwrap();
+    assert!(outcome.finished, "not finished after {:?}", outcome.elapsed);
+    assert!(
+        outcome.received == data,
+        "data was not transmitted correctly"
+    );
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(32))]
+
+    #[test]
+    fn transfer_across_the_wrap(
+        network in network(),
+        seed in any::<u64>(),
+        (data, below) in transfer(),
+        receiver_seq in u32::MAX - 16..=u32::MAX,
+    ) {
+        run(network, seed, &data, u32::MAX - below, receiver_seq);
+    }
+
+    #[test]
+    fn transfer_starting_at_the_last_number(network in network(), seed in any::<u64>(), len in 0..10_000usize) {
+        let data: Vec<u8> = (0..len).map(|i| i as u8).collect();
+        run(network, seed, &data, u32::MAX, u32::MAX);
+    }
+}
@@ -0,0 +1,253 @@
+-- Wireshark dissector for the header the sender and the receiver put in front of every datagram.
+--
+--   wireshark -X lua_script:wireshark/transport.lua capture.pcapng
+--
+-- or copy the file to the personal Lua plugins folder. Datagrams are recognized by their
+-- header length, "Decode As..." on the UDP port forces the dissector when that is not enough.
+
+local HEADER_SIZE = 62
+-- Address validation cookie at the start of a SYN-ACK's payload
+local COOKIE_SIZE = 16
+-- Resumption token a SYN brings back, or a SYN-ACK hands out
+local TOKEN_SIZE = 24
+-- First Noise message, a retried SYN echoes the cookie right after it
+local KEY_SIZE = 32
+
+local proto = Proto("transport", "Reliable Transport Protocol")
+
+-- Flags, PATH_CHALLENGE and PATH_RESPONSE sit above the usual TCP bits
+local FIN = 0x01
+local SYN = 0x02
+local RST = 0x04
+local PSH = 0x08
+local ACK = 0x10
+local URG = 0x20
+local PATH_CHALLENGE = 0x40
+local PATH_RESPONSE = 0x80
+
+-- Frame flags, in the low nibble of the header length byte. Their meaning depends on the packet,
+-- in a data segment they frame its message
+local MSG_START = 0x1
+local MSG_END = 0x2
+local FORWARD = 0x4
+local UNORDERED = 0x8
+-- In a SYN or SYN-ACK they say what the payload carries
+local TOKEN = 0x1
+local RESUMED = 0x2
+local MSS = 0x4
+local RETRY = 0x8
+-- In a pure ACK from the sender it marks a keepalive probe
+local KEEPALIVE = 0x1
+
+local f = proto.fields
+f.srcport = ProtoField.uint16("transport.srcport", "Source Port", base.DEC)
+f.dstport = ProtoField.uint16("transport.dstport", "Destination Port", base.DEC)
+f.seq = ProtoField.uint32("transport.seq", "Sequence Number", base.DEC)
+f.ack = ProtoField.uint32("transport.ack", "Acknowledgment Number", base.DEC)
+f.hdr_len = ProtoField.uint8("transport.hdr_len", "Header Length", base.DEC, nil, 0xF0)
+f.frame_flags = ProtoField.uint8("transport.frame_flags", "Frame Flags", base.HEX, nil, 0x0F)
+f.msg_start = ProtoField.bool("transport.frame_flags.msg_start", "Message Start", 8, nil, MSG_START)
+f.msg_end = ProtoField.bool("transport.frame_flags.msg_end", "Message End", 8, nil, MSG_END)
+f.forward = ProtoField.bool("transport.frame_flags.forward", "Forward (skip marker)", 8, nil, FORWARD)
+f.unordered = ProtoField.bool("transport.frame_flags.unordered", "Unordered", 8, nil, UNORDERED)
+f.token_flag = ProtoField.bool("transport.frame_flags.token", "Resumption Token", 8, nil, TOKEN)
+f.resumed = ProtoField.bool("transport.frame_flags.resumed", "Resumed", 8, nil, RESUMED)
+f.mss_flag = ProtoField.bool("transport.frame_flags.mss", "Maximum Datagram Size", 8, nil, MSS)
+f.retry = ProtoField.bool("transport.frame_flags.retry", "Retry", 8, nil, RETRY)
+f.keepalive = ProtoField.bool("transport.frame_flags.keepalive", "Keepalive", 8, nil, KEEPALIVE)
+f.flags = ProtoField.uint8("transport.flags", "Flags", base.HEX)
+f.path_response = ProtoField.bool("transport.flags.path_response", "Path Response", 8, nil, PATH_RESPONSE)
+f.path_challenge = ProtoField.bool("transport.flags.path_challenge", "Path Challenge", 8, nil, PATH_CHALLENGE)
+f.urg = ProtoField.bool("transport.flags.urg", "Urgent", 8, nil, URG)
+f.ack_flag = ProtoField.bool("transport.flags.ack", "Acknowledgment", 8, nil, ACK)
+f.psh = ProtoField.bool("transport.flags.push", "Push", 8, nil, PSH)
+f.rst = ProtoField.bool("transport.flags.reset", "Reset", 8, nil, RST)
+f.syn = ProtoField.bool("transport.flags.syn", "Syn", 8, nil, SYN)
+f.fin = ProtoField.bool("transport.flags.fin", "Fin", 8, nil, FIN)
+f.window = ProtoField.uint16("transport.window", "Window", base.DEC)
+f.connection_id = ProtoField.uint64("transport.connection_id", "Connection ID", base.HEX)
+f.stream_id = ProtoField.uint16("transport.stream_id", "Stream ID", base.DEC)
+f.stream_seq = ProtoField.uint32("transport.stream_seq", "Stream Sequence Number", base.DEC)
+f.hash = ProtoField.bytes("transport.hash", "SHA-256")
+f.len = ProtoField.uint32("transport.len", "Segment Length", base.DEC)
+f.sack = ProtoField.none("transport.sack", "SACK Block")
+f.sack_left = ProtoField.uint32("transport.sack.left", "Left Edge", base.DEC)
+f.sack_right = ProtoField.uint32("transport.sack.right", "Right Edge", base.DEC)
+f.token = ProtoField.bytes("transport.token", "Path Token")
+f.cookie = ProtoField.bytes("transport.cookie", "Cookie")
+f.resumption_token = ProtoField.bytes("transport.resumption_token", "Resumption Token")
+f.key_material = ProtoField.bytes("transport.key_material", "Key Material")
+f.padding = ProtoField.bytes("transport.padding", "Padding")
+f.mss = ProtoField.uint16("transport.mss", "Maximum Datagram Size", base.DEC)
+f.slot = ProtoField.uint32("transport.slot", "Skipped Slot Length", base.DEC)
+f.data = ProtoField.bytes("transport.data", "Data")
+
+local ALL_FLAGS = {
+    { PATH_RESPONSE, "PATH_RESPONSE" }, { PATH_CHALLENGE, "PATH_CHALLENGE" },
+    { URG, "URG" }, { SYN, "SYN" }, { FIN, "FIN" }, { RST, "RST" }, { PSH, "PSH" }, { ACK, "ACK" },
+}
+
+-- Names of the flags that are set, like "SYN, ACK"
+local function flag_names(flags, all)
+    local names = {}
+    for _, flag in ipairs(all or ALL_FLAGS) do
+        if bit.band(flags, flag[1]) ~= 0 then
+            table.insert(names, flag[2])
+        end
+    end
+    return table.concat(names, ", ")
+end
+
+-- Frame flags of a packet, by its flags, as the fields to show and their names
+local function frame_flags_of(flags)
+    if flags == SYN or flags == SYN + ACK then
+        return { f.retry, f.mss_flag, f.resumed, f.token_flag },
+            { { RETRY, "RETRY" }, { MSS, "MSS" }, { RESUMED, "RESUMED" }, { TOKEN, "TOKEN" } }
+    elseif bit.band(flags, PSH) ~= 0 then
+        return { f.unordered, f.forward, f.msg_end, f.msg_start },
+            { { UNORDERED, "UNORDERED" }, { FORWARD, "FORWARD" }, { MSG_END, "MSG_END" }, { MSG_START, "MSG_START" } }
+    elseif flags == ACK then
+        return { f.keepalive }, { { KEEPALIVE, "KEEPALIVE" } }
+    end
+    return {}, {}
+end
+
+function proto.dissector(tvb, pinfo, tree)
+    if tvb:len() < HEADER_SIZE then
+        return 0
+    end
+
+    pinfo.cols.protocol = "TRANSPORT"
+    local subtree = tree:add(proto, tvb(), "Reliable Transport Protocol")
+
+    subtree:add(f.srcport, tvb(0, 2))
+    subtree:add(f.dstport, tvb(2, 2))
+    subtree:add(f.seq, tvb(4, 4))
+    subtree:add(f.ack, tvb(8, 4))
+    subtree:add(f.hdr_len, tvb(12, 1))
+
+    local frame_flags = bit.band(tvb(12, 1):uint(), 0x0F)
+    local flags = tvb(13, 1):uint()
+    local frame_fields, frame_names = frame_flags_of(flags)
+    local frame_tree = subtree:add(f.frame_flags, tvb(12, 1))
+    if frame_flags ~= 0 then
+        frame_tree:append_text(" (" .. flag_names(frame_flags, frame_names) .. ")")
+    end
+    for _, field in ipairs(frame_fields) do
+        frame_tree:add(field, tvb(12, 1))
+    end
+
+    local flags_tree = subtree:add(f.flags, tvb(13, 1))
+    flags_tree:append_text(" (" .. flag_names(flags) .. ")")
+    for _, field in ipairs({ f.path_response, f.path_challenge, f.urg, f.ack_flag, f.psh, f.rst, f.syn, f.fin }) do
+        flags_tree:add(field, tvb(13, 1))
+    end
+
+    subtree:add(f.window, tvb(14, 2))
+    subtree:add(f.connection_id, tvb(16, 8))
+    subtree:add(f.stream_id, tvb(24, 2))
+    subtree:add(f.stream_seq, tvb(26, 4))
+    subtree:add(f.hash, tvb(30, 32))
+
+    local payload_len = tvb:len() - HEADER_SIZE
+    local payload = payload_len > 0 and tvb(HEADER_SIZE, payload_len) or nil
+
+    -- Sequence numbers taken by the segment: a skip marker stands for the slot it replaces,
+    -- an empty segment still takes one
+    local seg_len = math.max(payload_len, 1)
+    if bit.band(flags, PSH) ~= 0 and bit.band(frame_flags, FORWARD) ~= 0 and payload_len >= 4 then
+        seg_len = tvb(HEADER_SIZE, 4):uint()
+    end
+    -- Size of the resumption token in a SYN or SYN-ACK, and of what comes before the largest datagram it ends with
+    local token_len = bit.band(frame_flags, TOKEN) ~= 0 and TOKEN_SIZE or 0
+    local handshake = flags == SYN or flags == SYN + ACK
+    local mss_len = handshake and bit.band(frame_flags, MSS) ~= 0 and payload_len >= 2 and 2 or 0
+    local body_len = payload_len - mss_len
+
+    if flags == PATH_CHALLENGE or flags == PATH_RESPONSE then
+        if payload then
+            subtree:add(f.token, payload)
+        end
+    elseif flags == SYN and bit.band(frame_flags, RETRY) ~= 0 and body_len >= KEY_SIZE + COOKIE_SIZE then
+        -- Sent again with the receiver's cookie after the first Noise message, padded to the size of its answer
+        subtree:add(f.key_material, tvb(HEADER_SIZE, KEY_SIZE))
+        subtree:add(f.cookie, tvb(HEADER_SIZE + KEY_SIZE, COOKIE_SIZE))
+        if body_len > KEY_SIZE + COOKIE_SIZE then
+            subtree:add(f.padding, tvb(HEADER_SIZE + KEY_SIZE + COOKIE_SIZE, body_len - KEY_SIZE - COOKIE_SIZE))
+        end
+    elseif flags == SYN and body_len >= token_len then
+        -- The sender's key material if any, then the token of an earlier connection it brings back
+        local material_len = body_len - token_len
+        if material_len > 0 then
+            subtree:add(f.key_material, tvb(HEADER_SIZE, material_len))
+        end
+        if token_len > 0 then
+            subtree:add(f.resumption_token, tvb(HEADER_SIZE + material_len, token_len))
+        end
+    elseif flags == SYN + ACK and body_len >= COOKIE_SIZE + token_len then
+        -- The cookie the sender echoes to complete the handshake, a token for the next connection,
+        -- then the receiver's key material if any. A retry carries only the cookie.
+        subtree:add(f.cookie, tvb(HEADER_SIZE, COOKIE_SIZE))
+        if token_len > 0 then
+            subtree:add(f.resumption_token, tvb(HEADER_SIZE + COOKIE_SIZE, token_len))
+        end
+        if body_len > COOKIE_SIZE + token_len then
+            local offset = COOKIE_SIZE + token_len
+            subtree:add(f.key_material, tvb(HEADER_SIZE + offset, body_len - offset))
+        end
+    elseif flags == ACK then
+        -- The payload of an ACK is SACK blocks, [left, right) ranges above the cumulative ACK
+        for offset = HEADER_SIZE, tvb:len() - 8, 8 do
+            local block = subtree:add(f.sack, tvb(offset, 8))
+            block:append_text(string.format(": %u-%u", tvb(offset, 4):uint(), tvb(offset + 4, 4):uint()))
+            block:add(f.sack_left, tvb(offset, 4))
+            block:add(f.sack_right, tvb(offset + 4, 4))
+        end
+    elseif bit.band(flags, PSH) ~= 0 then
+        subtree:add(f.len, tvb(0, 0), seg_len):set_generated()
+        if bit.band(frame_flags, FORWARD) ~= 0 and payload_len >= 4 then
+            subtree:add(f.slot, tvb(HEADER_SIZE, 4))
+        elseif payload then
+            subtree:add(f.data, payload)
+        end
+    end
+
+    if mss_len > 0 then
+        subtree:add(f.mss, tvb(HEADER_SIZE + body_len, 2))
+    end
+
+    local info = string.format("%u → %u [%s] Seq=%u Ack=%u Win=%u",
+        tvb(0, 2):uint(), tvb(2, 2):uint(), flag_names(flags),
+        tvb(4, 4):uint(), tvb(8, 4):uint(), tvb(14, 2):uint())
+    if bit.band(flags, PSH) ~= 0 then
+        info = info .. string.format(" Stream=%u StreamSeq=%u Len=%u",
+            tvb(24, 2):uint(), tvb(26, 4):uint(), seg_len)
+    elseif mss_len > 0 then
+        info = info .. string.format(" MSS=%u", tvb(HEADER_SIZE + body_len, 2):uint())
+    elseif flags == ACK and bit.band(frame_flags, KEEPALIVE) ~= 0 then
+        -- The stream sequence number of a probe counts the probes
+        info = info .. string.format(" Keepalive=%u", tvb(26, 4):uint())
+    elseif flags == ACK and payload_len >= 8 then
+        info = info .. string.format(" SACK=%u", math.floor(payload_len / 8))
+    end
+    pinfo.cols.info = info
+
+    return tvb:len()
+end
+
+-- Every header has a length of 4, plus a known combination of flags
+local function heuristic(tvb, pinfo, tree)
+    if tvb:len() < HEADER_SIZE or bit.rshift(tvb(12, 1):uint(), 4) ~= 4 then
+        return false
+    end
+    local known = { [SYN] = true, [SYN + ACK] = true, [ACK] = true, [PSH + ACK] = true, [RST] = true,
+        [PATH_CHALLENGE] = true, [PATH_RESPONSE] = true }
+    if not known[tvb(13, 1):uint()] then
+        return false
+    end
+    proto.dissector(tvb, pinfo, tree)
+    return true
+end
+
+proto:register_heuristic("udp", heuristic)
+DissectorTable.get("udp.port"):add_for_decode_as(proto)